@@ -0,0 +1,1738 @@
+use std::iter::Peekable;
+use std::ops::RangeInclusive;
+use std::slice;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::diff::DEFAULT_MAX_DIFF_SIZE;
+use crate::git::{GitTarget, UntrackedFiles};
+use crate::layout::SideBySideAlignment;
+use crate::theme::ThemePreset;
+use crate::viewer::{PanelMode, PanelSide, DEFAULT_FONT_SIZE, DEFAULT_TAB_WIDTH};
+
+pub enum Mode {
+    FilePairs(Vec<(String, String)>),
+    /// A file listing one `old<TAB>new` file pair per line.
+    Manifest(String),
+    /// Two shell commands whose stdout is compared.
+    Commands {
+        old: String,
+        new: String,
+    },
+    Git(GitTarget),
+    Stdin {
+        file: String,
+        stdin_is_new: bool,
+    },
+    /// A unified diff read from a file, or from stdin for `-`.
+    Patch(String),
+    /// Two patches for the same change, compared with each other.
+    Interdiff {
+        old: String,
+        new: String,
+    },
+    LineRanges {
+        old: (String, RangeInclusive<usize>),
+        new: (String, RangeInclusive<usize>),
+    },
+}
+
+pub const STDIN_LABEL: &str = "<stdin>";
+
+/// Environment variable holding default flags, read before the command line.
+pub const OPTS_ENV: &str = "GPUI_DIFF_TOOL_OPTS";
+
+pub const DEFAULT_GENERATED_PATTERNS: &[&str] = &[
+    "**/*.lock",
+    "**/package-lock.json",
+    "**/pnpm-lock.yaml",
+    "**/go.sum",
+    "**/node_modules/**",
+    "**/vendor/**",
+    "**/*.min.js",
+    "**/*.min.css",
+    "**/*.map",
+];
+
+pub struct Args {
+    pub mode: Mode,
+    pub exclude: GlobSet,
+    pub untracked: UntrackedFiles,
+    pub generated: GlobSet,
+    pub line_numbers: bool,
+    pub ignore_blank_lines: bool,
+    pub hide_unchanged: bool,
+    pub ignore_case: bool,
+    pub semantic: bool,
+    pub context_lines: Option<usize>,
+    pub panel_side: PanelSide,
+    pub panel_mode: Option<PanelMode>,
+    pub sbs_alignment: SideBySideAlignment,
+    pub max_size: Option<u64>,
+    pub watch: bool,
+    pub json: bool,
+    pub summary: bool,
+    pub exit_on_empty: bool,
+    pub theme: ThemePreset,
+    pub insert_sign: Option<String>,
+    pub delete_sign: Option<String>,
+    pub label_old: Option<String>,
+    pub label_new: Option<String>,
+    pub indent_guides: bool,
+    pub tab_width: usize,
+    pub font: Option<String>,
+    pub font_size: f32,
+}
+
+pub enum Command {
+    Run(Box<Args>),
+    Help,
+    Version,
+}
+
+pub const HELP: &str = "\
+gpui-diff-tool - a native diff viewer
+
+USAGE:
+    gpui-diff-tool [OPTIONS] <old-file> <new-file> [<old-file2> <new-file2> ...]
+    gpui-diff-tool [OPTIONS] <old-file>:<new-file> [<old-file2>:<new-file2> ...]
+    gpui-diff-tool [OPTIONS] --manifest <file>
+    gpui-diff-tool [OPTIONS] --cmd <command> --cmd <command>
+    gpui-diff-tool [OPTIONS] --git [--staged | --split] [--merge-base <branch> | --base <ref>]
+    gpui-diff-tool [OPTIONS] --git (--stash [N] | --range <A>..<B>)
+    gpui-diff-tool [OPTIONS] --git --branch-compare <ref:path> <ref:path> ...
+    gpui-diff-tool [OPTIONS] --git --log <file>
+    gpui-diff-tool [OPTIONS] --git --blob <hash> <hash>
+    <command> | gpui-diff-tool [OPTIONS] (--stdin-new | --stdin-old) <file>
+    gpui-diff-tool [OPTIONS] --patch <file | ->
+    gpui-diff-tool [OPTIONS] --interdiff <old-patch> <new-patch>
+    gpui-diff-tool [OPTIONS] --range-old <file:N-M> --range-new <file:N-M>
+
+MODES:
+    <old-file> <new-file> ...   Diff one or more file pairs
+    <old-file>:<new-file> ...   Same, one pair per argument; an argument with a
+                                single `:` (not counting a drive letter's, as in
+                                C:\\) is always a pair, and the others pair up
+                                two at a time
+    --manifest <file>           Diff the file pairs listed in <file>, one
+                                `old<TAB>new` pair per line; blank lines and
+                                lines starting with # are skipped
+    --cmd <command>             Given twice, run both shell commands and diff
+                                their output, e.g. --cmd 'ls a' --cmd 'ls b'
+    --git                       Show unstaged changes in the current git repository
+    --stdin-new <file>          Diff <file> (old) against stdin (new)
+    --stdin-old <file>          Diff stdin (old) against <file> (new)
+    --patch <file>              Show a unified diff such as `git diff` output;
+                                use - to read it from stdin
+    --interdiff <old> <new>     Show how two patches for the same change differ,
+                                e.g. two revisions of a pull request
+    --range-old <file:N-M>      With --range-new, diff only lines N-M of each file,
+    --range-new <file:N-M>      e.g. --range-old a.rs:10-40 --range-new b.rs:12-45
+
+GIT OPTIONS:
+    --staged                    Show staged changes (HEAD vs index) instead
+    --split                     Show staged and unstaged changes together, grouped
+                                into sections in the file panel
+    --stash [N]                 Show stash@{N} against its parent commit (default N = 0)
+    --range <A>..<B>            Show the cumulative diff between commits A and B
+                                (branches, tags or SHAs)
+    --base <ref>                Diff the working tree (or the index with --staged)
+                                against <ref>, e.g. a tag like v1.0.0
+    --merge-base <branch>       Diff the working tree (or the index with --staged)
+                                against the merge base of HEAD and <branch>
+    --branch-compare            Diff pairs of <ref>:<path> arguments, e.g.
+                                feature:src/x.rs main:src/x.rs (paths are
+                                relative to the repository root)
+    --log <file>                Step through <file>'s history: one entry per commit
+                                that touched it, diffed against the previous one
+    --blob <A> <B>              Diff two blob objects by hash, e.g. ones found with
+                                `git fsck` or in the reflog
+    --no-untracked              Leave untracked files out of working tree diffs
+    --untracked-all             Include untracked files that .gitignore ignores,
+                                such as build output
+    --exit-on-empty             Exit with an error when there are no changes,
+                                instead of opening an empty window
+
+DIFF OPTIONS:
+    --max-size <SIZE>           Skip line-diffing files larger than SIZE (e.g. 500KB,
+                                2MB; default 5MB, 0 = no limit)
+    --watch                     Watch the compared files (or the git work tree) and
+                                reload the diff when they change
+    --ignore-case               Treat lines that differ only in letter case as
+                                unchanged
+    --semantic                  Diff .json, .yaml and .yml files with keys sorted
+                                and formatting normalized; files that fail to
+                                parse are diffed line by line
+
+FILTER OPTIONS:
+    --exclude <glob>            Hide files whose path matches <glob> (repeatable),
+                                e.g. --exclude '*.lock' --exclude 'dist/*'
+    --generated <glob>          Treat matching files as generated/vendored (repeatable);
+                                these are hidden in the panel until revealed
+    --no-default-generated      Don't apply the built-in generated-file patterns
+                                (lockfiles, node_modules/, vendor/, *.min.js, ...)
+    --hide-unchanged            Hide files whose two sides are identical
+
+DISPLAY OPTIONS:
+    --no-line-numbers           Hide the line-number gutters
+    --indent-guides             Draw faint guides at each indentation level
+    --tab-width <N>             Columns per tab for indent guides (default 4)
+    --font <family>             Font family for the diff (default: the platform's
+                                monospace font, e.g. Menlo, Consolas, DejaVu Sans Mono)
+    --font-size <px>            Font size in pixels (default 13)
+    --ignore-blank-lines        Hide added or removed lines that are blank
+    -U, --context <N>           Show only N lines of context around changes,
+                                split into @@ hunks (also --collapse-context)
+    --panel <left|right>        Which side of the window the file panel is on
+                                (default: right)
+    --tree, --list              Start the file panel as a folder tree or a flat list
+                                (default: the last one picked, or a tree for
+                                large changesets)
+    --align <similarity|grouped>
+                                How side-by-side rows are paired: by line similarity
+                                (default) or by whole-file grouped edits
+    --theme <default|colorblind|monochrome>
+                                Diff colors; colorblind uses blue/orange instead
+                                of green/red, monochrome uses only gray shading
+    --no-color                  Same as --theme monochrome; also enabled by the
+                                NO_COLOR environment variable
+    --add-sign <text>           Gutter sign for added lines (default '+')
+    --delete-sign <text>        Gutter sign for deleted lines (default '-')
+    --label-old <text>          Show <text> instead of the old file's path, e.g.
+    --label-new <text>          --label-old before --label-new after (not with --git)
+
+OUTPUT OPTIONS:
+    --json                      Print the diffs as JSON to stdout instead of
+                                opening a window
+    --summary                   Print a per-file change summary like
+                                `git diff --stat` instead of opening a window
+    --wait                      Don't return until the window is closed (always the
+                                case; accepted for editor and difftool configs).
+                                Exits 0 on a normal close and 1 on errors
+
+GENERAL OPTIONS:
+    -h, --help                  Print this help and exit
+    -V, --version               Print the version and exit
+
+ENVIRONMENT:
+    GPUI_DIFF_TOOL_OPTS         Default flags applied before the command line,
+                                e.g. \"--theme colorblind --font-size 15\";
+                                flags given on the command line win, and ones
+                                that don't parse are skipped with a warning.
+                                On/off flags have an opposite form to undo a
+                                default, e.g. --line-numbers, --no-indent-guides,
+                                --no-watch or --default-generated
+";
+
+pub fn version_string() -> String {
+    format!("gpui-diff-tool {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn parse_size(size: &str) -> Result<u64, String> {
+    let upper = size.trim().to_ascii_uppercase();
+    let (digits, multiplier) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)]
+        .iter()
+        .find_map(|(suffix, multiplier)| Some((upper.strip_suffix(suffix)?, *multiplier)))
+        .unwrap_or((upper.as_str(), 1));
+    let n = digits
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid --max-size '{size}', expected e.g. 500KB or 2MB"))?;
+    n.checked_mul(multiplier)
+        .ok_or_else(|| format!("--max-size '{size}' is too large"))
+}
+
+fn parse_line_range(spec: &str) -> Result<(String, RangeInclusive<usize>), String> {
+    let invalid = || format!("Invalid line range '{spec}', expected <file>:<start>-<end>");
+    let (path, lines) = spec.rsplit_once(':').ok_or_else(invalid)?;
+    let (start, end) = lines.split_once('-').ok_or_else(invalid)?;
+    let start = start.parse::<usize>().map_err(|_| invalid())?;
+    let end = end.parse::<usize>().map_err(|_| invalid())?;
+    if path.is_empty() || start == 0 || start > end {
+        return Err(invalid());
+    }
+    Ok((path.to_string(), start..=end))
+}
+
+/// Splits an `old:new` argument into its two paths. The separator is the
+/// only `:` that isn't a Windows drive letter's (`C:\` or `C:/` at the
+/// start of either path); arguments with no such `:`, or more than one,
+/// aren't pairs.
+fn split_pair_arg(arg: &str) -> Option<(&str, &str)> {
+    let bytes = arg.as_bytes();
+    let is_drive_colon = |i: usize| {
+        let starts_path = i == 1 || (i >= 2 && bytes[i - 2] == b':');
+        starts_path
+            && bytes[i - 1].is_ascii_alphabetic()
+            && matches!(bytes.get(i + 1), Some(b'\\' | b'/'))
+    };
+    let mut separators = arg
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .filter(|&i| !is_drive_colon(i));
+    let separator = separators.next()?;
+    if separators.next().is_some() {
+        return None;
+    }
+    match arg.split_at(separator) {
+        (old, new) if !old.is_empty() && new.len() > 1 => Some((old, &new[1..])),
+        _ => None,
+    }
+}
+
+/// Pairs up positional file arguments: each `old:new` argument is a pair
+/// on its own, and the other arguments pair up two at a time in order.
+fn file_pairs(positional: &[String]) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut pending: Option<&String> = None;
+    for arg in positional {
+        match (split_pair_arg(arg), pending.take()) {
+            (Some((old, new)), None) => pairs.push((old.to_string(), new.to_string())),
+            (Some(_), Some(old)) => {
+                return Err(format!(
+                    "Expected a new file after '{old}', got the pair '{arg}'"
+                ));
+            }
+            (None, Some(old)) => pairs.push((old.clone(), arg.clone())),
+            (None, None) => pending = Some(arg),
+        }
+    }
+    match pending {
+        Some(old) => Err(format!("Expected a new file after '{old}'")),
+        None => Ok(pairs),
+    }
+}
+
+fn parse_ref_path(spec: &str) -> Result<String, String> {
+    match spec.split_once(':') {
+        Some((rev, path)) if !rev.is_empty() && !path.is_empty() => Ok(spec.to_string()),
+        _ => Err(format!(
+            "Invalid ref:path '{spec}', expected e.g. main:src/lib.rs"
+        )),
+    }
+}
+
+/// Parses a manifest of file pairs: one `old<TAB>new` pair per line, with
+/// blank lines and `#` comments skipped.
+pub fn parse_manifest(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (ix, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        match line.split_once('\t') {
+            Some((old, new)) if !old.is_empty() && !new.is_empty() && !new.contains('\t') => {
+                pairs.push((old.to_string(), new.to_string()));
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid manifest line {}, expected <old-path><TAB><new-path>",
+                    ix + 1
+                ))
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn parse_range(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once("..") {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() && !new.starts_with('.') => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err(format!("Invalid --range '{spec}', expected <A>..<B>")),
+    }
+}
+
+/// Splits the value of [`OPTS_ENV`] into arguments like a shell would:
+/// whitespace separates them, quotes group them and a backslash escapes the
+/// next character. Malformed arguments are dropped with a warning.
+pub fn split_opts(text: &str) -> (Vec<String>, Vec<String>) {
+    let mut args = Vec::new();
+    let mut warnings = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut arg = String::new();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (None, c) if c.is_whitespace() => break,
+                (None, '\'' | '"') => quote = Some(c),
+                (Some(q), c) if c == q => quote = None,
+                (None | Some('"'), '\\') => arg.extend(chars.next()),
+                (_, c) => arg.push(c),
+            }
+        }
+        match quote {
+            Some(q) => warnings.push(format!("Ignoring unterminated {q}{arg} in {OPTS_ENV}")),
+            None => args.push(arg),
+        }
+    }
+    (args, warnings)
+}
+
+/// Flag values collected by [`parse_args`] before they are checked against each other.
+struct Flags {
+    git: bool,
+    staged: bool,
+    split: bool,
+    stash: Option<usize>,
+    range: Option<(String, String)>,
+    merge_base: Option<String>,
+    base: Option<String>,
+    branch_compare: bool,
+    log: Option<String>,
+    blob: Option<(String, String)>,
+    stdin_mode: Option<(String, bool)>,
+    patch: Option<String>,
+    manifest: Option<String>,
+    commands: Vec<String>,
+    interdiff: Option<(String, String)>,
+    range_old: Option<(String, RangeInclusive<usize>)>,
+    range_new: Option<(String, RangeInclusive<usize>)>,
+    exclude: GlobSetBuilder,
+    untracked: Vec<(&'static str, UntrackedFiles)>,
+    line_numbers: bool,
+    ignore_blank_lines: bool,
+    hide_unchanged: bool,
+    ignore_case: bool,
+    semantic: bool,
+    context_lines: Option<usize>,
+    panel_side: PanelSide,
+    panel_mode: Option<PanelMode>,
+    sbs_alignment: SideBySideAlignment,
+    max_size: Option<u64>,
+    watch: bool,
+    json: bool,
+    summary: bool,
+    wait: bool,
+    exit_on_empty: bool,
+    theme: ThemePreset,
+    insert_sign: Option<String>,
+    delete_sign: Option<String>,
+    label_old: Option<String>,
+    label_new: Option<String>,
+    indent_guides: bool,
+    tab_width: usize,
+    font: Option<String>,
+    font_size: f32,
+    generated: GlobSetBuilder,
+    default_generated: bool,
+    positional: Vec<String>,
+}
+
+impl Flags {
+    fn new() -> Self {
+        Self {
+            git: false,
+            staged: false,
+            split: false,
+            stash: None,
+            range: None,
+            merge_base: None,
+            base: None,
+            branch_compare: false,
+            log: None,
+            blob: None,
+            stdin_mode: None,
+            patch: None,
+            manifest: None,
+            commands: Vec::new(),
+            interdiff: None,
+            range_old: None,
+            range_new: None,
+            exclude: GlobSetBuilder::new(),
+            untracked: Vec::new(),
+            line_numbers: true,
+            ignore_blank_lines: false,
+            hide_unchanged: false,
+            ignore_case: false,
+            semantic: false,
+            context_lines: None,
+            panel_side: PanelSide::Right,
+            panel_mode: None,
+            sbs_alignment: SideBySideAlignment::Similarity,
+            max_size: Some(DEFAULT_MAX_DIFF_SIZE),
+            watch: false,
+            json: false,
+            summary: false,
+            wait: false,
+            exit_on_empty: false,
+            theme: ThemePreset::Default,
+            insert_sign: None,
+            delete_sign: None,
+            label_old: None,
+            label_new: None,
+            indent_guides: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            font: None,
+            font_size: DEFAULT_FONT_SIZE,
+            generated: GlobSetBuilder::new(),
+            default_generated: true,
+            positional: Vec::new(),
+        }
+    }
+
+    /// Applies `arg`, taking any values it needs from `iter`. Returns the command
+    /// to run right away for `--help` and `--version`.
+    fn apply(
+        &mut self,
+        arg: &String,
+        iter: &mut Peekable<slice::Iter<'_, String>>,
+    ) -> Result<Option<Command>, String> {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Some(Command::Help)),
+            "-V" | "--version" => return Ok(Some(Command::Version)),
+            "--git" => self.git = true,
+            "--staged" => self.staged = true,
+            "--split" => self.split = true,
+            "--stash" => {
+                let index = iter.peek().and_then(|next| next.parse::<usize>().ok());
+                if index.is_some() {
+                    iter.next();
+                }
+                self.stash = Some(index.unwrap_or(0));
+            }
+            "--range" => {
+                let spec = iter
+                    .next()
+                    .ok_or_else(|| "--range requires <A>..<B>".to_string())?;
+                self.range = Some(parse_range(spec)?);
+            }
+            "--base" => {
+                let rev = iter
+                    .next()
+                    .ok_or_else(|| "--base requires a ref".to_string())?;
+                self.base = Some(rev.clone());
+            }
+            "--merge-base" => {
+                let branch = iter
+                    .next()
+                    .ok_or_else(|| "--merge-base requires a branch".to_string())?;
+                self.merge_base = Some(branch.clone());
+            }
+            "--branch-compare" => self.branch_compare = true,
+            "--log" => {
+                let path = iter
+                    .next()
+                    .ok_or_else(|| "--log requires a file path".to_string())?;
+                self.log = Some(path.clone());
+            }
+            "--blob" => {
+                let (Some(old), Some(new)) = (iter.next(), iter.next()) else {
+                    return Err("--blob requires two object hashes".to_string());
+                };
+                self.blob = Some((old.clone(), new.clone()));
+            }
+            "--panel" => {
+                self.panel_side = match iter.next().map(String::as_str) {
+                    Some("left") => PanelSide::Left,
+                    Some("right") => PanelSide::Right,
+                    Some(other) => {
+                        return Err(format!("Invalid --panel '{other}', expected left or right"))
+                    }
+                    None => return Err("--panel requires left or right".to_string()),
+                };
+            }
+            "--tree" => self.panel_mode = Some(PanelMode::Tree),
+            "--list" => self.panel_mode = Some(PanelMode::List),
+            "--align" => {
+                self.sbs_alignment = match iter.next().map(String::as_str) {
+                    Some("similarity") => SideBySideAlignment::Similarity,
+                    Some("grouped") => SideBySideAlignment::Grouped,
+                    Some(other) => {
+                        return Err(format!(
+                            "Invalid --align '{other}', expected similarity or grouped"
+                        ))
+                    }
+                    None => return Err("--align requires similarity or grouped".to_string()),
+                };
+            }
+            "--max-size" => {
+                let size = iter
+                    .next()
+                    .ok_or_else(|| "--max-size requires a size".to_string())?;
+                self.max_size = Some(parse_size(size)?).filter(|&bytes| bytes > 0);
+            }
+            "--watch" => self.watch = true,
+            "--no-watch" => self.watch = false,
+            "--json" => self.json = true,
+            "--summary" => self.summary = true,
+            "--wait" => self.wait = true,
+            "--exit-on-empty" => self.exit_on_empty = true,
+            "--no-exit-on-empty" => self.exit_on_empty = false,
+            "--theme" => {
+                self.theme = match iter.next().map(String::as_str) {
+                    Some("default") => ThemePreset::Default,
+                    Some("colorblind") => ThemePreset::Colorblind,
+                    Some("monochrome") => ThemePreset::Monochrome,
+                    Some(other) => {
+                        return Err(format!(
+                            "Invalid --theme '{other}', expected default, colorblind or monochrome"
+                        ))
+                    }
+                    None => return Err("--theme requires a theme name".to_string()),
+                };
+            }
+            "--no-color" => self.theme = ThemePreset::Monochrome,
+            "--add-sign" | "--delete-sign" => {
+                let sign = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a sign"))?
+                    .clone();
+                if arg == "--add-sign" {
+                    self.insert_sign = Some(sign);
+                } else {
+                    self.delete_sign = Some(sign);
+                }
+            }
+            "--label-old" | "--label-new" => {
+                let label = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a label"))?
+                    .clone();
+                if arg == "--label-old" {
+                    self.label_old = Some(label);
+                } else {
+                    self.label_new = Some(label);
+                }
+            }
+            "--line-numbers" => self.line_numbers = true,
+            "--no-line-numbers" => self.line_numbers = false,
+            "--indent-guides" => self.indent_guides = true,
+            "--no-indent-guides" => self.indent_guides = false,
+            "--tab-width" => {
+                let n = iter
+                    .next()
+                    .ok_or_else(|| "--tab-width requires a number of columns".to_string())?;
+                self.tab_width = n
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid --tab-width value '{n}'"))?;
+            }
+            "--font" => {
+                let family = iter
+                    .next()
+                    .ok_or_else(|| "--font requires a font family".to_string())?;
+                self.font = Some(family.clone());
+            }
+            "--font-size" => {
+                let n = iter
+                    .next()
+                    .ok_or_else(|| "--font-size requires a size in pixels".to_string())?;
+                self.font_size = n
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|&n| n.is_finite() && n > 0.0)
+                    .ok_or_else(|| format!("Invalid --font-size value '{n}'"))?;
+            }
+            "--ignore-blank-lines" => self.ignore_blank_lines = true,
+            "--no-ignore-blank-lines" => self.ignore_blank_lines = false,
+            "--hide-unchanged" => self.hide_unchanged = true,
+            "--no-hide-unchanged" => self.hide_unchanged = false,
+            "--ignore-case" => self.ignore_case = true,
+            "--no-ignore-case" => self.ignore_case = false,
+            "--semantic" => self.semantic = true,
+            "--no-semantic" => self.semantic = false,
+            "-U" | "--context" | "--collapse-context" => {
+                let n = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a number of lines"))?;
+                let n = n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid {arg} value '{n}'"))?;
+                self.context_lines = Some(n);
+            }
+            "--range-old" | "--range-new" => {
+                let spec = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires <file>:<start>-<end>"))?;
+                let lines = Some(parse_line_range(spec)?);
+                if arg == "--range-old" {
+                    self.range_old = lines;
+                } else {
+                    self.range_new = lines;
+                }
+            }
+            "--stdin-new" | "--stdin-old" => {
+                let file = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a file argument"))?;
+                self.stdin_mode = Some((file.clone(), arg == "--stdin-new"));
+            }
+            "--patch" => {
+                let file = iter
+                    .next()
+                    .ok_or_else(|| "--patch requires a file argument".to_string())?;
+                self.patch = Some(file.clone());
+            }
+            "--manifest" => {
+                let file = iter
+                    .next()
+                    .ok_or_else(|| "--manifest requires a file argument".to_string())?;
+                self.manifest = Some(file.clone());
+            }
+            "--cmd" => {
+                let command = iter
+                    .next()
+                    .ok_or_else(|| "--cmd requires a command".to_string())?;
+                self.commands.push(command.clone());
+            }
+            "--interdiff" => {
+                let (Some(old), Some(new)) = (iter.next(), iter.next()) else {
+                    return Err("--interdiff requires two patch files".to_string());
+                };
+                self.interdiff = Some((old.clone(), new.clone()));
+            }
+            "--exclude" | "--generated" => {
+                let pattern = iter
+                    .next()
+                    .ok_or_else(|| format!("{arg} requires a glob pattern"))?;
+                let glob = Glob::new(pattern)
+                    .map_err(|e| format!("Invalid {arg} pattern '{pattern}': {e}"))?;
+                if arg == "--exclude" {
+                    self.exclude.add(glob);
+                } else {
+                    self.generated.add(glob);
+                }
+            }
+            "--default-generated" => self.default_generated = true,
+            "--no-default-generated" => self.default_generated = false,
+            "--no-untracked" => self
+                .untracked
+                .push(("--no-untracked", UntrackedFiles::Skip)),
+            "--untracked-all" => self
+                .untracked
+                .push(("--untracked-all", UntrackedFiles::All)),
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown flag '{flag}'"));
+            }
+            _ => self.positional.push(arg.clone()),
+        }
+        Ok(None)
+    }
+
+    fn finish(self) -> Result<Command, String> {
+        let Flags {
+            git,
+            staged,
+            split,
+            stash,
+            range,
+            merge_base,
+            base,
+            branch_compare,
+            log,
+            blob,
+            stdin_mode,
+            patch,
+            manifest,
+            commands,
+            interdiff,
+            range_old,
+            range_new,
+            exclude,
+            untracked,
+            line_numbers,
+            ignore_blank_lines,
+            hide_unchanged,
+            ignore_case,
+            semantic,
+            context_lines,
+            panel_side,
+            panel_mode,
+            sbs_alignment,
+            max_size,
+            watch,
+            json,
+            summary,
+            wait,
+            exit_on_empty,
+            theme,
+            insert_sign,
+            delete_sign,
+            label_old,
+            label_new,
+            indent_guides,
+            tab_width,
+            font,
+            font_size,
+            mut generated,
+            default_generated,
+            positional,
+        } = self;
+
+        let exclude = exclude
+            .build()
+            .map_err(|e| format!("Invalid --exclude patterns: {e}"))?;
+        if default_generated {
+            for pattern in DEFAULT_GENERATED_PATTERNS {
+                generated.add(Glob::new(pattern).expect("built-in pattern is valid"));
+            }
+        }
+        let generated = generated
+            .build()
+            .map_err(|e| format!("Invalid --generated patterns: {e}"))?;
+
+        let git_flags: Vec<&str> = [
+            (staged && merge_base.is_none() && base.is_none()).then_some("--staged"),
+            split.then_some("--split"),
+            stash.map(|_| "--stash"),
+            range.as_ref().map(|_| "--range"),
+            merge_base.as_ref().map(|_| "--merge-base"),
+            base.as_ref().map(|_| "--base"),
+            branch_compare.then_some("--branch-compare"),
+            log.as_ref().map(|_| "--log"),
+            blob.as_ref().map(|_| "--blob"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if let [first, second, ..] = git_flags[..] {
+            return Err(format!("{second} cannot be combined with {first}"));
+        }
+
+        if summary && json {
+            return Err("--summary cannot be combined with --json".to_string());
+        }
+        if wait && json {
+            return Err("--wait cannot be combined with --json".to_string());
+        }
+        if wait && summary {
+            return Err("--wait cannot be combined with --summary".to_string());
+        }
+
+        if watch {
+            if range_old.is_some() || range_new.is_some() {
+                return Err("--watch cannot be used with line ranges".to_string());
+            }
+            if json {
+                return Err("--watch cannot be combined with --json".to_string());
+            }
+            if summary {
+                return Err("--watch cannot be combined with --summary".to_string());
+            }
+            if stdin_mode.is_some() {
+                return Err("--watch cannot be used with stdin input".to_string());
+            }
+            if patch.is_some() {
+                return Err("--watch cannot be combined with --patch".to_string());
+            }
+            if interdiff.is_some() {
+                return Err("--watch cannot be combined with --interdiff".to_string());
+            }
+            if !commands.is_empty() {
+                return Err("--watch cannot be combined with --cmd".to_string());
+            }
+            let watchable = ["--staged", "--split", "--merge-base", "--base"];
+            if let Some(flag) = git_flags.iter().find(|flag| !watchable.contains(flag)) {
+                return Err(format!("--watch cannot be combined with {flag}"));
+            }
+        }
+
+        if patch.is_some() && (git || stdin_mode.is_some()) {
+            return Err("--patch cannot be combined with --git or stdin input".to_string());
+        }
+        if interdiff.is_some() && (git || stdin_mode.is_some() || patch.is_some()) {
+            return Err(
+                "--interdiff cannot be combined with --git, --patch or stdin input".to_string(),
+            );
+        }
+
+        let other_modes = git
+            || stdin_mode.is_some()
+            || patch.is_some()
+            || interdiff.is_some()
+            || range_old.is_some()
+            || range_new.is_some();
+        if manifest.is_some() && other_modes {
+            return Err("--manifest cannot be combined with another input mode".to_string());
+        }
+        if !commands.is_empty() && (other_modes || manifest.is_some()) {
+            return Err("--cmd cannot be combined with another input mode".to_string());
+        }
+
+        let mode = if git {
+            if label_old.is_some() || label_new.is_some() {
+                return Err("--label-old and --label-new cannot be used with --git".to_string());
+            }
+            if branch_compare {
+                if positional.is_empty() || positional.len() % 2 != 0 {
+                    return Err(
+                        "--branch-compare requires pairs of <ref>:<path> arguments".to_string()
+                    );
+                }
+                let specs = positional
+                    .iter()
+                    .map(|spec| parse_ref_path(spec))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let pairs = specs
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                Mode::Git(GitTarget::RefPaths(pairs))
+            } else if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' in --git mode"));
+            } else if let Some(path) = log {
+                Mode::Git(GitTarget::Log(path))
+            } else if let Some((old, new)) = blob {
+                Mode::Git(GitTarget::Blob { old, new })
+            } else if let Some(index) = stash {
+                Mode::Git(GitTarget::Stash(index))
+            } else if let Some((old, new)) = range {
+                Mode::Git(GitTarget::Range { old, new })
+            } else if let Some(branch) = merge_base {
+                Mode::Git(GitTarget::MergeBase { branch, staged })
+            } else if let Some(rev) = base {
+                Mode::Git(GitTarget::Base { rev, staged })
+            } else if split {
+                Mode::Git(GitTarget::Split)
+            } else {
+                Mode::Git(GitTarget::WorkTree { staged })
+            }
+        } else if let Some(flag) = git_flags.first() {
+            return Err(format!("{flag} requires --git"));
+        } else if let Some((file, stdin_is_new)) = stdin_mode {
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' in stdin mode"));
+            }
+            Mode::Stdin { file, stdin_is_new }
+        } else if let Some(file) = patch {
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' with --patch"));
+            }
+            Mode::Patch(file)
+        } else if let Some((old, new)) = interdiff {
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' with --interdiff"));
+            }
+            Mode::Interdiff { old, new }
+        } else if !commands.is_empty() {
+            let [old, new] = <[String; 2]>::try_from(commands)
+                .map_err(|_| "--cmd must be given exactly twice, old then new".to_string())?;
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' with --cmd"));
+            }
+            Mode::Commands { old, new }
+        } else if let Some(file) = manifest {
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' with --manifest"));
+            }
+            Mode::Manifest(file)
+        } else if range_old.is_some() || range_new.is_some() {
+            let (Some(old), Some(new)) = (range_old, range_new) else {
+                return Err("--range-old and --range-new must be given together".to_string());
+            };
+            if let Some(extra) = positional.first() {
+                return Err(format!("Unexpected argument '{extra}' with line ranges"));
+            }
+            Mode::LineRanges { old, new }
+        } else {
+            if positional.is_empty() {
+                return Err("No files to compare".to_string());
+            }
+            let pairs = file_pairs(&positional)?
+                .into_iter()
+                .filter(|(old, new)| !exclude.is_match(old) && !exclude.is_match(new))
+                .collect();
+            Mode::FilePairs(pairs)
+        };
+
+        if let Some(&(flag, setting)) = untracked.first() {
+            if let Some((other, _)) = untracked.iter().find(|(_, other)| *other != setting) {
+                return Err(format!("{other} cannot be combined with {flag}"));
+            }
+            match &mode {
+                Mode::Git(target) if target.lists_untracked() => {}
+                Mode::Git(_) => return Err(format!("{flag} only applies to working tree diffs")),
+                _ => return Err(format!("{flag} requires --git")),
+            }
+        }
+        let untracked = untracked
+            .first()
+            .map_or(UntrackedFiles::default(), |&(_, setting)| setting);
+
+        Ok(Command::Run(Box::new(Args {
+            mode,
+            exclude,
+            untracked,
+            generated,
+            line_numbers,
+            ignore_blank_lines,
+            hide_unchanged,
+            ignore_case,
+            semantic,
+            context_lines,
+            panel_side,
+            panel_mode,
+            sbs_alignment,
+            max_size,
+            watch,
+            json,
+            summary,
+            exit_on_empty,
+            theme,
+            insert_sign,
+            delete_sign,
+            label_old,
+            label_new,
+            indent_guides,
+            tab_width,
+            font,
+            font_size,
+        })))
+    }
+}
+
+/// Puts the flags in `defaults`, the arguments from [`OPTS_ENV`], in front of
+/// the command line `args`. Defaults that don't parse are dropped with a
+/// warning, and ones that conflict with the command line are dropped quietly
+/// so that the command line wins.
+pub fn with_defaults(defaults: &[String], args: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut merged = Vec::new();
+    let mut warnings = Vec::new();
+    let mut flags = Flags::new();
+    let mut iter = defaults.iter().peekable();
+    while let Some(arg) = iter.next() {
+        let start = defaults.len() - iter.len() - 1;
+        let positional = flags.positional.len();
+        let result = flags.apply(arg, &mut iter);
+        let group = &defaults[start..defaults.len() - iter.len()];
+        let problem = match result {
+            Err(e) => Some(e),
+            Ok(Some(_)) => Some("only options can be set there".to_string()),
+            Ok(None) if flags.positional.len() > positional => {
+                Some("only options can be set there".to_string())
+            }
+            Ok(None) => None,
+        };
+        if let Some(problem) = problem {
+            let group = group.join(" ");
+            warnings.push(format!("Ignoring '{group}' in {OPTS_ENV}: {problem}"));
+        } else if parse_args(&[merged.as_slice(), group, args].concat()).is_ok() {
+            merged.extend_from_slice(group);
+        }
+    }
+    merged.extend_from_slice(args);
+    (merged, warnings)
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut flags = Flags::new();
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        if let Some(command) = flags.apply(arg, &mut iter)? {
+            return Ok(command);
+        }
+    }
+    flags.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_split_opts() {
+        let (args, warnings) = split_opts("  --font 'JetBrains Mono'\t--add-sign \"\\\"\" -U\\ 3 ");
+        assert_eq!(
+            args,
+            ["--font", "JetBrains Mono", "--add-sign", "\"", "-U 3"]
+        );
+        assert!(warnings.is_empty());
+
+        let (args, warnings) = split_opts("--tree --font 'Fira Code");
+        assert_eq!(args, ["--tree", "--font"]);
+        assert_eq!(
+            warnings,
+            ["Ignoring unterminated 'Fira Code in GPUI_DIFF_TOOL_OPTS"]
+        );
+
+        let (mut args, _) = split_opts("--theme colorblind --list");
+        args.extend(["--tree", "a", "b"].map(String::from));
+        let Ok(Command::Run(args)) = parse_args(&args) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.theme, ThemePreset::Colorblind);
+        assert_eq!(args.panel_mode, Some(PanelMode::Tree));
+    }
+
+    #[test]
+    fn test_with_defaults() {
+        let strings = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let defaults = strings(&[
+            "--bogus",
+            "--theme",
+            "neon",
+            "--watch",
+            "--no-untracked",
+            "--font-size",
+            "15",
+            "stray",
+        ]);
+        let (args, warnings) = with_defaults(&defaults, &strings(&["--json", "a", "b"]));
+        assert_eq!(args, ["--font-size", "15", "--json", "a", "b"]);
+        assert_eq!(
+            warnings,
+            [
+                "Ignoring '--bogus' in GPUI_DIFF_TOOL_OPTS: Unknown flag '--bogus'",
+                "Ignoring '--theme neon' in GPUI_DIFF_TOOL_OPTS: Invalid --theme 'neon', \
+                 expected default, colorblind or monochrome",
+                "Ignoring 'stray' in GPUI_DIFF_TOOL_OPTS: only options can be set there",
+            ]
+        );
+
+        let defaults = strings(&["--no-line-numbers", "--indent-guides", "--no-untracked"]);
+        let cli = strings(&[
+            "--line-numbers",
+            "--no-indent-guides",
+            "--git",
+            "--untracked-all",
+        ]);
+        let (args, warnings) = with_defaults(&defaults, &cli);
+        assert!(warnings.is_empty());
+        let Ok(Command::Run(args)) = parse_args(&args) else {
+            panic!("expected run command");
+        };
+        assert!(args.line_numbers);
+        assert!(!args.indent_guides);
+        assert_eq!(args.untracked, UntrackedFiles::All);
+    }
+
+    #[test]
+    fn test_parse_file_pairs() {
+        let Ok(Command::Run(args)) = parse(&["a", "b", "c", "d"]) else {
+            panic!("expected run command");
+        };
+        let Mode::FilePairs(pairs) = args.mode else {
+            panic!("expected file pairs");
+        };
+        assert_eq!(
+            pairs,
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("c".to_string(), "d".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_colon_pairs() {
+        let Ok(Command::Run(args)) = parse(&["a:b", "c", "d", r"C:\x.txt:D:/y.txt"]) else {
+            panic!("expected run command");
+        };
+        let Mode::FilePairs(pairs) = args.mode else {
+            panic!("expected file pairs");
+        };
+        assert_eq!(
+            pairs,
+            vec![
+                ("a".to_string(), "b".to_string()),
+                ("c".to_string(), "d".to_string()),
+                (r"C:\x.txt".to_string(), "D:/y.txt".to_string())
+            ]
+        );
+
+        assert_eq!(split_pair_arg("C:/only.txt"), None);
+        assert_eq!(split_pair_arg("a:b:c"), None);
+        assert_eq!(split_pair_arg(":b"), None);
+        assert_eq!(split_pair_arg("a:"), None);
+        assert_eq!(
+            split_pair_arg("old.txt:C:\\new.txt"),
+            Some(("old.txt", "C:\\new.txt"))
+        );
+        assert_eq!(
+            parse(&["a", "b:c"]).err().as_deref(),
+            Some("Expected a new file after 'a', got the pair 'b:c'")
+        );
+        assert_eq!(
+            parse(&["a:b", "c"]).err().as_deref(),
+            Some("Expected a new file after 'c'")
+        );
+    }
+
+    #[test]
+    fn test_parse_untracked() {
+        let Ok(Command::Run(args)) = parse(&["--git"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.untracked, UntrackedFiles::Unignored);
+        let Ok(Command::Run(args)) = parse(&["--git", "--no-untracked", "--split"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.untracked, UntrackedFiles::Skip);
+        let Ok(Command::Run(args)) = parse(&["--untracked-all", "--git", "--base", "v1"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.untracked, UntrackedFiles::All);
+
+        assert_eq!(
+            parse(&["--git", "--no-untracked", "--untracked-all"])
+                .err()
+                .as_deref(),
+            Some("--untracked-all cannot be combined with --no-untracked")
+        );
+        assert_eq!(
+            parse(&["--git", "--staged", "--no-untracked"])
+                .err()
+                .as_deref(),
+            Some("--no-untracked only applies to working tree diffs")
+        );
+        assert_eq!(
+            parse(&["--untracked-all", "a", "b"]).err().as_deref(),
+            Some("--untracked-all requires --git")
+        );
+    }
+
+    #[test]
+    fn test_parse_git_staged() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--staged"]) else {
+            panic!("expected run command");
+        };
+        assert!(matches!(
+            args.mode,
+            Mode::Git(GitTarget::WorkTree { staged: true })
+        ));
+    }
+
+    #[test]
+    fn test_parse_git_merge_base() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--merge-base", "main", "--staged"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(
+            target,
+            GitTarget::MergeBase {
+                branch: "main".to_string(),
+                staged: true
+            }
+        );
+        assert!(parse(&["--git", "--merge-base"]).is_err());
+        assert!(parse(&["--git", "--merge-base", "main", "--stash"]).is_err());
+        assert!(parse(&["--merge-base", "main"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_base() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--base", "v1.0.0"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(
+            target,
+            GitTarget::Base {
+                rev: "v1.0.0".to_string(),
+                staged: false
+            }
+        );
+        assert!(parse(&["--git", "--base"]).is_err());
+        assert!(parse(&["--git", "--base", "v1", "--merge-base", "main"]).is_err());
+        assert!(parse(&["--base", "v1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_branch_compare() {
+        let Ok(Command::Run(args)) = parse(&[
+            "--git",
+            "--branch-compare",
+            "feature:src/x.rs",
+            "main:src/x.rs",
+        ]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(
+            target,
+            GitTarget::RefPaths(vec![(
+                "feature:src/x.rs".to_string(),
+                "main:src/x.rs".to_string()
+            )])
+        );
+        assert!(parse(&["--git", "--branch-compare", "main:a.rs"]).is_err());
+        assert!(parse(&["--git", "--branch-compare", "main:a.rs", "b.rs"]).is_err());
+        assert!(parse(&["--git", "--branch-compare", ":a.rs", "main:"]).is_err());
+        assert!(parse(&["--git", "--branch-compare", "--range", "a..b"]).is_err());
+        assert!(parse(&["--branch-compare", "a:x", "b:x"]).is_err());
+        assert!(parse(&["--git", "main:a.rs", "feature:a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_log() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--log", "src/x.rs"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(target, GitTarget::Log("src/x.rs".to_string()));
+        assert!(parse(&["--git", "--log"]).is_err());
+        assert!(parse(&["--log", "src/x.rs"]).is_err());
+        assert!(parse(&["--git", "--log", "src/x.rs", "--staged"]).is_err());
+        assert!(parse(&["--git", "--log", "src/x.rs", "--watch"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_blob() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--blob", "3b18e51", "9f2c1a0"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(
+            target,
+            GitTarget::Blob {
+                old: "3b18e51".to_string(),
+                new: "9f2c1a0".to_string()
+            }
+        );
+        assert!(parse(&["--git", "--blob", "3b18e51"]).is_err());
+        assert!(parse(&["--blob", "3b18e51", "9f2c1a0"]).is_err());
+        assert!(parse(&["--git", "--blob", "a", "b", "--staged"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_split() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--split"]) else {
+            panic!("expected run command");
+        };
+        assert!(matches!(args.mode, Mode::Git(GitTarget::Split)));
+        assert!(parse(&["--git", "--split", "--staged"]).is_err());
+        assert!(parse(&["--split"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_stash() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--stash", "2"]) else {
+            panic!("expected run command");
+        };
+        assert!(matches!(args.mode, Mode::Git(GitTarget::Stash(2))));
+        let Ok(Command::Run(args)) = parse(&["--stash", "--git"]) else {
+            panic!("expected run command");
+        };
+        assert!(matches!(args.mode, Mode::Git(GitTarget::Stash(0))));
+        assert!(parse(&["--git", "--stash", "--staged"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_git_range() {
+        let Ok(Command::Run(args)) = parse(&["--git", "--range", "main..feature"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Git(target) = args.mode else {
+            panic!("expected git mode");
+        };
+        assert_eq!(target.label(), "main..feature");
+        assert!(parse(&["--git", "--range", "main"]).is_err());
+        assert!(parse(&["--git", "--range", "main...feature"]).is_err());
+        assert!(parse(&["--git", "--range", "a..b", "--stash"]).is_err());
+        assert!(parse(&["--range", "a..b"]).is_err());
+    }
+
+    #[test]
+    fn test_help_and_version() {
+        assert!(matches!(parse(&["--git", "--help"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn test_unknown_flag_is_named() {
+        let Err(e) = parse(&["--gti"]) else {
+            panic!("expected error");
+        };
+        assert!(e.contains("--gti"));
+    }
+
+    #[test]
+    fn test_parse_stdin_new() {
+        let Ok(Command::Run(args)) = parse(&["--stdin-new", "golden.txt"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Stdin { file, stdin_is_new } = args.mode else {
+            panic!("expected stdin mode");
+        };
+        assert_eq!(file, "golden.txt");
+        assert!(stdin_is_new);
+        assert!(parse(&["--stdin-old"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let Ok(Command::Run(args)) = parse(&["--patch", "-"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Patch(file) = args.mode else {
+            panic!("expected patch mode");
+        };
+        assert_eq!(file, "-");
+        assert!(parse(&["--patch"]).is_err());
+        assert!(parse(&["--patch", "x.diff", "extra"]).is_err());
+        assert!(parse(&["--patch", "x.diff", "--watch"]).is_err());
+        assert!(parse(&["--patch", "x.diff", "--git"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let Ok(Command::Run(args)) = parse(&["--manifest", "pairs.txt"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Manifest(file) = args.mode else {
+            panic!("expected manifest mode");
+        };
+        assert_eq!(file, "pairs.txt");
+        assert!(parse(&["--manifest"]).is_err());
+        assert!(parse(&["--manifest", "pairs.txt", "a", "b"]).is_err());
+        assert!(parse(&["--manifest", "pairs.txt", "--git"]).is_err());
+
+        let text = "# old\tnew\na.txt\tb.txt\n\n  \ndir/with space.rs\tother.rs\r\n";
+        assert_eq!(
+            parse_manifest(text).unwrap(),
+            vec![
+                ("a.txt".to_string(), "b.txt".to_string()),
+                ("dir/with space.rs".to_string(), "other.rs".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_manifest("a\tb\n\nonly-one-path\n").unwrap_err(),
+            "Invalid manifest line 3, expected <old-path><TAB><new-path>"
+        );
+        assert!(parse_manifest("a\tb\tc\n").is_err());
+        assert!(parse_manifest("\tb\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let Ok(Command::Run(args)) = parse(&["--cmd", "ls a", "--cmd", "ls b"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Commands { old, new } = args.mode else {
+            panic!("expected commands mode");
+        };
+        assert_eq!((old.as_str(), new.as_str()), ("ls a", "ls b"));
+        assert!(parse(&["--cmd"]).is_err());
+        assert!(parse(&["--cmd", "ls a"]).is_err());
+        assert!(parse(&["--cmd", "ls a", "--cmd", "ls b", "--cmd", "ls c"]).is_err());
+        assert!(parse(&["--cmd", "ls a", "--cmd", "ls b", "extra"]).is_err());
+        assert!(parse(&["--cmd", "ls a", "--cmd", "ls b", "--git"]).is_err());
+        assert!(parse(&["--cmd", "ls a", "--cmd", "ls b", "--watch"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_interdiff() {
+        let Ok(Command::Run(args)) = parse(&["--interdiff", "v1.diff", "v2.diff"]) else {
+            panic!("expected run command");
+        };
+        let Mode::Interdiff { old, new } = args.mode else {
+            panic!("expected interdiff mode");
+        };
+        assert_eq!((old.as_str(), new.as_str()), ("v1.diff", "v2.diff"));
+        assert!(parse(&["--interdiff", "v1.diff"]).is_err());
+        assert!(parse(&["--interdiff", "a", "b", "--patch", "c"]).is_err());
+    }
+
+    #[test]
+    fn test_exclude_filters_file_pairs() {
+        let Ok(Command::Run(args)) = parse(&["--exclude", "*.lock", "a.lock", "b.lock", "x", "y"])
+        else {
+            panic!("expected run command");
+        };
+        let Mode::FilePairs(pairs) = args.mode else {
+            panic!("expected file pairs");
+        };
+        assert_eq!(pairs, vec![("x".to_string(), "y".to_string())]);
+        assert!(args.exclude.is_match("sub/dir/Cargo.lock"));
+        assert!(parse(&["--exclude", "a[", "x", "y"]).is_err());
+    }
+
+    #[test]
+    fn test_generated_patterns() {
+        let Ok(Command::Run(args)) = parse(&["--generated", "*.pb.go", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.generated.is_match("api/service.pb.go"));
+        assert!(args.generated.is_match("Cargo.lock"));
+        assert!(args.generated.is_match("web/node_modules/x/index.js"));
+        assert!(!args.generated.is_match("src/main.rs"));
+
+        let Ok(Command::Run(args)) = parse(&["--no-default-generated", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.generated.is_match("Cargo.lock"));
+    }
+
+    #[test]
+    fn test_context_lines() {
+        let Ok(Command::Run(args)) = parse(&["-U", "3", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.context_lines, Some(3));
+        assert!(parse(&["--context", "many", "a", "b"]).is_err());
+        assert!(parse(&["--context"]).is_err());
+        let Ok(Command::Run(args)) = parse(&["--collapse-context", "0", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.context_lines, Some(0));
+    }
+
+    #[test]
+    fn test_panel_side() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.panel_side, PanelSide::Right);
+        let Ok(Command::Run(args)) = parse(&["--panel", "left", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.panel_side, PanelSide::Left);
+        assert!(parse(&["--panel", "top", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_panel_mode() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.panel_mode, None);
+        let Ok(Command::Run(args)) = parse(&["--list", "--tree", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.panel_mode, Some(PanelMode::Tree));
+    }
+
+    #[test]
+    fn test_sbs_alignment() {
+        let Ok(Command::Run(args)) = parse(&["--align", "grouped", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.sbs_alignment, SideBySideAlignment::Grouped);
+        assert!(parse(&["--align", "fuzzy", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_theme_and_signs() {
+        let Ok(Command::Run(args)) = parse(&[
+            "--theme",
+            "colorblind",
+            "--add-sign",
+            "▸",
+            "--delete-sign",
+            "▾",
+            "a",
+            "b",
+        ]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.theme, ThemePreset::Colorblind);
+        assert_eq!(args.insert_sign.as_deref(), Some("▸"));
+        assert_eq!(args.delete_sign.as_deref(), Some("▾"));
+        assert!(parse(&["--theme", "neon", "a", "b"]).is_err());
+
+        let Ok(Command::Run(args)) = parse(&["--no-color", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.theme, ThemePreset::Monochrome);
+        let Ok(Command::Run(args)) = parse(&["--no-color", "--theme", "default", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.theme, ThemePreset::Default);
+        assert!(parse(&["a", "b", "--add-sign"]).is_err());
+    }
+
+    #[test]
+    fn test_side_labels() {
+        let Ok(Command::Run(args)) = parse(&[
+            "--label-old",
+            "v1.2",
+            "--label-new",
+            "v1.3",
+            "/tmp/a",
+            "/tmp/b",
+        ]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.label_old.as_deref(), Some("v1.2"));
+        assert_eq!(args.label_new.as_deref(), Some("v1.3"));
+        assert!(matches!(args.mode, Mode::FilePairs(_)));
+        assert!(parse(&["--git", "--label-old", "before"]).is_err());
+        assert!(parse(&["a", "b", "--label-new"]).is_err());
+    }
+
+    #[test]
+    fn test_exit_on_empty() {
+        let Ok(Command::Run(args)) = parse(&["--git"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.exit_on_empty);
+        let Ok(Command::Run(args)) = parse(&["--git", "--exit-on-empty"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.exit_on_empty);
+    }
+
+    #[test]
+    fn test_hide_unchanged() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.hide_unchanged);
+        let Ok(Command::Run(args)) = parse(&["--hide-unchanged", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.hide_unchanged);
+    }
+
+    #[test]
+    fn test_indent_guides() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.indent_guides);
+        assert_eq!(args.tab_width, DEFAULT_TAB_WIDTH);
+        let Ok(Command::Run(args)) = parse(&["--indent-guides", "--tab-width", "2", "a", "b"])
+        else {
+            panic!("expected run command");
+        };
+        assert!(args.indent_guides);
+        assert_eq!(args.tab_width, 2);
+        assert!(parse(&["--tab-width", "0", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.ignore_case);
+        let Ok(Command::Run(args)) = parse(&["--ignore-case", "--git"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.ignore_case);
+    }
+
+    #[test]
+    fn test_semantic() {
+        let Ok(Command::Run(args)) = parse(&["a.json", "b.json"]) else {
+            panic!("expected run command");
+        };
+        assert!(!args.semantic);
+        let Ok(Command::Run(args)) = parse(&["--semantic", "a.json", "b.json"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.semantic);
+    }
+
+    #[test]
+    fn test_font_flags() {
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.font, None);
+        assert_eq!(args.font_size, DEFAULT_FONT_SIZE);
+        let Ok(Command::Run(args)) =
+            parse(&["--font", "Fira Code", "--font-size", "15.5", "a", "b"])
+        else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.font.as_deref(), Some("Fira Code"));
+        assert_eq!(args.font_size, 15.5);
+        assert!(parse(&["--font-size", "0", "a", "b"]).is_err());
+        assert!(parse(&["--font-size", "big", "a", "b"]).is_err());
+        assert!(parse(&["a", "b", "--font"]).is_err());
+    }
+
+    #[test]
+    fn test_max_size() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("2MB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("512kb"), Ok(512 * 1024));
+        assert!(parse_size("big").is_err());
+        assert_eq!(
+            parse(&["--max-size", "99999999999GB", "a", "b"])
+                .err()
+                .as_deref(),
+            Some("--max-size '99999999999GB' is too large")
+        );
+
+        let Ok(Command::Run(args)) = parse(&["a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.max_size, Some(DEFAULT_MAX_DIFF_SIZE));
+        let Ok(Command::Run(args)) = parse(&["--max-size", "0", "a", "b"]) else {
+            panic!("expected run command");
+        };
+        assert_eq!(args.max_size, None);
+    }
+
+    #[test]
+    fn test_watch() {
+        let Ok(Command::Run(args)) = parse(&["--watch", "--git", "--staged"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.watch);
+        assert!(parse(&["--watch", "--stdin-new", "a"]).is_err());
+        assert!(parse(&["--watch", "--git", "--stash"]).is_err());
+        assert!(parse(&["--watch", "--git", "--range", "a..b"]).is_err());
+        assert!(parse(&["--watch", "--json", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_wait() {
+        assert!(matches!(parse(&["--wait", "a", "b"]), Ok(Command::Run(_))));
+        assert!(parse(&["--wait", "--json", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let Ok(Command::Run(args)) = parse(&["--summary", "--git", "--staged"]) else {
+            panic!("expected run command");
+        };
+        assert!(args.summary);
+        assert!(parse(&["--summary", "--json", "a", "b"]).is_err());
+        assert!(parse(&["--summary", "--wait", "a", "b"]).is_err());
+        assert!(parse(&["--summary", "--watch", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_ranges() {
+        let Ok(Command::Run(args)) = parse(&[
+            "--range-old",
+            "a.txt:10-40",
+            "--range-new",
+            "C:/b.txt:12-45",
+        ]) else {
+            panic!("expected run command");
+        };
+        let Mode::LineRanges { old, new } = args.mode else {
+            panic!("expected line ranges mode");
+        };
+        assert_eq!(old, ("a.txt".to_string(), 10..=40));
+        assert_eq!(new, ("C:/b.txt".to_string(), 12..=45));
+        assert!(parse(&["--range-old", "a.txt:10-40"]).is_err());
+        assert!(parse(&["--range-old", "a.txt:40-10", "--range-new", "b:1-2"]).is_err());
+        assert!(parse(&["--range-old", "a.txt:0-3", "--range-new", "b:1-2"]).is_err());
+        assert!(parse(&["--range-old", "a.txt", "--range-new", "b:1-2"]).is_err());
+    }
+
+    #[test]
+    fn test_odd_number_of_files() {
+        assert!(parse(&["a", "b", "c"]).is_err());
+    }
+}
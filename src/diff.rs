@@ -1,82 +1,879 @@
-use gpui::SharedString;
-use similar::{ChangeTag, TextDiff};
+use serde::{Serialize, Serializer};
+use similar::{capture_diff_slices, Algorithm, ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fs;
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-#[derive(Clone)]
+use crate::semantic::canonicalize_pair;
+
+/// Files larger than this are not line-diffed unless forced.
+pub const DEFAULT_MAX_DIFF_SIZE: u64 = 5 * 1024 * 1024;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp"];
+
+/// Resolves a user-given path to the canonical file it names, following
+/// symlinks and relative components, so it can be read regardless of how
+/// it was spelled. Directories are rejected.
+pub fn resolve_file_path(path: &str) -> Result<PathBuf, String> {
+    let resolved = fs::canonicalize(path).map_err(|e| format!("Cannot open {path}: {e}"))?;
+    if resolved.is_dir() {
+        return Err(format!("{path} is a directory, not a file"));
+    }
+    Ok(resolved)
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    let resolved = resolve_file_path(path)?;
+    fs::read_to_string(resolved).map_err(|e| format!("Failed to read {path}: {e}"))
+}
+
+/// Whether `path` has an image file extension the viewer can render.
+pub fn is_image_path(path: &str) -> bool {
+    path.rsplit_once('.').is_some_and(|(_, ext)| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|image_ext| ext.eq_ignore_ascii_case(image_ext))
+    })
+}
+
+/// Whether `content` ends some lines with `\n` and others with `\r\n`.
+pub fn has_mixed_line_endings(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    crlf > 0 && crlf < content.matches('\n').count()
+}
+
+/// Removes ANSI color (SGR) escape sequences, such as those in the output of
+/// `git -c color.ui=always diff`, so they don't end up in line content.
+pub fn strip_ansi_colors(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let params = after
+            .find(|c: char| !c.is_ascii_digit() && c != ';')
+            .unwrap_or(after.len());
+        if after[params..].starts_with('m') {
+            rest = &after[params + 1..];
+        } else {
+            out.push_str("\x1b[");
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// A single line of a computed diff with its line numbers on each side.
+#[derive(Clone, Serialize)]
 pub struct DiffLine {
+    #[serde(serialize_with = "serialize_tag")]
     pub tag: ChangeTag,
     pub old_lineno: Option<usize>,
     pub new_lineno: Option<usize>,
-    pub content: SharedString,
+    pub content: Arc<str>,
+}
+
+// `similar` only implements serde behind a feature; write tags as add/del/equal.
+fn serialize_tag<S: Serializer>(tag: &ChangeTag, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match tag {
+        ChangeTag::Equal => "equal",
+        ChangeTag::Delete => "del",
+        ChangeTag::Insert => "add",
+    })
 }
 
+impl DiffLine {
+    /// Whether this is an added or removed line containing only whitespace.
+    pub fn is_blank_change(&self) -> bool {
+        self.tag != ChangeTag::Equal && self.content.trim().is_empty()
+    }
+
+    /// Whether this line is a merge conflict marker (`<<<<<<<`, `|||||||`,
+    /// `=======` or `>>>>>>>`).
+    pub fn is_conflict_marker(&self) -> bool {
+        let content = self.content.trim_end();
+        if content == "=======" {
+            return true;
+        }
+        ["<<<<<<<", "|||||||", ">>>>>>>"].iter().any(|marker| {
+            content
+                .strip_prefix(marker)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+        })
+    }
+
+    /// The width of the leading whitespace in columns, with tabs advancing to
+    /// the next multiple of `tab_width`.
+    pub fn indent_columns(&self, tab_width: usize) -> usize {
+        let tab_width = tab_width.max(1);
+        self.content
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .fold(0, |column, c| match c {
+                '\t' => (column / tab_width + 1) * tab_width,
+                _ => column + 1,
+            })
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// The leading whitespace of `text` as one entry per column, with tabs
+/// filling up to the next multiple of `tab_width`. Each entry is the byte
+/// offset and character covering that column.
+fn indent_cells(text: &str, tab_width: usize) -> Vec<(usize, char)> {
+    let tab_width = tab_width.max(1);
+    let mut cells = Vec::new();
+    for (offset, c) in text.char_indices().take_while(|(_, c)| is_blank(*c)) {
+        let width = match c {
+            '\t' => tab_width - cells.len() % tab_width,
+            _ => 1,
+        };
+        cells.extend(std::iter::repeat_n((offset, c), width));
+    }
+    cells
+}
+
+/// Byte ranges of the leading and trailing whitespace that differs between
+/// two paired lines, for the old and the new line respectively. Indentation
+/// is compared column by column with tabs expanded to `tab_width`, so
+/// swapping spaces for tabs counts as a change but a shared prefix doesn't.
+pub fn whitespace_change_ranges(
+    old: &str,
+    new: &str,
+    tab_width: usize,
+) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let mut old_ranges = Vec::new();
+    let mut new_ranges = Vec::new();
+
+    let old_indent_end = old.len() - old.trim_start_matches(is_blank).len();
+    let new_indent_end = new.len() - new.trim_start_matches(is_blank).len();
+    let (old_cells, new_cells) = (indent_cells(old, tab_width), indent_cells(new, tab_width));
+    let common = old_cells
+        .iter()
+        .zip(&new_cells)
+        .take_while(|((_, a), (_, b))| a == b)
+        .count();
+    if let Some((offset, _)) = old_cells.get(common) {
+        old_ranges.push(*offset..old_indent_end);
+    }
+    if let Some((offset, _)) = new_cells.get(common) {
+        new_ranges.push(*offset..new_indent_end);
+    }
+
+    // A blank line is all indentation, which was compared above.
+    if old_indent_end < old.len() && new_indent_end < new.len() {
+        let old_trail = old.len() - old.trim_end_matches(is_blank).len();
+        let new_trail = new.len() - new.trim_end_matches(is_blank).len();
+        let common = old[old.len() - old_trail..]
+            .bytes()
+            .rev()
+            .zip(new[new.len() - new_trail..].bytes().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if old_trail > common {
+            old_ranges.push(old.len() - old_trail..old.len() - common);
+        }
+        if new_trail > common {
+            new_ranges.push(new.len() - new_trail..new.len() - common);
+        }
+    }
+    (old_ranges, new_ranges)
+}
+
+/// How a file changed, as reported by `git status`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Untracked,
+}
+
+impl FileStatus {
+    /// The single-letter badge git uses for this status.
+    pub fn letter(self) -> &'static str {
+        match self {
+            FileStatus::Added => "A",
+            FileStatus::Modified => "M",
+            FileStatus::Deleted => "D",
+            FileStatus::Untracked => "U",
+        }
+    }
+
+    /// The status of the same change viewed with old and new swapped.
+    pub fn reversed(self) -> Self {
+        match self {
+            FileStatus::Added | FileStatus::Untracked => FileStatus::Deleted,
+            FileStatus::Deleted => FileStatus::Added,
+            FileStatus::Modified => FileStatus::Modified,
+        }
+    }
+}
+
+/// Which side of the index a work-tree change is on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Staging {
+    Staged,
+    Unstaged,
+}
+
+/// The kind of object git stores at a path.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileMode {
+    Regular,
+    Executable,
+    Symlink,
+    Submodule,
+}
+
+impl FileMode {
+    /// Parses an octal mode such as `100644` from `git diff --raw`.
+    pub fn from_octal(mode: &str) -> Option<Self> {
+        match mode {
+            "100644" => Some(FileMode::Regular),
+            "100755" => Some(FileMode::Executable),
+            "120000" => Some(FileMode::Symlink),
+            "160000" => Some(FileMode::Submodule),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            FileMode::Regular => "regular file",
+            FileMode::Executable => "executable file",
+            FileMode::Symlink => "symlink",
+            FileMode::Submodule => "submodule",
+        }
+    }
+}
+
+/// The line diff between two versions of a file.
+#[derive(Serialize)]
 pub struct FileDiff {
-    pub old_path: SharedString,
-    pub new_path: SharedString,
+    pub old_path: Arc<str>,
+    pub new_path: Arc<str>,
     pub lines: Vec<DiffLine>,
+    pub generated: bool,
+    pub status: Option<FileStatus>,
+    /// Set when staged and unstaged changes are shown together.
+    pub staging: Option<Staging>,
+    /// Set to the larger file's size in bytes when the diff was skipped.
+    pub too_large: Option<u64>,
+    /// The old and new kinds when the path changed kind, e.g. from a regular
+    /// file to a symlink.
+    pub type_change: Option<(FileMode, FileMode)>,
+    /// Set for image files read from disk; the viewer shows the images
+    /// instead of a line diff.
+    pub image: bool,
+    /// The commit this diff introduced, as `<short hash> <subject>`, when
+    /// stepping through a file's history.
+    pub commit: Option<Arc<str>>,
+    /// Set when the new side ends some lines with LF and others with CRLF.
+    /// Only reported; the lines are diffed as they are.
+    pub mixed_line_endings: bool,
+    /// The texts the diff was computed from, kept so it can be recomputed.
+    #[serde(skip)]
+    pub old_content: Option<Arc<str>>,
+    #[serde(skip)]
+    pub new_content: Option<Arc<str>>,
+    /// The options [`FileDiff::lines`] was last computed with.
+    #[serde(skip)]
+    pub options: DiffOptions,
+    /// Added to old and new line numbers when only part of each file was
+    /// diffed, so they match the original files.
+    #[serde(skip)]
+    pub line_offset: (usize, usize),
+    /// The largest old or new line number, kept to size the line-number
+    /// gutter without scanning every line on each render.
+    #[serde(skip)]
+    max_lineno: usize,
+}
+
+/// Settings that control how [`FileDiff::lines`] is computed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffOptions {
+    pub algorithm: Algorithm,
+    /// Keep the raw old and new texts on the [`FileDiff`] so it can be
+    /// recomputed later. Turn off to save memory on very large sets.
+    pub keep_contents: bool,
+    /// Treat lines that differ only in letter case as equal. Equal lines
+    /// show the new text.
+    pub ignore_case: bool,
+    /// Diff JSON and YAML files as their canonical text, keys sorted, so
+    /// reordering and reformatting don't show as changes. Files that fail
+    /// to parse are diffed as they are.
+    pub semantic: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Myers,
+            keep_contents: true,
+            ignore_case: false,
+            semantic: false,
+        }
+    }
+}
+
+fn changes_ignoring_case<'a>(
+    old_content: &'a str,
+    new_content: &'a str,
+    algorithm: Algorithm,
+) -> Vec<(ChangeTag, Cow<'a, str>)> {
+    let old_lines: Vec<&str> = old_content.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new_content.split_inclusive('\n').collect();
+    let old_keys: Vec<String> = old_lines.iter().map(|l| l.to_lowercase()).collect();
+    let new_keys: Vec<String> = new_lines.iter().map(|l| l.to_lowercase()).collect();
+
+    let mut changes = Vec::new();
+    for op in capture_diff_slices(algorithm, &old_keys, &new_keys) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => {
+                changes.extend(new_range.map(|i| (ChangeTag::Equal, Cow::from(new_lines[i]))));
+            }
+            _ => {
+                changes.extend(old_range.map(|i| (ChangeTag::Delete, Cow::from(old_lines[i]))));
+                changes.extend(new_range.map(|i| (ChangeTag::Insert, Cow::from(new_lines[i]))));
+            }
+        }
+    }
+    changes
+}
+
+fn max_lineno(lines: &[DiffLine]) -> usize {
+    lines
+        .iter()
+        .flat_map(|line| [line.old_lineno, line.new_lineno])
+        .flatten()
+        .max()
+        .unwrap_or(0)
+}
+
+fn diff_lines(old_content: &str, new_content: &str, options: &DiffOptions) -> Vec<DiffLine> {
+    let changes = if options.ignore_case {
+        changes_ignoring_case(old_content, new_content, options.algorithm)
+    } else {
+        TextDiff::configure()
+            .algorithm(options.algorithm)
+            .diff_lines(old_content, new_content)
+            .iter_all_changes()
+            .map(|change| (change.tag(), change.to_string_lossy()))
+            .collect()
+    };
+    let mut lines = Vec::new();
+    let mut old_lineno = 0usize;
+    let mut new_lineno = 0usize;
+
+    for (tag, text) in changes {
+        let (old_ln, new_ln) = match tag {
+            ChangeTag::Equal => {
+                old_lineno += 1;
+                new_lineno += 1;
+                (Some(old_lineno), Some(new_lineno))
+            }
+            ChangeTag::Delete => {
+                old_lineno += 1;
+                (Some(old_lineno), None)
+            }
+            ChangeTag::Insert => {
+                new_lineno += 1;
+                (None, Some(new_lineno))
+            }
+        };
+
+        let text = text.trim_end_matches('\n');
+        lines.push(DiffLine {
+            tag,
+            old_lineno: old_ln,
+            new_lineno: new_ln,
+            content: Arc::from(text),
+        });
+    }
+    lines
+}
+
+/// [`diff_lines`] over the texts [`DiffOptions::semantic`] picks: the
+/// canonical forms when both sides parse, otherwise the raw contents.
+fn diff_file_lines(
+    old_path: &str,
+    new_path: &str,
+    old_content: &str,
+    new_content: &str,
+    options: &DiffOptions,
+) -> Vec<DiffLine> {
+    let canonical = options
+        .semantic
+        .then(|| canonicalize_pair(old_path, new_path, old_content, new_content))
+        .flatten();
+    match canonical {
+        Some((old, new)) => diff_lines(&old, &new, options),
+        None => diff_lines(old_content, new_content, options),
+    }
 }
 
 impl FileDiff {
+    /// Diffs two in-memory texts line by line with the default options.
     pub fn from_contents(
         old_path: &str,
         new_path: &str,
         old_content: &str,
         new_content: &str,
     ) -> Self {
-        let diff = TextDiff::from_lines(old_content, new_content);
-        let mut lines = Vec::new();
-        let mut old_lineno = 0usize;
-        let mut new_lineno = 0usize;
-
-        for change in diff.iter_all_changes() {
-            let tag = change.tag();
-            let (old_ln, new_ln) = match tag {
-                ChangeTag::Equal => {
-                    old_lineno += 1;
-                    new_lineno += 1;
-                    (Some(old_lineno), Some(new_lineno))
-                }
-                ChangeTag::Delete => {
-                    old_lineno += 1;
-                    (Some(old_lineno), None)
-                }
-                ChangeTag::Insert => {
-                    new_lineno += 1;
-                    (None, Some(new_lineno))
-                }
+        Self::from_contents_with(
+            old_path,
+            new_path,
+            old_content,
+            new_content,
+            DiffOptions::default(),
+        )
+    }
+
+    /// Diffs two in-memory texts line by line.
+    pub fn from_contents_with(
+        old_path: &str,
+        new_path: &str,
+        old_content: &str,
+        new_content: &str,
+        options: DiffOptions,
+    ) -> Self {
+        let keep = |content: &str| options.keep_contents.then(|| Arc::from(content));
+        let lines = diff_file_lines(old_path, new_path, old_content, new_content, &options);
+        Self {
+            old_path: Arc::from(old_path),
+            new_path: Arc::from(new_path),
+            max_lineno: max_lineno(&lines),
+            lines,
+            generated: false,
+            status: None,
+            staging: None,
+            too_large: None,
+            type_change: None,
+            image: false,
+            commit: None,
+            mixed_line_endings: has_mixed_line_endings(new_content),
+            old_content: keep(old_content),
+            new_content: keep(new_content),
+            options,
+            line_offset: (0, 0),
+        }
+    }
+
+    fn apply_line_offset(&mut self) {
+        let (old_offset, new_offset) = self.line_offset;
+        for line in &mut self.lines {
+            line.old_lineno = line.old_lineno.map(|n| n + old_offset);
+            line.new_lineno = line.new_lineno.map(|n| n + new_offset);
+        }
+        self.max_lineno = max_lineno(&self.lines);
+    }
+
+    /// Replaces [`FileDiff::lines`] with lines built elsewhere, e.g. parsed
+    /// from a patch.
+    pub fn set_lines(&mut self, lines: Vec<DiffLine>) {
+        self.max_lineno = max_lineno(&lines);
+        self.lines = lines;
+    }
+
+    /// The largest old or new line number in the diff, or 0 if it has none.
+    pub fn max_lineno(&self) -> usize {
+        self.max_lineno
+    }
+
+    /// Rebuilds [`FileDiff::lines`] from the kept contents using `options`.
+    pub fn recompute(&mut self, options: DiffOptions) -> Result<(), String> {
+        let (Some(old_content), Some(new_content)) = (&self.old_content, &self.new_content) else {
+            return Err(format!("Contents of {} were not kept", self.new_path));
+        };
+        self.lines = diff_file_lines(
+            &self.old_path,
+            &self.new_path,
+            old_content,
+            new_content,
+            &options,
+        );
+        self.options = options;
+        self.apply_line_offset();
+        Ok(())
+    }
+
+    /// Rediffs the file with old and new swapped, so inserts become deletes
+    /// and vice versa. Returns `None` if the contents were not kept.
+    pub fn reversed(&self) -> Option<Self> {
+        let (old_content, new_content) = (self.old_content.as_ref()?, self.new_content.as_ref()?);
+        let mut diff = Self::from_contents_with(
+            &self.new_path,
+            &self.old_path,
+            new_content,
+            old_content,
+            self.options,
+        );
+        diff.generated = self.generated;
+        diff.status = self.status.map(FileStatus::reversed);
+        diff.staging = self.staging;
+        diff.type_change = self.type_change.map(|(old, new)| (new, old));
+        diff.commit = self.commit.clone();
+        diff.line_offset = (self.line_offset.1, self.line_offset.0);
+        diff.apply_line_offset();
+        Some(diff)
+    }
+
+    /// Builds the diff for a path that changed kind. Only symlink targets
+    /// are diffed; the contents of the other side are left out.
+    pub fn from_type_change(
+        path: &str,
+        modes: (FileMode, FileMode),
+        old_content: &str,
+        new_content: &str,
+    ) -> Self {
+        let is_link = |mode| mode == FileMode::Symlink;
+        let old_target = if is_link(modes.0) { old_content } else { "" };
+        let new_target = if is_link(modes.1) { new_content } else { "" };
+        let mut diff = Self::from_contents(path, path, old_target, new_target);
+        diff.type_change = Some(modes);
+        diff
+    }
+
+    /// Describes a change of kind, e.g. "Changed from regular file to symlink".
+    pub fn type_change_message(&self) -> Option<String> {
+        let (old, new) = self.type_change?;
+        Some(format!(
+            "Changed from {} to {}",
+            old.describe(),
+            new.describe()
+        ))
+    }
+
+    /// True when both sides have identical content, so the diff has nothing
+    /// to show.
+    pub fn is_unchanged(&self) -> bool {
+        self.type_change.is_none()
+            && self.too_large.is_none()
+            && !self.image
+            && self.lines.iter().all(|line| line.tag == ChangeTag::Equal)
+    }
+
+    /// The number of added and removed lines.
+    pub fn change_counts(&self) -> (usize, usize) {
+        let count = |tag| self.lines.iter().filter(|line| line.tag == tag).count();
+        (count(ChangeTag::Insert), count(ChangeTag::Delete))
+    }
+
+    /// Whether every line was removed, leaving the new side empty.
+    pub fn is_full_deletion(&self) -> bool {
+        !self.lines.is_empty() && self.lines.iter().all(|line| line.tag == ChangeTag::Delete)
+    }
+
+    /// Whether any line has an old and a new line number, respectively. Added
+    /// files have no old numbers at all and deleted files no new ones.
+    pub fn line_number_sides(&self) -> (bool, bool) {
+        (
+            self.lines.iter().any(|line| line.old_lineno.is_some()),
+            self.lines.iter().any(|line| line.new_lineno.is_some()),
+        )
+    }
+
+    /// The old side's text rebuilt from [`FileDiff::lines`]. See
+    /// [`FileDiff::new_text`].
+    pub fn old_text(&self) -> Option<String> {
+        self.side_text(self.line_offset.0, |line| line.old_lineno)
+    }
+
+    /// The new side's text rebuilt from [`FileDiff::lines`], every line
+    /// ending in `\n`. `None` if the lines skip any line numbers, as a
+    /// patch's hunks do, since the file can't be rebuilt from them.
+    pub fn new_text(&self) -> Option<String> {
+        self.side_text(self.line_offset.1, |line| line.new_lineno)
+    }
+
+    fn side_text(
+        &self,
+        offset: usize,
+        lineno: impl Fn(&DiffLine) -> Option<usize>,
+    ) -> Option<String> {
+        let mut text = String::new();
+        let mut next = offset + 1;
+        for line in &self.lines {
+            let Some(n) = lineno(line) else {
+                continue;
             };
+            if n != next {
+                return None;
+            }
+            next += 1;
+            text.push_str(&line.content);
+            text.push('\n');
+        }
+        Some(text)
+    }
 
-            let text = change.to_string_lossy();
-            let text = text.trim_end_matches('\n');
-            lines.push(DiffLine {
-                tag,
-                old_lineno: old_ln,
-                new_lineno: new_ln,
-                content: SharedString::from(text.to_string()),
-            });
+    /// The line a removed or added line is paired with: the i-th removed
+    /// line of a change block goes with its i-th added line.
+    pub fn paired_line(&self, ix: usize) -> Option<usize> {
+        let tag = self.lines.get(ix)?.tag;
+        if tag == ChangeTag::Equal {
+            return None;
         }
+        let is_change = |line: &DiffLine| line.tag != ChangeTag::Equal;
+        let start = self.lines[..ix]
+            .iter()
+            .rposition(|line| !is_change(line))
+            .map_or(0, |pos| pos + 1);
+        let end = self.lines[ix..]
+            .iter()
+            .position(|line| !is_change(line))
+            .map_or(self.lines.len(), |pos| ix + pos);
+        let of_tag = |tag: ChangeTag| (start..end).filter(move |&i| self.lines[i].tag == tag);
+        let nth = of_tag(tag).position(|i| i == ix)?;
+        let other = match tag {
+            ChangeTag::Delete => ChangeTag::Insert,
+            _ => ChangeTag::Delete,
+        };
+        of_tag(other).nth(nth)
+    }
 
-        Self {
-            old_path: SharedString::from(old_path.to_string()),
-            new_path: SharedString::from(new_path.to_string()),
-            lines,
+    /// Byte ranges of line `ix` whose leading or trailing whitespace differs
+    /// from its paired line's, see [`whitespace_change_ranges`].
+    pub fn whitespace_changes(&self, ix: usize, tab_width: usize) -> Vec<Range<usize>> {
+        let Some(other) = self.paired_line(ix) else {
+            return Vec::new();
+        };
+        let (line, other) = (&self.lines[ix], &self.lines[other]);
+        match line.tag {
+            ChangeTag::Delete => {
+                whitespace_change_ranges(&line.content, &other.content, tab_width).0
+            }
+            _ => whitespace_change_ranges(&other.content, &line.content, tab_width).1,
+        }
+    }
+
+    /// Formats `path:N` for a line, or `old_path:~N` for a deleted line.
+    pub fn line_reference(&self, line: &DiffLine) -> String {
+        match (line.new_lineno, line.old_lineno) {
+            (Some(n), _) => format!("{}:{n}", self.new_path),
+            (None, Some(n)) => format!("{}:~{n}", self.old_path),
+            (None, None) => self.new_path.to_string(),
         }
     }
 
+    /// Reads two files and diffs only the given 1-based, inclusive line
+    /// ranges, numbering lines as they appear in the original files.
+    pub fn from_file_ranges(
+        old_path: &str,
+        old_lines: RangeInclusive<usize>,
+        new_path: &str,
+        new_lines: RangeInclusive<usize>,
+    ) -> Result<Self, String> {
+        let old_content = read_line_range(old_path, &old_lines)?;
+        let new_content = read_line_range(new_path, &new_lines)?;
+        let mut diff = Self::from_contents(old_path, new_path, &old_content, &new_content);
+        diff.line_offset = (old_lines.start() - 1, new_lines.start() - 1);
+        diff.apply_line_offset();
+        Ok(diff)
+    }
+
+    /// Reads and diffs two files; read errors become the file's content.
+    /// The paths are kept as given for display.
     pub fn from_files(old_path: &str, new_path: &str) -> Self {
-        let old_content =
-            fs::read_to_string(old_path).unwrap_or_else(|e| format!("Error reading file: {e}"));
-        let new_content =
-            fs::read_to_string(new_path).unwrap_or_else(|e| format!("Error reading file: {e}"));
+        let old_content = read_file(old_path).unwrap_or_else(|e| format!("Error: {e}"));
+        let new_content = read_file(new_path).unwrap_or_else(|e| format!("Error: {e}"));
         Self::from_contents(old_path, new_path, &old_content, &new_content)
     }
+
+    /// Like [`FileDiff::from_files`], but returns an empty diff with
+    /// `too_large` set when either file exceeds `max_size` bytes, or with
+    /// `image` set for image files. The sizes are checked before the files
+    /// are read.
+    pub fn from_files_with_limit(old_path: &str, new_path: &str, max_size: Option<u64>) -> Self {
+        if is_image_path(old_path) || is_image_path(new_path) {
+            let mut diff = Self::from_contents(old_path, new_path, "", "");
+            diff.image = true;
+            diff.old_content = None;
+            diff.new_content = None;
+            return diff;
+        }
+        let size = [old_path, new_path]
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .max()
+            .unwrap_or(0);
+        match max_size {
+            Some(max_size) if size > max_size => {
+                let mut diff = Self::from_contents(old_path, new_path, "", "");
+                diff.too_large = Some(size);
+                diff.old_content = None;
+                diff.new_content = None;
+                diff
+            }
+            _ => Self::from_files(old_path, new_path),
+        }
+    }
+}
+
+fn read_line_range(path: &str, lines: &RangeInclusive<usize>) -> Result<String, String> {
+    let content = read_file(path)?;
+    let total = content.lines().count();
+    let (start, end) = (*lines.start(), *lines.end());
+    if start == 0 || start > end || end > total {
+        return Err(format!(
+            "Line range {path}:{start}-{end} is out of bounds ({path} has {total} lines)"
+        ));
+    }
+    Ok(content
+        .split_inclusive('\n')
+        .skip(start - 1)
+        .take(end - start + 1)
+        .collect())
+}
+
+/// A run of changed lines plus surrounding context, like a `@@` hunk in git.
+pub struct Hunk {
+    /// Indices into [`FileDiff::lines`].
+    pub lines: Range<usize>,
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// The nearest preceding unindented line, used as the section heading.
+    pub section: Option<String>,
+}
+
+impl Hunk {
+    /// Formats the hunk as `@@ -old_start,old_len +new_start,new_len @@ section`.
+    pub fn header(&self) -> String {
+        let range = format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        );
+        match &self.section {
+            Some(section) => format!("{range} {section}"),
+            None => range,
+        }
+    }
+}
+
+fn is_section_heading(content: &str) -> bool {
+    content
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+}
+
+fn hunk_span(
+    lines: &[DiffLine],
+    range: &Range<usize>,
+    lineno: fn(&DiffLine) -> Option<usize>,
+) -> (usize, usize) {
+    let len = lines[range.clone()].iter().filter_map(lineno).count();
+    let start = lines[range.clone()]
+        .iter()
+        .find_map(lineno)
+        .or_else(|| lines[..range.start].iter().rev().find_map(lineno))
+        .unwrap_or(0);
+    (start, len)
+}
+
+impl FileDiff {
+    /// Groups changed lines into hunks with `context` unchanged lines on each
+    /// side, merging hunks whose context would overlap.
+    pub fn hunks(&self, context: usize) -> Vec<Hunk> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (ix, line) in self.lines.iter().enumerate() {
+            if line.tag == ChangeTag::Equal {
+                continue;
+            }
+            let start = ix.saturating_sub(context);
+            let end = (ix + context + 1).min(self.lines.len());
+            match ranges.last_mut() {
+                Some(last) if start <= last.end => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+
+        ranges
+            .into_iter()
+            .map(|range| {
+                let (old_start, old_len) = hunk_span(&self.lines, &range, |l| l.old_lineno);
+                let (new_start, new_len) = hunk_span(&self.lines, &range, |l| l.new_lineno);
+                let section = self.lines[..range.start]
+                    .iter()
+                    .rev()
+                    .filter(|l| l.old_lineno.is_some())
+                    .find(|l| is_section_heading(&l.content))
+                    .map(|l| l.content.trim_end().to_string());
+                Hunk {
+                    lines: range,
+                    old_start,
+                    old_len,
+                    new_start,
+                    new_len,
+                    section,
+                }
+            })
+            .collect()
+    }
+
+    /// Formats the hunks of [`FileDiff::hunks`] whose indices are in
+    /// `selected` as a unified diff that applies to the old file on its own:
+    /// new line numbers are shifted to account for the hunks left out.
+    pub fn partial_patch(&self, context: usize, selected: &BTreeSet<usize>) -> String {
+        let hunks = self.hunks(context);
+        let old_name = match self.status {
+            Some(FileStatus::Added | FileStatus::Untracked) => "/dev/null".to_string(),
+            _ => format!("a/{}", self.old_path),
+        };
+        let new_name = match self.status {
+            Some(FileStatus::Deleted) if selected.len() == hunks.len() => "/dev/null".to_string(),
+            _ => format!("b/{}", self.new_path),
+        };
+        let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+        let mut skipped = 0isize;
+        for (ix, mut hunk) in hunks.into_iter().enumerate() {
+            if !selected.contains(&ix) {
+                skipped += hunk.new_len as isize - hunk.old_len as isize;
+                continue;
+            }
+            hunk.new_start = hunk.new_start.saturating_add_signed(-skipped);
+            out.push_str(&hunk.header());
+            out.push('\n');
+            for line in &self.lines[hunk.lines] {
+                let sign = match line.tag {
+                    ChangeTag::Equal => ' ',
+                    ChangeTag::Delete => '-',
+                    ChangeTag::Insert => '+',
+                };
+                out.push(sign);
+                out.push_str(&line.content);
+                out.push('\n');
+            }
+        }
+        out
+    }
 }
 
+/// One row of a side-by-side view; `None` leaves that side empty.
 pub struct SideBySideLine {
     pub left: Option<DiffLine>,
     pub right: Option<DiffLine>,
 }
 
+/// Aligns diff lines into rows, pairing deletes with inserts in order.
 pub fn to_side_by_side(lines: &[DiffLine]) -> Vec<SideBySideLine> {
     let mut result = Vec::new();
     let mut delete_buf: Vec<DiffLine> = Vec::new();
@@ -125,10 +922,718 @@ pub fn to_side_by_side(lines: &[DiffLine]) -> Vec<SideBySideLine> {
     result
 }
 
+const MATCH_THRESHOLD: f32 = 0.5;
+const MAX_MATCH_GROUP: usize = 200;
+
+fn line_similarity(a: &str, b: &str) -> f32 {
+    TextDiff::from_chars(a, b).ratio()
+}
+
+fn match_change_group(
+    deletes: &[DiffLine],
+    inserts: &[DiffLine],
+    result: &mut Vec<SideBySideLine>,
+) {
+    let (n, m) = (deletes.len(), inserts.len());
+    if n == 0 || m == 0 || n > MAX_MATCH_GROUP || m > MAX_MATCH_GROUP {
+        let group: Vec<DiffLine> = deletes.iter().chain(inserts).cloned().collect();
+        result.extend(to_side_by_side(&group));
+        return;
+    }
+
+    let mut sim = vec![vec![0.0f32; m]; n];
+    for (i, del) in deletes.iter().enumerate() {
+        for (j, ins) in inserts.iter().enumerate() {
+            sim[i][j] = line_similarity(&del.content, &ins.content);
+        }
+    }
+
+    let mut score = vec![vec![0.0f32; m + 1]; n + 1];
+    for i in 1..=n {
+        for j in 1..=m {
+            let mut best = score[i - 1][j].max(score[i][j - 1]);
+            if sim[i - 1][j - 1] >= MATCH_THRESHOLD {
+                best = best.max(score[i - 1][j - 1] + sim[i - 1][j - 1]);
+            }
+            score[i][j] = best;
+        }
+    }
+
+    let mut rows = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0
+            && j > 0
+            && sim[i - 1][j - 1] >= MATCH_THRESHOLD
+            && score[i][j] == score[i - 1][j - 1] + sim[i - 1][j - 1]
+        {
+            rows.push((Some(i - 1), Some(j - 1)));
+            i -= 1;
+            j -= 1;
+        } else if j > 0 && (i == 0 || score[i][j] == score[i][j - 1]) {
+            rows.push((None, Some(j - 1)));
+            j -= 1;
+        } else {
+            rows.push((Some(i - 1), None));
+            i -= 1;
+        }
+    }
+
+    for (del, ins) in rows.into_iter().rev() {
+        result.push(SideBySideLine {
+            left: del.map(|i| deletes[i].clone()),
+            right: ins.map(|j| inserts[j].clone()),
+        });
+    }
+}
+
+/// Aligns diff lines into rows, pairing each delete with the most similar
+/// insert in its change group and leaving dissimilar lines unpaired.
+pub fn to_side_by_side_matched(lines: &[DiffLine]) -> Vec<SideBySideLine> {
+    let mut result = Vec::new();
+    let mut deletes: Vec<DiffLine> = Vec::new();
+    let mut inserts: Vec<DiffLine> = Vec::new();
+
+    for line in lines {
+        match line.tag {
+            ChangeTag::Delete => deletes.push(line.clone()),
+            ChangeTag::Insert => inserts.push(line.clone()),
+            ChangeTag::Equal => {
+                match_change_group(&deletes, &inserts, &mut result);
+                deletes.clear();
+                inserts.clear();
+                result.push(SideBySideLine {
+                    left: Some(line.clone()),
+                    right: Some(line.clone()),
+                });
+            }
+        }
+    }
+    match_change_group(&deletes, &inserts, &mut result);
+
+    result
+}
+
+enum AlignBlock {
+    Equal {
+        old_index: usize,
+        new_index: usize,
+        len: usize,
+    },
+    Change {
+        old: Range<usize>,
+        new: Range<usize>,
+    },
+}
+
+fn is_trivial_line(content: &str) -> bool {
+    content.trim().chars().count() <= 1
+}
+
+/// Aligns the whole file from patience-diff opcodes instead of streaming
+/// through `lines`. A single blank or brace-only line that splits two edits
+/// is folded into one block so the edit is paired row by row as a whole.
+pub fn to_side_by_side_grouped(lines: &[DiffLine]) -> Vec<SideBySideLine> {
+    let old: Vec<&DiffLine> = lines.iter().filter(|l| l.old_lineno.is_some()).collect();
+    let new: Vec<&DiffLine> = lines.iter().filter(|l| l.new_lineno.is_some()).collect();
+    let old_text: Vec<&str> = old.iter().map(|l| l.content.as_ref()).collect();
+    let new_text: Vec<&str> = new.iter().map(|l| l.content.as_ref()).collect();
+
+    let mut blocks: Vec<AlignBlock> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Patience, &old_text, &new_text) {
+        let block = match op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => AlignBlock::Equal {
+                old_index,
+                new_index,
+                len,
+            },
+            op => AlignBlock::Change {
+                old: op.old_range(),
+                new: op.new_range(),
+            },
+        };
+        let bridges_gap = matches!(block, AlignBlock::Change { .. })
+            && match blocks.as_slice() {
+                [.., AlignBlock::Change { .. }, AlignBlock::Equal {
+                    old_index, len: 1, ..
+                }] => is_trivial_line(old_text[*old_index]),
+                _ => false,
+            };
+        if bridges_gap {
+            blocks.pop();
+            if let (
+                Some(AlignBlock::Change { old, new }),
+                AlignBlock::Change {
+                    old: next_old,
+                    new: next_new,
+                },
+            ) = (blocks.last_mut(), &block)
+            {
+                old.end = next_old.end;
+                new.end = next_new.end;
+            }
+            continue;
+        }
+        blocks.push(block);
+    }
+
+    let side = |line: &DiffLine, tag: ChangeTag| DiffLine {
+        tag,
+        old_lineno: line.old_lineno.filter(|_| tag != ChangeTag::Insert),
+        new_lineno: line.new_lineno.filter(|_| tag != ChangeTag::Delete),
+        content: line.content.clone(),
+    };
+
+    let mut result = Vec::new();
+    for block in blocks {
+        match block {
+            AlignBlock::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                for i in 0..len {
+                    let line = DiffLine {
+                        tag: ChangeTag::Equal,
+                        old_lineno: old[old_index + i].old_lineno,
+                        new_lineno: new[new_index + i].new_lineno,
+                        content: old[old_index + i].content.clone(),
+                    };
+                    result.push(SideBySideLine {
+                        left: Some(line.clone()),
+                        right: Some(line),
+                    });
+                }
+            }
+            AlignBlock::Change {
+                old: old_range,
+                new: new_range,
+            } => {
+                for i in 0..old_range.len().max(new_range.len()) {
+                    let left = old[old_range.clone()].get(i);
+                    let right = new[new_range.clone()].get(i);
+                    result.push(SideBySideLine {
+                        left: left.map(|l| side(l, ChangeTag::Delete)),
+                        right: right.map(|l| side(l, ChangeTag::Insert)),
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_conflict_markers() {
+        let diff = FileDiff::from_contents(
+            "a",
+            "a",
+            "",
+            "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n========\n<<<<<<<<\n",
+        );
+        let markers: Vec<bool> = diff.lines.iter().map(|l| l.is_conflict_marker()).collect();
+        assert_eq!(markers, vec![true, false, true, false, true, false, false]);
+    }
+
+    #[test]
+    fn test_indent_columns() {
+        let diff = FileDiff::from_contents("a", "a", "", "x\n    y\n\tz\n  \tw\n\t  v\n");
+        let columns: Vec<usize> = diff.lines.iter().map(|l| l.indent_columns(4)).collect();
+        assert_eq!(columns, vec![0, 4, 4, 4, 6]);
+        assert_eq!(diff.lines[2].indent_columns(8), 8);
+    }
+
+    #[test]
+    fn test_whitespace_change_ranges() {
+        let marked = |old: &'static str, new: &'static str| {
+            let (old_ranges, new_ranges) = whitespace_change_ranges(old, new, 4);
+            let old_marked: Vec<&str> = old_ranges.into_iter().map(|r| &old[r]).collect();
+            let new_marked: Vec<&str> = new_ranges.into_iter().map(|r| &new[r]).collect();
+            (old_marked, new_marked)
+        };
+        assert_eq!(marked("    x", "\tx"), (vec!["    "], vec!["\t"]));
+        assert_eq!(marked("\tx", "\t    x"), (vec![], vec!["    "]));
+        assert_eq!(marked("  x = 1;", "  x = 1;  "), (vec![], vec!["  "]));
+        assert_eq!(marked("  a \t", "  b\t"), (vec![" "], vec![]));
+        assert_eq!(marked("  \tx", "    x"), (vec!["\t"], vec!["  "]));
+        assert_eq!(marked("  x", "  y"), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_paired_line_whitespace_changes() {
+        let diff = FileDiff::from_contents("a", "a", "k\n  x\ny\n", "k\n\tx\nz\n");
+        let tags: Vec<ChangeTag> = diff.lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![
+                ChangeTag::Equal,
+                ChangeTag::Delete,
+                ChangeTag::Delete,
+                ChangeTag::Insert,
+                ChangeTag::Insert
+            ]
+        );
+        assert_eq!(diff.paired_line(1), Some(3));
+        assert_eq!(diff.paired_line(4), Some(2));
+        assert_eq!(diff.paired_line(0), None);
+        let marked = |ix: usize| -> Vec<&str> {
+            diff.whitespace_changes(ix, 4)
+                .into_iter()
+                .map(|range| &diff.lines[ix].content[range])
+                .collect()
+        };
+        assert_eq!(marked(1), vec!["  "]);
+        assert_eq!(marked(3), vec!["\t"]);
+        assert!(marked(2).is_empty());
+    }
+
+    #[test]
+    fn test_line_reference() {
+        let diff = FileDiff::from_contents("old.rs", "new.rs", "a\nb\n", "a\nc\n");
+        let refs: Vec<String> = diff.lines.iter().map(|l| diff.line_reference(l)).collect();
+        assert_eq!(refs, vec!["new.rs:1", "old.rs:~2", "new.rs:2"]);
+    }
+
+    #[test]
+    fn test_strip_ansi_colors() {
+        let colored = "\x1b[1mdiff --git a/x b/x\x1b[m\n\x1b[32m+added\x1b[0;1m\n plain\n";
+        assert_eq!(
+            strip_ansi_colors(colored),
+            "diff --git a/x b/x\n+added\n plain\n"
+        );
+        assert!(matches!(strip_ansi_colors("plain\n"), Cow::Borrowed(_)));
+        assert_eq!(strip_ansi_colors("\x1b[2K kept"), "\x1b[2K kept");
+    }
+
+    #[test]
+    fn test_line_number_sides() {
+        let added = FileDiff::from_contents("a", "a", "", "x\ny\n");
+        assert_eq!(added.line_number_sides(), (false, true));
+        let deleted = FileDiff::from_contents("a", "a", "x\n", "");
+        assert_eq!(deleted.line_number_sides(), (true, false));
+        let modified = FileDiff::from_contents("a", "a", "x\n", "y\n");
+        assert_eq!(modified.line_number_sides(), (true, true));
+    }
+
+    #[test]
+    fn test_is_unchanged() {
+        assert!(FileDiff::from_contents("a", "b", "x\ny\n", "x\ny\n").is_unchanged());
+        assert!(FileDiff::from_contents("a", "b", "", "").is_unchanged());
+        assert!(!FileDiff::from_contents("a", "b", "x\n", "x\ny\n").is_unchanged());
+    }
+
+    #[test]
+    fn test_max_lineno() {
+        let mut diff = FileDiff::from_contents("a", "b", "x\ny\nz\n", "x\n");
+        assert_eq!(diff.max_lineno(), 3);
+        diff.line_offset = (10, 0);
+        diff.recompute(diff.options).unwrap();
+        assert_eq!(diff.max_lineno(), 13);
+        diff.set_lines(Vec::new());
+        assert_eq!(diff.max_lineno(), 0);
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        assert!(has_mixed_line_endings("a\r\nb\nc\r\n"));
+        assert!(!has_mixed_line_endings("a\r\nb\r\n"));
+        assert!(!has_mixed_line_endings("a\nb\n"));
+        assert!(!has_mixed_line_endings("a\r\nb"));
+        assert!(FileDiff::from_contents("a", "b", "x\n", "x\r\ny\n").mixed_line_endings);
+        assert!(!FileDiff::from_contents("a", "b", "x\r\ny\n", "x\n").mixed_line_endings);
+    }
+
+    #[test]
+    fn test_is_full_deletion() {
+        assert!(FileDiff::from_contents("a", "b", "x\ny\n", "").is_full_deletion());
+        assert!(!FileDiff::from_contents("a", "b", "x\ny\n", "x\n").is_full_deletion());
+        assert!(!FileDiff::from_contents("a", "b", "", "").is_full_deletion());
+    }
+
+    #[test]
+    fn test_blank_changes_keep_line_numbers() {
+        let diff = FileDiff::from_contents("a", "b", "x\n\ny\n", "x\ny\n  \nz\n");
+        let kept: Vec<(Option<usize>, Option<usize>, &str)> = diff
+            .lines
+            .iter()
+            .filter(|l| !l.is_blank_change())
+            .map(|l| (l.old_lineno, l.new_lineno, l.content.as_ref()))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                (Some(1), Some(1), "x"),
+                (Some(3), Some(2), "y"),
+                (None, Some(4), "z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hunks_merge_and_split_by_context() {
+        let old = "fn a() {\n    1\n    2\n    3\n    4\n    5\n    6\n}\n";
+        let new = "fn a() {\n    1\n    two\n    3\n    4\n    5\n    six\n}\n";
+        let diff = FileDiff::from_contents("a", "b", old, new);
+
+        let hunks = diff.hunks(1);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -2,3 +2,3 @@ fn a() {");
+        assert_eq!(hunks[1].header(), "@@ -6,3 +6,3 @@ fn a() {");
+
+        let hunks = diff.hunks(3);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].lines, 0..diff.lines.len());
+        assert_eq!(hunks[0].header(), "@@ -1,8 +1,8 @@");
+    }
+
+    #[test]
+    fn test_partial_patch_shifts_later_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\n";
+        let new = "a\nb1\nb2\nc\nd\ne\nf\n";
+        let diff = FileDiff::from_contents("x.txt", "x.txt", old, new);
+        assert_eq!(diff.hunks(0).len(), 2);
+        assert_eq!(
+            diff.partial_patch(0, &BTreeSet::from([1])),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -7,1 +6,0 @@ f\n-g\n"
+        );
+        assert_eq!(
+            diff.partial_patch(0, &BTreeSet::from([0, 1])),
+            "--- a/x.txt\n+++ b/x.txt\n@@ -2,1 +2,2 @@ a\n-b\n+b1\n+b2\n@@ -7,1 +7,0 @@ f\n-g\n"
+        );
+    }
+
+    #[test]
+    fn test_partial_patch_for_untracked_file() {
+        let mut diff = FileDiff::from_contents("new.txt", "new.txt", "", "one\ntwo\n");
+        diff.status = Some(FileStatus::Untracked);
+        assert_eq!(
+            diff.partial_patch(3, &BTreeSet::from([0])),
+            "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+one\n+two\n"
+        );
+    }
+
+    #[test]
+    fn test_hunk_pure_insertion_start() {
+        let diff = FileDiff::from_contents("a", "b", "x\ny\n", "x\nnew\ny\n");
+        let hunks = diff.hunks(0);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].header(), "@@ -1,0 +2,1 @@ x");
+    }
+
+    fn assert_each_line_once(sbs: &[SideBySideLine], diff: &FileDiff) {
+        let left: Vec<usize> = sbs
+            .iter()
+            .filter_map(|row| row.left.as_ref()?.old_lineno)
+            .collect();
+        let right: Vec<usize> = sbs
+            .iter()
+            .filter_map(|row| row.right.as_ref()?.new_lineno)
+            .collect();
+        let old_count = diff.lines.iter().filter(|l| l.old_lineno.is_some()).count();
+        let new_count = diff.lines.iter().filter(|l| l.new_lineno.is_some()).count();
+        assert_eq!(left, (1..=old_count).collect::<Vec<_>>());
+        assert_eq!(right, (1..=new_count).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_side_by_side_grouped_realigns_across_shared_lines() {
+        let diff = FileDiff::from_contents("a", "b", "a\n}\nb\n}\n", "A\n}\nB\n}\nC\n}\n");
+
+        let streaming = to_side_by_side(&diff.lines);
+        assert_each_line_once(&streaming, &diff);
+        assert!(contents(&streaming).contains(&(Some("b"), Some("C"))));
+
+        let grouped = to_side_by_side_grouped(&diff.lines);
+        assert_each_line_once(&grouped, &diff);
+        let rows = contents(&grouped);
+        assert!(rows.contains(&(Some("a"), Some("A"))));
+        assert!(rows.contains(&(Some("b"), Some("B"))));
+    }
+
+    #[test]
+    fn test_side_by_side_grouped_folds_trivial_equal_line() {
+        let diff = FileDiff::from_contents("a", "b", "x1\nx2\n\nx3\n", "y1\n\ny2\ny3\n");
+
+        let streaming = to_side_by_side(&diff.lines);
+        assert_each_line_once(&streaming, &diff);
+        assert_eq!(streaming.len(), 5);
+
+        let grouped = to_side_by_side_grouped(&diff.lines);
+        assert_each_line_once(&grouped, &diff);
+        assert_eq!(grouped.len(), 4);
+        assert!(grouped
+            .iter()
+            .all(|row| row.left.is_some() && row.right.is_some()));
+    }
+
+    #[test]
+    fn test_serialize_json() {
+        let mut diff = FileDiff::from_contents("a.txt", "a.txt", "one\ntwo\n", "one\n");
+        diff.status = Some(FileStatus::Modified);
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "old_path": "a.txt",
+                "new_path": "a.txt",
+                "lines": [
+                    {"tag": "equal", "old_lineno": 1, "new_lineno": 1, "content": "one"},
+                    {"tag": "del", "old_lineno": 2, "new_lineno": null, "content": "two"},
+                ],
+                "generated": false,
+                "status": "modified",
+                "staging": null,
+                "too_large": null,
+                "type_change": null,
+                "image": false,
+                "commit": null,
+                "mixed_line_endings": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_recompute_from_kept_contents() {
+        let old = "a\nb\nc\n";
+        let new = "a\nc\nb\n";
+        let summary = |diff: &FileDiff| -> Vec<(ChangeTag, Arc<str>)> {
+            diff.lines
+                .iter()
+                .map(|l| (l.tag, l.content.clone()))
+                .collect()
+        };
+        let patience = DiffOptions {
+            algorithm: Algorithm::Patience,
+            ..DiffOptions::default()
+        };
+        let mut diff = FileDiff::from_contents("x", "x", old, new);
+        diff.lines.clear();
+        diff.recompute(patience).unwrap();
+        assert_eq!(diff.options, patience);
+        assert_eq!(
+            summary(&diff),
+            summary(&FileDiff::from_contents_with("x", "x", old, new, patience))
+        );
+
+        let dropped = DiffOptions {
+            keep_contents: false,
+            ..DiffOptions::default()
+        };
+        let mut diff = FileDiff::from_contents_with("x", "x", old, new, dropped);
+        assert_eq!(diff.old_content, None);
+        assert!(diff.recompute(DiffOptions::default()).is_err());
+        assert!(diff.reversed().is_none());
+    }
+
+    #[test]
+    fn test_ignore_case_matches_case_only_changes() {
+        let old = "Hello\nworld\nlast";
+        let new = "hello\nWorld!\nLAST\n";
+        let options = DiffOptions {
+            ignore_case: true,
+            ..DiffOptions::default()
+        };
+        let diff = FileDiff::from_contents_with("x", "x", old, new, options);
+        let summary: Vec<(ChangeTag, &str)> = diff
+            .lines
+            .iter()
+            .map(|l| (l.tag, l.content.as_ref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeTag::Equal, "hello"),
+                (ChangeTag::Delete, "world"),
+                (ChangeTag::Delete, "last"),
+                (ChangeTag::Insert, "World!"),
+                (ChangeTag::Insert, "LAST"),
+            ]
+        );
+        assert_eq!(diff.lines[0].old_lineno, Some(1));
+        assert_eq!(diff.lines[4].new_lineno, Some(3));
+
+        let case_sensitive = FileDiff::from_contents("x", "x", old, new);
+        assert_eq!(case_sensitive.lines[0].tag, ChangeTag::Delete);
+    }
+
+    #[test]
+    fn test_semantic_ignores_key_order() {
+        let old = "{\"b\": 1, \"a\": 2}";
+        let new = "{\"a\": 2, \"b\": 3}";
+        let options = DiffOptions {
+            semantic: true,
+            ..DiffOptions::default()
+        };
+        let mut diff = FileDiff::from_contents_with("c.json", "c.json", old, new, options);
+        let changed: Vec<(ChangeTag, &str)> = diff
+            .lines
+            .iter()
+            .filter(|l| l.tag != ChangeTag::Equal)
+            .map(|l| (l.tag, l.content.as_ref()))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                (ChangeTag::Delete, "  \"b\": 1"),
+                (ChangeTag::Insert, "  \"b\": 3")
+            ]
+        );
+        assert_eq!(diff.new_content.as_deref(), Some(new));
+
+        diff.recompute(DiffOptions::default()).unwrap();
+        assert!(diff.lines.iter().all(|l| l.tag != ChangeTag::Equal));
+
+        let broken = FileDiff::from_contents_with("c.json", "c.json", old, "{", options);
+        assert_eq!(broken.lines.len(), 2);
+    }
+
+    #[test]
+    fn test_side_texts_round_trip() {
+        let old = "one\ntwo\nthree\nfour\n";
+        let new = "zero\none\nthree\n4\n";
+        let mut diff = FileDiff::from_contents("x", "x", old, new);
+        assert_eq!(diff.old_text().as_deref(), Some(old));
+        assert_eq!(diff.new_text().as_deref(), Some(new));
+
+        diff.line_offset = (10, 20);
+        diff.apply_line_offset();
+        assert_eq!(diff.new_text().as_deref(), Some(new));
+
+        diff.lines.remove(0);
+        assert_eq!(diff.new_text(), None);
+        assert_eq!(diff.old_text().as_deref(), Some(old));
+    }
+
+    #[test]
+    fn test_reversed_swaps_sides() {
+        let mut diff = FileDiff::from_contents("a.txt", "b.txt", "one\ntwo\n", "one\nthree\n");
+        diff.status = Some(FileStatus::Added);
+        let reversed = diff.reversed().unwrap();
+        assert_eq!(reversed.old_path.as_ref(), "b.txt");
+        assert_eq!(reversed.new_path.as_ref(), "a.txt");
+        assert_eq!(reversed.status, Some(FileStatus::Deleted));
+        let tags: Vec<_> = reversed
+            .lines
+            .iter()
+            .map(|l| (l.tag, l.content.as_ref()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                (ChangeTag::Equal, "one"),
+                (ChangeTag::Delete, "three"),
+                (ChangeTag::Insert, "two"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_file_ranges_keeps_original_line_numbers() {
+        let dir =
+            std::env::temp_dir().join(format!("gpui-diff-tool-ranges-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.txt");
+        let new = dir.join("new.txt");
+        fs::write(&old, "a\nb\nc\nd\n").unwrap();
+        fs::write(&new, "x\ny\nb\nC\nd\n").unwrap();
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        let diff = FileDiff::from_file_ranges(old, 2..=3, new, 3..=4).unwrap();
+        let numbers: Vec<_> = diff
+            .lines
+            .iter()
+            .map(|l| (l.old_lineno, l.new_lineno))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![(Some(2), Some(3)), (Some(3), None), (None, Some(4))]
+        );
+        let reversed = diff.reversed().unwrap();
+        assert_eq!(reversed.lines[0].old_lineno, Some(3));
+        assert_eq!(reversed.lines[0].new_lineno, Some(2));
+
+        let err = FileDiff::from_file_ranges(old, 3..=9, new, 1..=2)
+            .err()
+            .unwrap();
+        assert!(err.contains("out of bounds"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_file_path() {
+        let dir =
+            std::env::temp_dir().join(format!("gpui-diff-tool-resolve-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.txt");
+        fs::write(&file, "a\n").unwrap();
+
+        let dir_path = dir.to_str().unwrap();
+        let err = resolve_file_path(dir_path).err().unwrap();
+        assert_eq!(err, format!("{dir_path} is a directory, not a file"));
+        assert!(resolve_file_path(&format!("{dir_path}/missing.txt")).is_err());
+
+        let dotted = format!("{dir_path}/./a.txt");
+        assert_eq!(
+            resolve_file_path(&dotted).unwrap(),
+            fs::canonicalize(&file).unwrap()
+        );
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.txt");
+            std::os::unix::fs::symlink(&file, &link).unwrap();
+            let link = link.to_str().unwrap();
+            let diff = FileDiff::from_files(link, &dotted);
+            assert_eq!(diff.old_path.as_ref(), link);
+            assert!(diff.is_unchanged());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_files_with_limit_skips_large_files() {
+        let dir = std::env::temp_dir().join(format!("gpui-diff-tool-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.txt");
+        let new = dir.join("new.txt");
+        fs::write(&old, "a\n").unwrap();
+        fs::write(&new, "a\nbbbbbbbb\n").unwrap();
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        let skipped = FileDiff::from_files_with_limit(old, new, Some(4));
+        assert_eq!(skipped.too_large, Some(11));
+        assert!(skipped.lines.is_empty());
+        assert!(skipped.reversed().is_none());
+
+        let diffed = FileDiff::from_files_with_limit(old, new, Some(11));
+        assert_eq!(diffed.too_large, None);
+        assert_eq!(diffed.lines.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_image_files_are_not_line_diffed() {
+        assert!(is_image_path("assets/logo.PNG"));
+        assert!(is_image_path("icon.svg"));
+        assert!(!is_image_path("src/png.rs"));
+        assert!(!is_image_path("Makefile"));
+
+        let diff = FileDiff::from_files_with_limit("old/logo.png", "new/logo.png", None);
+        assert!(diff.image);
+        assert!(diff.lines.is_empty());
+    }
+
     #[test]
     fn test_side_by_side_equal_lines() {
         let lines = vec![
@@ -250,4 +1755,67 @@ mod tests {
         assert!(sbs[0].left.is_some() && sbs[0].right.is_none());
         assert!(sbs[1].left.is_some() && sbs[1].right.is_none());
     }
+
+    fn contents(sbs: &[SideBySideLine]) -> Vec<(Option<&str>, Option<&str>)> {
+        sbs.iter()
+            .map(|row| {
+                (
+                    row.left.as_ref().map(|l| l.content.as_ref()),
+                    row.right.as_ref().map(|l| l.content.as_ref()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_side_by_side_matched_pairs_similar_lines() {
+        let diff = FileDiff::from_contents(
+            "a",
+            "b",
+            "let x = 1;\nlet y = 2;\n",
+            "// added\nlet y = 3;\n",
+        );
+        let sbs = to_side_by_side_matched(&diff.lines);
+        assert_eq!(
+            contents(&sbs),
+            vec![
+                (Some("let x = 1;"), None),
+                (None, Some("// added")),
+                (Some("let y = 2;"), Some("let y = 3;")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_side_by_side_matched_reordered_lines() {
+        let diff = FileDiff::from_contents(
+            "a",
+            "b",
+            "start\nopen_file(path)\ncount += 1\nend\n",
+            "start\ncount += 2\nopen_file(path, mode)\nend\n",
+        );
+        let sbs = to_side_by_side_matched(&diff.lines);
+        // Pairs can't cross, so only one of the two similar pairs is kept;
+        // the other lines get rows of their own.
+        assert_eq!(
+            contents(&sbs),
+            vec![
+                (Some("start"), Some("start")),
+                (Some("open_file(path)"), None),
+                (Some("count += 1"), Some("count += 2")),
+                (None, Some("open_file(path, mode)")),
+                (Some("end"), Some("end")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_side_by_side_matched_dissimilar_lines_unpaired() {
+        let diff = FileDiff::from_contents("a", "b", "old\n", "new\n");
+        let sbs = to_side_by_side_matched(&diff.lines);
+        assert_eq!(
+            contents(&sbs),
+            vec![(Some("old"), None), (None, Some("new"))]
+        );
+    }
 }
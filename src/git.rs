@@ -1,102 +1,1646 @@
+use std::collections::HashMap;
 use std::fs;
+use std::io::ErrorKind;
 use std::process::Command;
 
-use crate::diff::FileDiff;
+use globset::GlobSet;
 
-pub fn git_toplevel() -> Result<String, String> {
-    let output = Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .output()
-        .map_err(|e| format!("Failed to run git: {e}"))?;
+use crate::diff::{FileDiff, FileMode, FileStatus, Staging};
 
-    if !output.status.success() {
+pub struct GitOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GitTarget {
+    WorkTree { staged: bool },
+    Split,
+    Stash(usize),
+    Range { old: String, new: String },
+    MergeBase { branch: String, staged: bool },
+    Base { rev: String, staged: bool },
+    RefPaths(Vec<(String, String)>),
+    Log(String),
+    Blob { old: String, new: String },
+}
+
+impl GitTarget {
+    pub fn label(&self) -> String {
+        match self {
+            GitTarget::WorkTree { staged: false } => "unstaged".to_string(),
+            GitTarget::WorkTree { staged: true } => "staged".to_string(),
+            GitTarget::Split => "staged + unstaged".to_string(),
+            GitTarget::Stash(index) => format!("stash@{{{index}}}"),
+            GitTarget::Range { old, new } => format!("{old}..{new}"),
+            GitTarget::MergeBase { branch, staged } => {
+                let suffix = if *staged { " (staged)" } else { "" };
+                format!("merge-base {branch}{suffix}")
+            }
+            GitTarget::Base { rev, staged } => {
+                let suffix = if *staged { " (staged)" } else { "" };
+                format!("vs {rev}{suffix}")
+            }
+            GitTarget::RefPaths(pairs) => match &pairs[..] {
+                [(old, new)] => format!("{old} vs {new}"),
+                _ => format!("{} ref:path pairs", pairs.len()),
+            },
+            GitTarget::Log(path) => format!("log {path}"),
+            GitTarget::Blob { old, new } => {
+                format!("blob {} vs {}", short_hash(old), short_hash(new))
+            }
+        }
+    }
+
+    /// What to say when [`git_target_files`] finds nothing to show.
+    pub fn empty_message(&self) -> String {
+        match self {
+            GitTarget::WorkTree { staged: false } => "No unstaged changes found".to_string(),
+            GitTarget::WorkTree { staged: true } => "No staged changes found".to_string(),
+            GitTarget::Split => "No staged or unstaged changes found".to_string(),
+            GitTarget::Stash(index) => format!("No changes found in stash@{{{index}}}"),
+            GitTarget::Range { old, new } => format!("No changes found between {old} and {new}"),
+            GitTarget::MergeBase { branch, .. } => {
+                format!("No changes found since the merge base with {branch}")
+            }
+            GitTarget::Base { rev, .. } => format!("No changes found since {rev}"),
+            GitTarget::RefPaths(_) | GitTarget::Log(_) | GitTarget::Blob { .. } => {
+                "No changes found".to_string()
+            }
+        }
+    }
+}
+
+/// Which untracked files diffs of the working tree list as added.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UntrackedFiles {
+    /// Tracked changes only.
+    Skip,
+    /// Untracked files that aren't ignored by `.gitignore` and friends.
+    #[default]
+    Unignored,
+    /// Ignored files too, e.g. build output.
+    All,
+}
+
+/// Where the new side of a [`GitTarget`]'s files can be blamed from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlameSource {
+    WorkTree,
+    Rev(String),
+}
+
+impl GitTarget {
+    /// Whether this target diffs the working tree, so lists untracked
+    /// files as added.
+    pub fn lists_untracked(&self) -> bool {
+        matches!(
+            self,
+            GitTarget::WorkTree { staged: false }
+                | GitTarget::Split
+                | GitTarget::MergeBase { staged: false, .. }
+                | GitTarget::Base { staged: false, .. }
+        )
+    }
+
+    /// Where to run `git blame` for the new side of this target's files.
+    /// `None` when that side is the index or varies per file.
+    pub fn blame_source(&self) -> Option<BlameSource> {
+        match self {
+            GitTarget::WorkTree { staged: false }
+            | GitTarget::MergeBase { staged: false, .. }
+            | GitTarget::Base { staged: false, .. } => Some(BlameSource::WorkTree),
+            GitTarget::Stash(index) => Some(BlameSource::Rev(format!("stash@{{{index}}}"))),
+            GitTarget::Range { new, .. } => Some(BlameSource::Rev(new.clone())),
+            _ => None,
+        }
+    }
+}
+
+pub trait GitRunner {
+    fn run(&self, args: &[&str], cwd: Option<&str>) -> Result<GitOutput, String>;
+    fn read_file(&self, path: &str) -> Option<String>;
+    fn read_link(&self, path: &str) -> Option<String>;
+}
+
+pub struct SystemGit;
+
+impl GitRunner for SystemGit {
+    fn run(&self, args: &[&str], cwd: Option<&str>) -> Result<GitOutput, String> {
+        let mut command = Command::new("git");
+        command.args(args);
+        if let Some(cwd) = cwd {
+            command.current_dir(cwd);
+        }
+        let output = command.output().map_err(|e| match e.kind() {
+            ErrorKind::NotFound => "git was not found on PATH".to_string(),
+            _ => format!("Failed to run git: {e}"),
+        })?;
+        Ok(GitOutput {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    fn read_file(&self, path: &str) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    fn read_link(&self, path: &str) -> Option<String> {
+        let target = fs::read_link(path).ok()?;
+        Some(target.to_string_lossy().to_string())
+    }
+}
+
+fn git_checked(runner: &dyn GitRunner, args: &[&str], cwd: &str) -> Result<String, String> {
+    let output = runner.run(args, Some(cwd))?;
+    if !output.success {
+        let subcommand = args.first().copied().unwrap_or_default();
+        return Err(format!("git {subcommand} failed: {}", output.stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Checks that `rev` (a branch, tag, SHA or any other revision) names a
+/// commit, so a typo is reported as such rather than as a failed diff.
+fn verify_rev(runner: &dyn GitRunner, toplevel: &str, rev: &str) -> Result<(), String> {
+    let spec = format!("{rev}^{{commit}}");
+    let output = runner.run(&["rev-parse", "--verify", "--quiet", &spec], Some(toplevel))?;
+    if output.success {
+        return Ok(());
+    }
+    // With --quiet, git only prints to stderr for failures other than an
+    // unresolvable revision.
+    match output.stderr.trim() {
+        "" => Err(format!("Unknown ref '{rev}'")),
+        stderr => Err(format!("git rev-parse failed: {stderr}")),
+    }
+}
+
+pub fn git_toplevel(runner: &dyn GitRunner) -> Result<String, String> {
+    let output = runner.run(&["rev-parse", "--show-toplevel"], None)?;
+
+    if !output.success {
         return Err("Not a git repository".to_string());
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    Ok(output.stdout.trim().to_string())
 }
 
-pub fn git_diff_files(staged: bool) -> Result<Vec<FileDiff>, String> {
-    let toplevel = git_toplevel()?;
+/// How many paths one `git check-ignore` run is given, to stay well below
+/// the command line length limit.
+const CHECK_IGNORE_BATCH: usize = 500;
 
-    let mut args = vec!["diff", "--name-only"];
-    if staged {
-        args.push("--cached");
+/// Whether git ignores every one of `paths` (relative to `toplevel`), such
+/// as build output under an ignored `target/`. Tracked files never count
+/// as ignored.
+pub fn git_all_ignored(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    paths: &[&str],
+) -> Result<bool, String> {
+    for batch in paths.chunks(CHECK_IGNORE_BATCH) {
+        let mut args = vec!["check-ignore", "--"];
+        args.extend(batch);
+        let output = runner.run(&args, Some(toplevel))?;
+        // check-ignore exits with 1, saying nothing, when no path is ignored.
+        if !output.success && !output.stderr.trim().is_empty() {
+            return Err(format!("git check-ignore failed: {}", output.stderr.trim()));
+        }
+        // Each ignored path is printed on one line, quoted if it contains a
+        // newline, so counting lines is enough and needs no unquoting.
+        if output.stdout.lines().count() < batch.len() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Loads the diffs for `target`. An empty result is not an error; callers
+/// decide whether to report [`GitTarget::empty_message`].
+pub fn git_target_files(
+    runner: &dyn GitRunner,
+    target: &GitTarget,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    match target {
+        GitTarget::WorkTree { staged } => {
+            git_diff_files(runner, *staged, exclude, untracked, progress)
+        }
+        GitTarget::Split => git_split_files(runner, exclude, untracked, progress),
+        GitTarget::Stash(index) => git_stash_files(runner, *index, exclude, progress),
+        GitTarget::Range { old, new } => git_range_files(runner, old, new, exclude, progress),
+        GitTarget::MergeBase { branch, staged } => {
+            git_merge_base_files(runner, branch, *staged, exclude, untracked, progress)
+        }
+        GitTarget::Base { rev, staged } => {
+            git_base_files(runner, rev, *staged, exclude, untracked, progress)
+        }
+        GitTarget::RefPaths(pairs) => git_ref_path_files(runner, pairs, progress),
+        GitTarget::Log(path) => git_log_files(runner, path, progress),
+        GitTarget::Blob { old, new } => git_blob_files(runner, old, new),
+    }
+}
+
+fn status_from_code(code: char) -> FileStatus {
+    match code {
+        'A' => FileStatus::Added,
+        'D' => FileStatus::Deleted,
+        '?' => FileStatus::Untracked,
+        _ => FileStatus::Modified,
+    }
+}
+
+fn git_status_codes(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    staged: bool,
+) -> Result<HashMap<String, FileStatus>, String> {
+    // With -z paths are never quoted, and a rename or copy is followed by
+    // its original path as a separate entry.
+    let output = runner.run(&["status", "--porcelain", "-z"], Some(toplevel))?;
+    if !output.success {
+        return Ok(HashMap::new());
+    }
+    let mut codes = HashMap::new();
+    let mut entries = output.stdout.split('\0');
+    while let Some(entry) = entries.next() {
+        let (Some(index), Some(worktree), Some(path)) =
+            (entry.chars().next(), entry.chars().nth(1), entry.get(3..))
+        else {
+            continue;
+        };
+        if matches!(index, 'R' | 'C') {
+            entries.next();
+        }
+        let code = if staged { index } else { worktree };
+        codes.insert(path.to_string(), status_from_code(code));
+    }
+    Ok(codes)
+}
+
+/// Splits the output of a command run with `-z` into its NUL-terminated fields.
+/// Paths in it are never quoted, unlike in the line-based output.
+fn nul_fields(output: &str) -> impl Iterator<Item = &str> {
+    output.split('\0').filter(|field| !field.is_empty())
+}
+
+/// Reads `git diff --raw -z` or `--name-status -z` output into each entry's
+/// status field and paths: the old and new path for renames and copies, the
+/// one path otherwise.
+fn diff_entries(output: &str) -> Vec<(&str, Vec<&str>)> {
+    let mut fields = nul_fields(output);
+    let mut entries = Vec::new();
+    while let Some(meta) = fields.next() {
+        let status = meta.rsplit(' ').next().unwrap_or(meta);
+        let paths = if status.starts_with(['R', 'C']) { 2 } else { 1 };
+        entries.push((meta, fields.by_ref().take(paths).collect()));
     }
+    entries
+}
 
-    let output = Command::new("git")
-        .args(&args)
-        .current_dir(&toplevel)
-        .output()
-        .map_err(|e| format!("Failed to run git diff: {e}"))?;
+fn type_changes(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    revs: &[&str],
+) -> Result<HashMap<String, (FileMode, FileMode)>, String> {
+    let mut args = vec!["diff", "--raw", "-z"];
+    args.extend(revs);
+    let output = runner.run(&args, Some(toplevel))?;
+    if !output.success {
+        return Ok(HashMap::new());
+    }
+    Ok(diff_entries(&output.stdout)
+        .into_iter()
+        .filter_map(|(meta, paths)| {
+            let path = paths.last()?;
+            let fields: Vec<&str> = meta.trim_start_matches(':').split(' ').collect();
+            let [old_mode, new_mode, _, _, status] = fields[..] else {
+                return None;
+            };
+            if status != "T" {
+                return None;
+            }
+            let old_mode = FileMode::from_octal(old_mode)?;
+            let new_mode = FileMode::from_octal(new_mode)?;
+            Some((path.to_string(), (old_mode, new_mode)))
+        })
+        .collect())
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("git diff failed: {stderr}"));
+/// Maps the new path of each file git detects as renamed to its old path.
+fn renamed_paths(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    revs: &[&str],
+) -> Result<HashMap<String, String>, String> {
+    let mut args = vec!["diff", "--name-status", "-M", "-z"];
+    args.extend(revs);
+    let output = runner.run(&args, Some(toplevel))?;
+    if !output.success {
+        return Ok(HashMap::new());
     }
+    Ok(diff_entries(&output.stdout)
+        .into_iter()
+        .filter_map(|(status, paths)| match paths[..] {
+            [old, new] if status.starts_with('R') => Some((new.to_string(), old.to_string())),
+            _ => None,
+        })
+        .collect())
+}
 
-    let file_list = String::from_utf8_lossy(&output.stdout);
-    let files: Vec<&str> = file_list.lines().filter(|l| !l.is_empty()).collect();
+pub fn git_diff_files(
+    runner: &dyn GitRunner,
+    staged: bool,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    work_tree_files(
+        runner, &toplevel, staged, None, exclude, untracked, progress,
+    )
+}
+
+pub fn git_split_files(
+    runner: &dyn GitRunner,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    let mut diffs = Vec::new();
+    for (staged, staging) in [(true, Staging::Staged), (false, Staging::Unstaged)] {
+        let section = work_tree_files(
+            runner, &toplevel, staged, None, exclude, untracked, progress,
+        )?;
+        diffs.extend(section.into_iter().map(|mut diff| {
+            diff.staging = Some(staging);
+            diff
+        }));
+    }
+    Ok(diffs)
+}
+
+pub fn git_merge_base_files(
+    runner: &dyn GitRunner,
+    branch: &str,
+    staged: bool,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    verify_rev(runner, &toplevel, branch)?;
+    let output = runner.run(&["merge-base", "HEAD", branch], Some(&toplevel))?;
+    let base = output.stdout.trim();
+    if !output.success || base.is_empty() {
+        return Err(format!("No merge base found between HEAD and {branch}"));
+    }
+    work_tree_files(
+        runner,
+        &toplevel,
+        staged,
+        Some(base),
+        exclude,
+        untracked,
+        progress,
+    )
+}
+
+pub fn git_base_files(
+    runner: &dyn GitRunner,
+    rev: &str,
+    staged: bool,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    verify_rev(runner, &toplevel, rev)?;
+    work_tree_files(
+        runner,
+        &toplevel,
+        staged,
+        Some(rev),
+        exclude,
+        untracked,
+        progress,
+    )
+}
+
+fn work_tree_files(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    staged: bool,
+    base: Option<&str>,
+    exclude: &GlobSet,
+    untracked: UntrackedFiles,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let mut revs = Vec::new();
+    if staged {
+        revs.push("--cached");
+    }
+    revs.extend(base);
+    let mut args = vec!["diff", "--name-only", "-z"];
+    args.extend(&revs);
+
+    let file_list = git_checked(runner, &args, toplevel)?;
+    let files: Vec<&str> = nul_fields(&file_list)
+        .filter(|f| !exclude.is_match(f))
+        .collect();
+
+    let untracked_list = if staged || untracked == UntrackedFiles::Skip {
+        String::new()
+    } else {
+        let mut args = vec!["ls-files", "--others", "-z"];
+        if untracked == UntrackedFiles::Unignored {
+            args.push("--exclude-standard");
+        }
+        let untracked_output = runner.run(&args, Some(toplevel))?;
+        if untracked_output.success {
+            untracked_output.stdout
+        } else {
+            String::new()
+        }
+    };
+    let untracked: Vec<&str> = nul_fields(&untracked_list)
+        .filter(|f| !exclude.is_match(f))
+        .collect();
+
+    let statuses = match base {
+        Some(base) => base_status_codes(runner, toplevel, staged, base)?,
+        None => git_status_codes(runner, toplevel, staged)?,
+    };
+    let type_changes = type_changes(runner, toplevel, &revs)?;
+    let renames = renamed_paths(runner, toplevel, &revs)?;
+
+    // Staged changes compare HEAD with the index, unstaged ones the index with the
+    // work tree; an empty rev in `<rev>:<file>` names the index.
+    let old_rev = match base {
+        Some(base) => base,
+        None if staged => "HEAD",
+        None => "",
+    };
+
+    let total = files.len() + untracked.len();
+    progress(0, total);
 
     let mut diffs = Vec::new();
     for file in files {
-        let mut show_args = vec!["show".to_string()];
-        let ref_prefix = if staged { "" } else { "" };
-        show_args.push(format!(":{ref_prefix}{file}"));
-
-        let old_output = Command::new("git")
-            .args(&show_args)
-            .current_dir(&toplevel)
-            .output()
-            .map_err(|e| format!("Failed to get index version of {file}: {e}"))?;
-
-        let old_content = if old_output.status.success() {
-            String::from_utf8_lossy(&old_output.stdout).to_string()
+        let old_file = renames.get(file).map_or(file, String::as_str);
+        let old_spec = format!("{old_rev}:{old_file}");
+        let old_output = runner.run(&["show", &old_spec], Some(toplevel))?;
+        let old_content = if old_output.success {
+            old_output.stdout
         } else {
             String::new()
         };
 
         let file_path = format!("{toplevel}/{file}");
+        let type_change = type_changes.get(file).copied();
         let new_content = if staged {
-            let staged_output = Command::new("git")
-                .args(["show", &format!(":{file}")])
-                .current_dir(&toplevel)
-                .output()
-                .map_err(|e| format!("Failed to get staged version of {file}: {e}"))?;
-            String::from_utf8_lossy(&staged_output.stdout).to_string()
+            let staged_spec = format!(":{file}");
+            runner.run(&["show", &staged_spec], Some(toplevel))?.stdout
+        } else if type_change.is_some_and(|(_, new)| new == FileMode::Symlink) {
+            runner.read_link(&file_path).unwrap_or_default()
         } else {
-            fs::read_to_string(&file_path).unwrap_or_default()
+            runner.read_file(&file_path).unwrap_or_default()
+        };
+
+        let mut diff = match type_change {
+            Some(modes) => FileDiff::from_type_change(file, modes, &old_content, &new_content),
+            None => FileDiff::from_contents(old_file, file, &old_content, &new_content),
         };
+        diff.status = Some(statuses.get(file).copied().unwrap_or(FileStatus::Modified));
+        diffs.push(diff);
+        progress(diffs.len(), total);
+    }
+
+    for file in untracked {
+        let file_path = format!("{toplevel}/{file}");
+        let new_content = runner.read_file(&file_path).unwrap_or_default();
+        let mut diff = FileDiff::from_contents(file, file, "", &new_content);
+        diff.status = Some(FileStatus::Untracked);
+        diffs.push(diff);
+        progress(diffs.len(), total);
+    }
+
+    Ok(diffs)
+}
+
+fn base_status_codes(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    staged: bool,
+    base: &str,
+) -> Result<HashMap<String, FileStatus>, String> {
+    let mut args = vec!["diff", "--name-status", "-z"];
+    if staged {
+        args.push("--cached");
+    }
+    args.push(base);
+    let output = git_checked(runner, &args, toplevel)?;
+    Ok(diff_entries(&output)
+        .into_iter()
+        .filter_map(|(code, paths)| {
+            let path = paths.last()?;
+            let code = code.chars().next()?;
+            Some((path.to_string(), status_from_code(code)))
+        })
+        .collect())
+}
 
+fn show_or_empty(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    rev: &str,
+    file: &str,
+) -> Result<String, String> {
+    let spec = format!("{rev}:{file}");
+    let output = runner.run(&["show", &spec], Some(toplevel))?;
+    Ok(if output.success {
+        output.stdout
+    } else {
+        String::new()
+    })
+}
+
+fn diff_between_revs(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    old_rev: &str,
+    new_rev: &str,
+    exclude: &GlobSet,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let file_list = git_checked(
+        runner,
+        &["diff", "--name-only", "-z", old_rev, new_rev],
+        toplevel,
+    )?;
+    let files: Vec<&str> = nul_fields(&file_list)
+        .filter(|f| !exclude.is_match(f))
+        .collect();
+
+    let type_changes = type_changes(runner, toplevel, &[old_rev, new_rev])?;
+    let renames = renamed_paths(runner, toplevel, &[old_rev, new_rev])?;
+
+    progress(0, files.len());
+    let mut diffs = Vec::new();
+    for file in &files {
+        let old_file = renames.get(*file).map_or(*file, String::as_str);
+        let old_content = show_or_empty(runner, toplevel, old_rev, old_file)?;
+        let new_content = show_or_empty(runner, toplevel, new_rev, file)?;
+        diffs.push(match type_changes.get(*file) {
+            Some(&modes) => FileDiff::from_type_change(file, modes, &old_content, &new_content),
+            None => FileDiff::from_contents(old_file, file, &old_content, &new_content),
+        });
+        progress(diffs.len(), files.len());
+    }
+    Ok(diffs)
+}
+
+pub fn git_stash_files(
+    runner: &dyn GitRunner,
+    index: usize,
+    exclude: &GlobSet,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+
+    let stash_list = git_checked(runner, &["stash", "list"], &toplevel)?;
+    let count = stash_list.lines().filter(|l| !l.is_empty()).count();
+    if count == 0 {
+        return Err("No stash entries found".to_string());
+    }
+    if index >= count {
+        return Err(format!(
+            "stash@{{{index}}} does not exist (there are {count} stash entries)"
+        ));
+    }
+
+    let stash = format!("stash@{{{index}}}");
+    diff_between_revs(
+        runner,
+        &toplevel,
+        &format!("{stash}^1"),
+        &stash,
+        exclude,
+        progress,
+    )
+}
+
+pub fn git_range_files(
+    runner: &dyn GitRunner,
+    old_rev: &str,
+    new_rev: &str,
+    exclude: &GlobSet,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    verify_rev(runner, &toplevel, old_rev)?;
+    verify_rev(runner, &toplevel, new_rev)?;
+    diff_between_revs(runner, &toplevel, old_rev, new_rev, exclude, progress)
+}
+
+pub fn git_show(runner: &dyn GitRunner, toplevel: &str, spec: &str) -> Result<String, String> {
+    git_checked(runner, &["show", spec], toplevel)
+}
+
+pub fn git_ref_path_files(
+    runner: &dyn GitRunner,
+    pairs: &[(String, String)],
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+
+    progress(0, pairs.len());
+    let mut diffs = Vec::new();
+    for (old, new) in pairs {
+        let old_content = git_show(runner, &toplevel, old)?;
+        let new_content = git_show(runner, &toplevel, new)?;
         diffs.push(FileDiff::from_contents(
-            file,
-            file,
+            old,
+            new,
             &old_content,
             &new_content,
         ));
+        progress(diffs.len(), pairs.len());
+    }
+    Ok(diffs)
+}
+
+/// Shortens a full object hash to git's usual 7 characters; other object
+/// names are kept as given.
+fn short_hash(object: &str) -> &str {
+    if object.len() > 7 && object.chars().all(|c| c.is_ascii_hexdigit()) {
+        &object[..7]
+    } else {
+        object
     }
+}
 
-    if !staged {
-        let untracked_output = Command::new("git")
-            .args(["ls-files", "--others", "--exclude-standard"])
-            .current_dir(&toplevel)
-            .output()
-            .map_err(|e| format!("Failed to list untracked files: {e}"))?;
+/// Reads a blob object's content by hash (or any name `git cat-file` accepts).
+pub fn git_blob(runner: &dyn GitRunner, toplevel: &str, object: &str) -> Result<String, String> {
+    git_checked(runner, &["cat-file", "blob", object], toplevel)
+}
 
-        if untracked_output.status.success() {
-            let untracked_list = String::from_utf8_lossy(&untracked_output.stdout);
-            for file in untracked_list.lines().filter(|l| !l.is_empty()) {
-                let file_path = format!("{toplevel}/{file}");
-                let new_content = fs::read_to_string(&file_path).unwrap_or_default();
-                diffs.push(FileDiff::from_contents(file, file, "", &new_content));
-            }
+/// Diffs two blob objects directly, with the sides labelled by short hash.
+pub fn git_blob_files(
+    runner: &dyn GitRunner,
+    old: &str,
+    new: &str,
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    let old_content = git_blob(runner, &toplevel, old)?;
+    let new_content = git_blob(runner, &toplevel, new)?;
+    Ok(vec![FileDiff::from_contents(
+        short_hash(old),
+        short_hash(new),
+        &old_content,
+        &new_content,
+    )])
+}
+
+/// The author time, in Unix seconds, of the commit that last changed each
+/// line of `path`, in line order. Lines not committed yet are `None`.
+pub fn git_blame_times(
+    runner: &dyn GitRunner,
+    toplevel: &str,
+    source: &BlameSource,
+    path: &str,
+) -> Result<Vec<Option<i64>>, String> {
+    let mut args = vec!["blame", "--line-porcelain"];
+    if let BlameSource::Rev(rev) = source {
+        args.push(rev);
+    }
+    args.extend(["--", path]);
+    let porcelain = git_checked(runner, &args, toplevel)?;
+    Ok(parse_blame_times(&porcelain))
+}
+
+/// Reads `git blame --line-porcelain` output, where every line is a
+/// `<hash> <orig> <final>` header, `key value` fields and the line's text
+/// prefixed with a tab.
+fn parse_blame_times(porcelain: &str) -> Vec<Option<i64>> {
+    let mut times = Vec::new();
+    let mut header = true;
+    let mut uncommitted = false;
+    let mut time = None;
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            times.push(time.filter(|_| !uncommitted));
+            header = true;
+        } else if header {
+            let hash = line.split(' ').next().unwrap_or_default();
+            uncommitted = hash.bytes().all(|b| b == b'0');
+            time = None;
+            header = false;
+        } else if let Some(value) = line.strip_prefix("author-time ") {
+            time = value.parse().ok();
         }
     }
+    times
+}
+
+struct LogEntry {
+    hash: String,
+    subject: String,
+    path: String,
+}
+
+// Parses `git log -z --name-only --format=%x1e%h%x09%s` output, newest first:
+// a NUL ends the header and each path, and a newline precedes the paths.
+// Commits that list no path (merges) keep the path of the newer commit.
+fn parse_file_log(output: &str, path: &str) -> Vec<LogEntry> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for record in output.split('\x1e').filter(|r| !r.trim().is_empty()) {
+        let (header, names) = record.split_once('\0').unwrap_or((record, ""));
+        let Some((hash, subject)) = header.split_once('\t') else {
+            continue;
+        };
+        let file = nul_fields(names.trim_start_matches('\n')).last();
+        let path = file
+            .or(entries.last().map(|e| e.path.as_str()))
+            .unwrap_or(path);
+        entries.push(LogEntry {
+            hash: hash.to_string(),
+            subject: subject.to_string(),
+            path: path.to_string(),
+        });
+    }
+    entries
+}
 
-    if diffs.is_empty() {
-        let kind = if staged { "staged" } else { "unstaged" };
-        return Err(format!("No {kind} changes found"));
+pub fn git_log_files(
+    runner: &dyn GitRunner,
+    path: &str,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<Vec<FileDiff>, String> {
+    let toplevel = git_toplevel(runner)?;
+    let output = runner.run(
+        &[
+            "log",
+            "--follow",
+            "-z",
+            "--name-only",
+            "--format=%x1e%h%x09%s",
+            "--",
+            path,
+        ],
+        None,
+    )?;
+    if !output.success {
+        return Err(format!("git log failed: {}", output.stderr.trim()));
+    }
+    let entries = parse_file_log(&output.stdout, path);
+    if entries.is_empty() {
+        return Err(format!("No commits found for {path}"));
     }
 
+    progress(0, entries.len());
+    let mut diffs = Vec::new();
+    for (ix, entry) in entries.iter().enumerate() {
+        let new_spec = format!("{}:{}", entry.hash, entry.path);
+        let new_content = show_or_empty(runner, &toplevel, &entry.hash, &entry.path)?;
+        let (old_spec, old_content, status) = match entries.get(ix + 1) {
+            Some(parent) => (
+                format!("{}:{}", parent.hash, parent.path),
+                show_or_empty(runner, &toplevel, &parent.hash, &parent.path)?,
+                FileStatus::Modified,
+            ),
+            None => (new_spec.clone(), String::new(), FileStatus::Added),
+        };
+        let mut diff = FileDiff::from_contents(&old_spec, &new_spec, &old_content, &new_content);
+        diff.status = Some(status);
+        diff.commit = Some(format!("{} {}", entry.hash, entry.subject).into());
+        diffs.push(diff);
+        progress(diffs.len(), entries.len());
+    }
     Ok(diffs)
 }
+
+#[cfg(test)]
+pub mod mock {
+    use std::collections::HashMap;
+
+    use super::{GitOutput, GitRunner};
+
+    #[derive(Default)]
+    pub struct MockGit {
+        commands: HashMap<String, (bool, String)>,
+        files: HashMap<String, String>,
+        links: HashMap<String, String>,
+    }
+
+    impl MockGit {
+        pub fn new(toplevel: &str) -> Self {
+            Self::default().with_output("rev-parse --show-toplevel", &format!("{toplevel}\n"))
+        }
+
+        pub fn with_output(mut self, args: &str, stdout: &str) -> Self {
+            self.commands
+                .insert(args.to_string(), (true, stdout.to_string()));
+            self
+        }
+
+        pub fn with_failure(mut self, args: &str, stderr: &str) -> Self {
+            self.commands
+                .insert(args.to_string(), (false, stderr.to_string()));
+            self
+        }
+
+        pub fn with_file(mut self, path: &str, content: &str) -> Self {
+            self.files.insert(path.to_string(), content.to_string());
+            self
+        }
+
+        pub fn with_link(mut self, path: &str, target: &str) -> Self {
+            self.links.insert(path.to_string(), target.to_string());
+            self
+        }
+    }
+
+    impl GitRunner for MockGit {
+        fn run(&self, args: &[&str], _cwd: Option<&str>) -> Result<GitOutput, String> {
+            let key = args.join(" ");
+            Ok(match self.commands.get(&key) {
+                Some((true, stdout)) => GitOutput {
+                    success: true,
+                    stdout: stdout.clone(),
+                    stderr: String::new(),
+                },
+                Some((false, stderr)) => GitOutput {
+                    success: false,
+                    stdout: String::new(),
+                    stderr: stderr.clone(),
+                },
+                None => GitOutput {
+                    success: false,
+                    stdout: String::new(),
+                    stderr: format!("fatal: unexpected command: git {key}"),
+                },
+            })
+        }
+
+        fn read_file(&self, path: &str) -> Option<String> {
+            self.files.get(path).cloned()
+        }
+
+        fn read_link(&self, path: &str) -> Option<String> {
+            self.links.get(path).cloned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use similar::ChangeTag;
+
+    use super::mock::MockGit;
+    use super::*;
+
+    #[test]
+    fn test_toplevel_outside_repo() {
+        let git = MockGit::default().with_failure("rev-parse --show-toplevel", "fatal");
+        assert_eq!(git_toplevel(&git).unwrap_err(), "Not a git repository");
+    }
+
+    #[test]
+    fn test_all_ignored() {
+        let git = MockGit::new("/repo")
+            .with_output(
+                "check-ignore -- target/debug/app target/\"q\".o",
+                "target/debug/app\n\"target/\\\"q\\\".o\"\n",
+            )
+            .with_output(
+                "check-ignore -- target/debug/app src/main.rs",
+                "target/debug/app\n",
+            )
+            .with_failure("check-ignore -- src/main.rs", "")
+            .with_failure(
+                "check-ignore -- ../outside",
+                "fatal: ../outside is outside repository",
+            );
+        let all_ignored = |paths: &[&str]| git_all_ignored(&git, "/repo", paths);
+        assert_eq!(
+            all_ignored(&["target/debug/app", "target/\"q\".o"]),
+            Ok(true)
+        );
+        assert_eq!(all_ignored(&["target/debug/app", "src/main.rs"]), Ok(false));
+        assert_eq!(all_ignored(&["src/main.rs"]), Ok(false));
+        assert_eq!(all_ignored(&[]), Ok(true));
+        assert_eq!(
+            all_ignored(&["../outside"]).unwrap_err(),
+            "git check-ignore failed: fatal: ../outside is outside repository"
+        );
+    }
+
+    #[test]
+    fn test_diff_failure_reports_stderr() {
+        let git = MockGit::new("/repo").with_failure("diff --name-only -z", "fatal: bad revision");
+        let err = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "git diff failed: fatal: bad revision");
+    }
+
+    #[test]
+    fn test_exclude_skips_matching_files() {
+        let mut exclude = globset::GlobSetBuilder::new();
+        exclude.add(globset::Glob::new("*.lock").unwrap());
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "Cargo.lock\0src/a.rs\0")
+            .with_output("show :src/a.rs", "x\n")
+            .with_output("ls-files --others -z --exclude-standard", "sub/yarn.lock\0")
+            .with_file("/repo/src/a.rs", "y\n");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &exclude.build().unwrap(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].new_path.as_ref(), "src/a.rs");
+    }
+
+    #[test]
+    fn test_progress_counts_tracked_and_untracked() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "a.txt\0")
+            .with_output("show :a.txt", "x\n")
+            .with_output("ls-files --others -z --exclude-standard", "b.txt\0")
+            .with_file("/repo/a.txt", "y\n")
+            .with_file("/repo/b.txt", "z\n");
+        let mut reports = Vec::new();
+        git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |done, total| reports.push((done, total)),
+        )
+        .unwrap();
+        assert_eq!(reports, vec![(0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_untracked_files_setting() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "a.txt\0")
+            .with_output("show :a.txt", "x\n")
+            .with_output("ls-files --others -z --exclude-standard", "b.txt\0")
+            .with_output("ls-files --others -z", "b.txt\0target/out.o\0")
+            .with_file("/repo/a.txt", "y\n")
+            .with_file("/repo/b.txt", "z\n")
+            .with_file("/repo/target/out.o", "bin\n");
+        let paths = |untracked| {
+            git_diff_files(&git, false, &GlobSet::empty(), untracked, &mut |_, _| {})
+                .unwrap()
+                .iter()
+                .map(|diff| diff.new_path.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(UntrackedFiles::Skip), ["a.txt"]);
+        assert_eq!(paths(UntrackedFiles::Unignored), ["a.txt", "b.txt"]);
+        assert_eq!(
+            paths(UntrackedFiles::All),
+            ["a.txt", "b.txt", "target/out.o"]
+        );
+        assert!(GitTarget::Split.lists_untracked());
+        assert!(!GitTarget::WorkTree { staged: true }.lists_untracked());
+    }
+
+    #[test]
+    fn test_stash_diffs_against_parent() {
+        let git = MockGit::new("/repo")
+            .with_output("stash list", "stash@{0}: WIP on main\n")
+            .with_output("diff --name-only -z stash@{0}^1 stash@{0}", "a.txt\0")
+            .with_output("show stash@{0}^1:a.txt", "old\n")
+            .with_output("show stash@{0}:a.txt", "new\n");
+        let diffs = git_stash_files(&git, 0, &GlobSet::empty(), &mut |_, _| {}).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].lines.len(), 2);
+    }
+
+    #[test]
+    fn test_stash_errors() {
+        let empty = MockGit::new("/repo").with_output("stash list", "");
+        let err = git_stash_files(&empty, 0, &GlobSet::empty(), &mut |_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err, "No stash entries found");
+
+        let one = MockGit::new("/repo").with_output("stash list", "stash@{0}: WIP\n");
+        let err = git_stash_files(&one, 3, &GlobSet::empty(), &mut |_, _| {})
+            .err()
+            .unwrap();
+        assert!(err.contains("stash@{3} does not exist"));
+    }
+
+    #[test]
+    fn test_range_diffs_both_endpoints() {
+        let git = MockGit::new("/repo")
+            .with_output("rev-parse --verify --quiet v1^{commit}", "abc\n")
+            .with_output("rev-parse --verify --quiet v2^{commit}", "def\n")
+            .with_output("diff --name-only -z v1 v2", "a.txt\0new.txt\0")
+            .with_output("show v1:a.txt", "old\n")
+            .with_output("show v2:a.txt", "new\n")
+            .with_output("show v2:new.txt", "added\n");
+        let diffs = git_range_files(&git, "v1", "v2", &GlobSet::empty(), &mut |_, _| {}).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].lines.len(), 2);
+        assert_eq!(diffs[1].lines.len(), 1);
+        assert_eq!(diffs[1].lines[0].tag, ChangeTag::Insert);
+
+        let unchanged = MockGit::new("/repo")
+            .with_output("rev-parse --verify --quiet v1^{commit}", "abc\n")
+            .with_output("diff --name-only -z v1 v1", "");
+        let diffs =
+            git_range_files(&unchanged, "v1", "v1", &GlobSet::empty(), &mut |_, _| {}).unwrap();
+        assert!(diffs.is_empty());
+    }
+
+    #[test]
+    fn test_ref_path_pairs() {
+        let git = MockGit::new("/repo")
+            .with_output("show feature:src/x.rs", "a\nb\n")
+            .with_output("show main:src/x.rs", "a\nc\n")
+            .with_failure(
+                "show main:missing.rs",
+                "fatal: path 'missing.rs' does not exist in 'main'",
+            );
+        let pairs = vec![("feature:src/x.rs".to_string(), "main:src/x.rs".to_string())];
+        let diffs = git_ref_path_files(&git, &pairs, &mut |_, _| {}).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].old_path.as_ref(), "feature:src/x.rs");
+        assert_eq!(diffs[0].new_path.as_ref(), "main:src/x.rs");
+        let tags: Vec<_> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert]
+        );
+
+        let pairs = vec![(
+            "feature:src/x.rs".to_string(),
+            "main:missing.rs".to_string(),
+        )];
+        let err = git_ref_path_files(&git, &pairs, &mut |_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            "git show failed: fatal: path 'missing.rs' does not exist in 'main'"
+        );
+    }
+
+    #[test]
+    fn test_blob_diffs_objects_by_hash() {
+        let old = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+        let git = MockGit::new("/repo")
+            .with_output(&format!("cat-file blob {old}"), "a\nb\n")
+            .with_output("cat-file blob 9f2c1a0", "a\nc\n")
+            .with_failure(
+                "cat-file blob deadbeef",
+                "fatal: Not a valid object name deadbeef",
+            );
+        let diffs = git_blob_files(&git, old, "9f2c1a0").unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].old_path.as_ref(), "3b18e51");
+        assert_eq!(diffs[0].new_path.as_ref(), "9f2c1a0");
+        let tags: Vec<_> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert]
+        );
+
+        let err = git_blob_files(&git, old, "deadbeef").err().unwrap();
+        assert_eq!(
+            err,
+            "git cat-file failed: fatal: Not a valid object name deadbeef"
+        );
+    }
+
+    #[test]
+    fn test_log_steps_through_commits() {
+        let log = concat!(
+            "\x1eccc3333\tTweak\0\nsrc/new.rs\0",
+            "\x1ebbb2222\tRename\0\nsrc/new.rs\0",
+            "\x1eaaa1111\tAdd\0\nsrc/old.rs\0",
+        );
+        let git = MockGit::new("/repo")
+            .with_output(
+                "log --follow -z --name-only --format=%x1e%h%x09%s -- src/new.rs",
+                log,
+            )
+            .with_output("show ccc3333:src/new.rs", "one\nthree\n")
+            .with_output("show bbb2222:src/new.rs", "one\ntwo\n")
+            .with_output("show aaa1111:src/old.rs", "one\ntwo\n");
+        let diffs = git_log_files(&git, "src/new.rs", &mut |_, _| {}).unwrap();
+        let summary: Vec<(&str, &str, usize)> = diffs
+            .iter()
+            .map(|d| {
+                let changed = d.lines.iter().filter(|l| l.tag != ChangeTag::Equal).count();
+                (d.old_path.as_ref(), d.new_path.as_ref(), changed)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("bbb2222:src/new.rs", "ccc3333:src/new.rs", 2),
+                ("aaa1111:src/old.rs", "bbb2222:src/new.rs", 0),
+                ("aaa1111:src/old.rs", "aaa1111:src/old.rs", 2),
+            ]
+        );
+        assert_eq!(diffs[0].commit.as_deref(), Some("ccc3333 Tweak"));
+        assert_eq!(diffs[2].status, Some(FileStatus::Added));
+
+        let empty = MockGit::new("/repo").with_output(
+            "log --follow -z --name-only --format=%x1e%h%x09%s -- nope.rs",
+            "",
+        );
+        let err = git_log_files(&empty, "nope.rs", &mut |_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err, "No commits found for nope.rs");
+    }
+
+    #[test]
+    fn test_split_tags_staged_and_unstaged() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "a.txt\0")
+            .with_output("diff --name-only -z", "a.txt\0")
+            .with_output("show :a.txt", "staged\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_file("/repo/a.txt", "work tree\n");
+        let diffs = git_split_files(
+            &git,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        let staging: Vec<_> = diffs.iter().map(|d| d.staging).collect();
+        assert_eq!(
+            staging,
+            vec![Some(Staging::Staged), Some(Staging::Unstaged)]
+        );
+        assert_eq!(diffs[1].lines[1].content.as_ref(), "work tree");
+
+        let clean = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "")
+            .with_output("diff --name-only -z", "")
+            .with_output("ls-files --others -z --exclude-standard", "");
+        let diffs = git_split_files(
+            &clean,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert!(diffs.is_empty());
+    }
+
+    #[test]
+    fn test_base_accepts_tags_and_reports_unknown_refs() {
+        let git = MockGit::new("/repo")
+            .with_output("rev-parse --verify --quiet v1.0.0^{commit}", "abc123\n")
+            .with_output("diff --name-only -z v1.0.0", "a.txt\0")
+            .with_output("diff --name-status -z v1.0.0", "M\0a.txt\0")
+            .with_output("show v1.0.0:a.txt", "released\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_file("/repo/a.txt", "current\n");
+        let diffs = git_base_files(
+            &git,
+            "v1.0.0",
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs[0].lines[0].content.as_ref(), "released");
+
+        let unknown =
+            MockGit::new("/repo").with_failure("rev-parse --verify --quiet v9^{commit}", "");
+        let err = git_base_files(
+            &unknown,
+            "v9",
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "Unknown ref 'v9'");
+
+        let broken = MockGit::new("/repo").with_failure(
+            "rev-parse --verify --quiet v1^{commit}",
+            "fatal: bad object\n",
+        );
+        let err = git_range_files(&broken, "v1", "v2", &GlobSet::empty(), &mut |_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err, "git rev-parse failed: fatal: bad object");
+    }
+
+    #[test]
+    fn test_merge_base_diffs_against_work_tree() {
+        let git = MockGit::new("/repo")
+            .with_output("rev-parse --verify --quiet main^{commit}", "def456\n")
+            .with_output("merge-base HEAD main", "abc123\n")
+            .with_output("diff --name-only -z abc123", "a.txt\0added.txt\0")
+            .with_output("diff --name-status -z abc123", "M\0a.txt\0A\0added.txt\0")
+            .with_output("show abc123:a.txt", "base\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_file("/repo/a.txt", "branch\n")
+            .with_file("/repo/added.txt", "new\n");
+        let diffs = git_merge_base_files(
+            &git,
+            "main",
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].lines[0].content.as_ref(), "base");
+        assert_eq!(diffs[0].status, Some(FileStatus::Modified));
+        assert_eq!(diffs[1].status, Some(FileStatus::Added));
+
+        let unrelated = MockGit::new("/repo")
+            .with_output("rev-parse --verify --quiet main^{commit}", "def456\n")
+            .with_failure("merge-base HEAD main", "");
+        let err = git_merge_base_files(
+            &unrelated,
+            "main",
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err, "No merge base found between HEAD and main");
+    }
+
+    #[test]
+    fn test_file_status_badges() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "a.txt\0gone.txt\0")
+            .with_output("show :a.txt", "x\n")
+            .with_output("show :gone.txt", "bye\n")
+            .with_output("ls-files --others -z --exclude-standard", "new.txt\0")
+            .with_output(
+                "status --porcelain -z",
+                "AM a.txt\0 D gone.txt\0?? new.txt\0",
+            )
+            .with_file("/repo/a.txt", "y\n")
+            .with_file("/repo/new.txt", "hi\n");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        let statuses: Vec<Option<FileStatus>> = diffs.iter().map(|d| d.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                Some(FileStatus::Modified),
+                Some(FileStatus::Deleted),
+                Some(FileStatus::Untracked),
+            ]
+        );
+
+        let staged = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "a.txt\0")
+            .with_output("show :a.txt", "x\n")
+            .with_output("status --porcelain -z", "AM a.txt\0");
+        let diffs = git_diff_files(
+            &staged,
+            true,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs[0].status, Some(FileStatus::Added));
+    }
+
+    #[test]
+    fn test_unstaged_modified_file() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "a.txt\0")
+            .with_output("show :a.txt", "one\ntwo\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_file("/repo/a.txt", "one\nthree\n");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].new_path.as_ref(), "a.txt");
+        let tags: Vec<ChangeTag> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert]
+        );
+    }
+
+    #[test]
+    fn test_staged_diffs_head_against_index() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "a.txt\0")
+            .with_output("show HEAD:a.txt", "one\ntwo\n")
+            .with_output("show :a.txt", "one\nthree\n");
+        let diffs = git_diff_files(
+            &git,
+            true,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        let tags: Vec<ChangeTag> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert]
+        );
+        assert_eq!(diffs[0].lines[1].content.as_ref(), "two");
+        assert_eq!(diffs[0].lines[2].content.as_ref(), "three");
+    }
+
+    #[test]
+    fn test_renamed_file_uses_new_path() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "src/new.rs\0")
+            .with_output("show :src/new.rs", "fn main() {}\n")
+            .with_output("status --porcelain -z", "R  src/new.rs\0src/old.rs\0");
+        let diffs = git_diff_files(
+            &git,
+            true,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].new_path.as_ref(), "src/new.rs");
+        assert_eq!(diffs[0].status, Some(FileStatus::Modified));
+    }
+
+    #[test]
+    fn test_renamed_file_diffs_against_old_path() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z --cached", "src/new.rs\0")
+            .with_output(
+                "diff --name-status -M -z --cached",
+                "R087\0src/old.rs\0src/new.rs\0",
+            )
+            .with_output("show HEAD:src/old.rs", "fn main() {}\nfn a() {}\n")
+            .with_output("show :src/new.rs", "fn main() {}\nfn b() {}\n")
+            .with_output("status --porcelain -z", "R  src/new.rs\0src/old.rs\0");
+        let diffs = git_diff_files(
+            &git,
+            true,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs[0].old_path.as_ref(), "src/old.rs");
+        assert_eq!(diffs[0].new_path.as_ref(), "src/new.rs");
+        let tags: Vec<ChangeTag> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(
+            tags,
+            vec![ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert]
+        );
+    }
+
+    #[test]
+    fn test_untracked_file_is_all_insertions() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "")
+            .with_output("ls-files --others -z --exclude-standard", "notes.txt\0")
+            .with_file("/repo/notes.txt", "one\ntwo\n");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].status, Some(FileStatus::Untracked));
+        assert!(diffs[0]
+            .lines
+            .iter()
+            .all(|l| l.tag == ChangeTag::Insert && l.old_lineno.is_none()));
+        assert_eq!(diffs[0].lines.len(), 2);
+    }
+
+    #[test]
+    fn test_file_deleted_in_work_tree_is_all_deletions() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "gone.txt\0")
+            .with_output("show :gone.txt", "one\ntwo\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_output("status --porcelain -z", " D gone.txt\0");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(diffs[0].status, Some(FileStatus::Deleted));
+        let tags: Vec<ChangeTag> = diffs[0].lines.iter().map(|l| l.tag).collect();
+        assert_eq!(tags, vec![ChangeTag::Delete, ChangeTag::Delete]);
+    }
+
+    #[test]
+    fn test_no_changes_is_empty() {
+        let clean = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "")
+            .with_output("diff --name-only -z --cached", "")
+            .with_output("ls-files --others -z --exclude-standard", "");
+        for staged in [false, true] {
+            let target = GitTarget::WorkTree { staged };
+            let diffs = git_target_files(
+                &clean,
+                &target,
+                &GlobSet::empty(),
+                UntrackedFiles::default(),
+                &mut |_, _| {},
+            );
+            assert!(diffs.unwrap().is_empty());
+        }
+        assert_eq!(
+            GitTarget::WorkTree { staged: false }.empty_message(),
+            "No unstaged changes found"
+        );
+        assert_eq!(
+            GitTarget::Range {
+                old: "v1".into(),
+                new: "v2".into()
+            }
+            .empty_message(),
+            "No changes found between v1 and v2"
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_paths() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "caf\u{e9}.txt\0")
+            .with_output("show :caf\u{e9}.txt", "old\n")
+            .with_output(
+                "ls-files --others -z --exclude-standard",
+                "r\u{e9}sum\u{e9}.txt\0",
+            )
+            .with_output(
+                "status --porcelain -z",
+                " M caf\u{e9}.txt\0?? r\u{e9}sum\u{e9}.txt\0",
+            )
+            .with_file("/repo/caf\u{e9}.txt", "new\n")
+            .with_file("/repo/r\u{e9}sum\u{e9}.txt", "hired\n");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        let summary: Vec<(&str, Option<FileStatus>, Vec<&str>)> = diffs
+            .iter()
+            .map(|d| {
+                let lines = d.lines.iter().map(|l| l.content.as_ref()).collect();
+                (d.new_path.as_ref(), d.status, lines)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "caf\u{e9}.txt",
+                    Some(FileStatus::Modified),
+                    vec!["old", "new"]
+                ),
+                (
+                    "r\u{e9}sum\u{e9}.txt",
+                    Some(FileStatus::Untracked),
+                    vec!["hired"]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_status_codes_with_unusual_paths() {
+        let git = MockGit::new("/repo").with_output(
+            "status --porcelain -z",
+            "RM new name.rs\0old name.rs\0 D caf\u{e9}.txt\0?? say \"hi\".txt\0",
+        );
+        let codes = git_status_codes(&git, "/repo", false).unwrap();
+        assert_eq!(
+            codes,
+            HashMap::from([
+                ("new name.rs".to_string(), FileStatus::Modified),
+                ("caf\u{e9}.txt".to_string(), FileStatus::Deleted),
+                ("say \"hi\".txt".to_string(), FileStatus::Untracked),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_blame_times() {
+        let zeros = "0".repeat(40);
+        let porcelain = format!(
+            "{a} 1 1 2\nauthor A\nauthor-time 1700000000\nsummary one\n\tfirst\n\
+             {a} 2 2\nauthor A\nauthor-time 1700000000\n\tsecond\n\
+             {zeros} 3 3 1\nauthor Not Committed Yet\nauthor-time 1800000000\n\t\n\
+             {b} 3 4 1\nauthor B\nauthor-time 1600000000\n\tauthor-time 5\n",
+            a = "a".repeat(40),
+            b = "b".repeat(40),
+        );
+        let git = MockGit::new("/repo")
+            .with_output("blame --line-porcelain -- src/lib.rs", &porcelain)
+            .with_output("blame --line-porcelain v2 -- src/lib.rs", "");
+        assert_eq!(
+            git_blame_times(&git, "/repo", &BlameSource::WorkTree, "src/lib.rs").unwrap(),
+            vec![Some(1700000000), Some(1700000000), None, Some(1600000000)]
+        );
+        let rev = BlameSource::Rev("v2".into());
+        assert_eq!(
+            git_blame_times(&git, "/repo", &rev, "src/lib.rs").unwrap(),
+            Vec::new()
+        );
+        assert!(git_blame_times(&git, "/repo", &rev, "missing.rs").is_err());
+
+        assert_eq!(
+            GitTarget::Range {
+                old: "v1".into(),
+                new: "v2".into()
+            }
+            .blame_source(),
+            Some(rev)
+        );
+        assert_eq!(GitTarget::WorkTree { staged: true }.blame_source(), None);
+    }
+
+    #[test]
+    fn test_type_change_diffs_symlink_target() {
+        let git = MockGit::new("/repo")
+            .with_output("diff --name-only -z", "link\0")
+            .with_output("diff --raw -z", ":100644 120000 1234567 0000000 T\0link\0")
+            .with_output("show :link", "a regular file\nwith two lines\n")
+            .with_output("ls-files --others -z --exclude-standard", "")
+            .with_file("/repo/link", "contents of the target\n")
+            .with_link("/repo/link", "target.txt");
+        let diffs = git_diff_files(
+            &git,
+            false,
+            &GlobSet::empty(),
+            UntrackedFiles::default(),
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            diffs[0].type_change,
+            Some((FileMode::Regular, FileMode::Symlink))
+        );
+        assert_eq!(
+            diffs[0].type_change_message().unwrap(),
+            "Changed from regular file to symlink"
+        );
+        assert_eq!(diffs[0].lines.len(), 1);
+        assert_eq!(diffs[0].lines[0].tag, ChangeTag::Insert);
+        assert_eq!(diffs[0].lines[0].content.as_ref(), "target.txt");
+    }
+}
@@ -0,0 +1,152 @@
+//! Compares two patches for the same change, e.g. two revisions of a pull
+//! request, showing how the patches themselves differ.
+
+use similar::ChangeTag;
+
+use crate::diff::{DiffLine, FileDiff, FileStatus};
+
+/// Writes a parsed patch entry back out as diff text, one `+`/`-`/` ` line
+/// per [`DiffLine`]. Line numbers are left out so hunks that only moved
+/// compare equal; an `@@` line marks each gap between hunks.
+pub fn patch_body(diff: &FileDiff) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&DiffLine> = None;
+    for line in &diff.lines {
+        let follows = |a: Option<usize>, b: Option<usize>| match (a, b) {
+            (Some(a), Some(b)) => b == a + 1,
+            _ => true,
+        };
+        let contiguous = prev.is_some_and(|prev| {
+            follows(prev.old_lineno, line.old_lineno) && follows(prev.new_lineno, line.new_lineno)
+        });
+        if !contiguous {
+            out.push_str("@@\n");
+        }
+        let sign = match line.tag {
+            ChangeTag::Equal => ' ',
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+        };
+        out.push(sign);
+        out.push_str(&line.content);
+        out.push('\n');
+        prev = Some(line);
+    }
+    out
+}
+
+/// Diffs each file's patch body in `old` against the same file's in `new`.
+/// Files only in `new` are marked added and files only in `old` deleted.
+pub fn interdiff(old: &[FileDiff], new: &[FileDiff]) -> Vec<FileDiff> {
+    let find_old = |path: &str| old.iter().position(|diff| diff.new_path.as_ref() == path);
+    let mut matched = vec![false; old.len()];
+    let mut diffs = Vec::new();
+
+    for new_diff in new {
+        let old_ix = find_old(new_diff.new_path.as_ref());
+        let old_body = old_ix.map_or(String::new(), |ix| patch_body(&old[ix]));
+        let path = new_diff.new_path.as_ref();
+        let mut diff = FileDiff::from_contents(path, path, &old_body, &patch_body(new_diff));
+        diff.status = Some(match old_ix {
+            Some(ix) => {
+                matched[ix] = true;
+                FileStatus::Modified
+            }
+            None => FileStatus::Added,
+        });
+        diffs.push(diff);
+    }
+    for (old_diff, _) in old.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+        let path = old_diff.new_path.as_ref();
+        let mut diff = FileDiff::from_contents(path, path, &patch_body(old_diff), "");
+        diff.status = Some(FileStatus::Deleted);
+        diffs.push(diff);
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::parse_patch;
+
+    const V1: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,2 +1,2 @@
+ fn a() {}
+-fn b() {}
++fn b() { todo!() }
+@@ -20,1 +20,2 @@
+ fn z() {}
++fn y() {}
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+
+    const V2: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3,2 +3,2 @@
+ fn a() {}
+-fn b() {}
++fn b() { 1 }
+@@ -22,1 +22,2 @@
+ fn z() {}
++fn y() {}
+diff --git a/new.txt b/new.txt
+new file mode 100644
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+";
+
+    fn changes(diff: &FileDiff) -> Vec<String> {
+        diff.lines
+            .iter()
+            .filter(|line| line.tag != ChangeTag::Equal)
+            .map(|line| format!("{:?} {}", line.tag, line.content))
+            .collect()
+    }
+
+    #[test]
+    fn test_patch_body_marks_hunks() {
+        let diffs = parse_patch(V1).unwrap();
+        assert_eq!(
+            patch_body(&diffs[0]),
+            "@@\n fn a() {}\n-fn b() {}\n+fn b() { todo!() }\n@@\n fn z() {}\n+fn y() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_interdiff() {
+        let diffs = interdiff(&parse_patch(V1).unwrap(), &parse_patch(V2).unwrap());
+        let summary: Vec<(&str, Option<FileStatus>)> = diffs
+            .iter()
+            .map(|diff| (diff.new_path.as_ref(), diff.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("src/lib.rs", Some(FileStatus::Modified)),
+                ("new.txt", Some(FileStatus::Added)),
+                ("old.txt", Some(FileStatus::Deleted)),
+            ]
+        );
+        assert_eq!(
+            changes(&diffs[0]),
+            vec!["Delete +fn b() { todo!() }", "Insert +fn b() { 1 }"]
+        );
+        assert_eq!(changes(&diffs[1]), vec!["Insert @@", "Insert +hello"]);
+
+        let same = interdiff(&parse_patch(V2).unwrap(), &parse_patch(V2).unwrap());
+        assert!(same.iter().all(FileDiff::is_unchanged));
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::{Range, RangeInclusive};
+
+use gpui::SharedString;
+use similar::ChangeTag;
+
+use crate::diff::{
+    to_side_by_side_grouped, to_side_by_side_matched, DiffLine, FileDiff, SideBySideLine,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewMode {
+    Unified,
+    SideBySide,
+    ChangesOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SideBySideAlignment {
+    Similarity,
+    Grouped,
+}
+
+/// The view settings that decide which rows of a diff are shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutOptions {
+    pub view_mode: ViewMode,
+    pub alignment: SideBySideAlignment,
+    pub context_lines: Option<usize>,
+    pub ignore_blank_lines: bool,
+    /// Soft-wrap long lines. This only changes how rows are drawn; rows and
+    /// copied text are always whole logical lines.
+    pub wrap_lines: bool,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            view_mode: ViewMode::Unified,
+            alignment: SideBySideAlignment::Similarity,
+            context_lines: None,
+            ignore_blank_lines: false,
+            wrap_lines: true,
+        }
+    }
+}
+
+/// One rendered row of a file diff.
+pub enum Row {
+    HunkHeader {
+        /// An index into [`FileDiff::hunks`].
+        hunk: usize,
+        header: SharedString,
+    },
+    /// An index into [`FileDiff::lines`].
+    Line(usize),
+    SideBySide {
+        line: SideBySideLine,
+        /// Indices into [`FileDiff::lines`] for the left and right halves.
+        indices: (Option<usize>, Option<usize>),
+    },
+}
+
+impl Row {
+    /// The [`FileDiff::lines`] indices shown on this row.
+    fn line_indices(&self) -> impl Iterator<Item = usize> {
+        let (left, right) = match self {
+            Row::HunkHeader { .. } => (None, None),
+            Row::Line(ix) => (Some(*ix), None),
+            Row::SideBySide { indices, .. } => *indices,
+        };
+        left.into_iter().chain(right)
+    }
+}
+
+/// The first line shown at or below `row`, used to remember the scroll
+/// position across layouts.
+pub fn line_at_row(rows: &[Row], row: usize) -> Option<usize> {
+    rows.iter()
+        .skip(row)
+        .find_map(|row| row.line_indices().min())
+}
+
+/// The row showing `line`, or the first row after it when this layout
+/// hides the line.
+pub fn row_of_line(rows: &[Row], line: usize) -> Option<usize> {
+    rows.iter()
+        .position(|row| row.line_indices().any(|ix| ix >= line))
+}
+
+impl LayoutOptions {
+    pub fn is_line_visible(&self, line: &DiffLine) -> bool {
+        if self.view_mode == ViewMode::ChangesOnly && line.tag == ChangeTag::Equal {
+            return false;
+        }
+        !(self.ignore_blank_lines && line.is_blank_change())
+    }
+
+    pub fn sections(&self, diff: &FileDiff) -> Vec<(Option<SharedString>, Range<usize>)> {
+        match self.context_lines {
+            Some(context) => diff
+                .hunks(context)
+                .into_iter()
+                .map(|hunk| (Some(SharedString::from(hunk.header())), hunk.lines))
+                .collect(),
+            None => vec![(None, 0..diff.lines.len())],
+        }
+    }
+
+    pub fn visible_line_indices(&self, diff: &FileDiff) -> Vec<usize> {
+        self.sections(diff)
+            .into_iter()
+            .flat_map(|(_, range)| range)
+            .filter(|&ix| self.is_line_visible(&diff.lines[ix]))
+            .collect()
+    }
+
+    /// How many visible lines are added or removed, and how many are
+    /// visible in total, context included.
+    pub fn shown_line_counts(&self, diff: &FileDiff) -> (usize, usize) {
+        let visible = self.visible_line_indices(diff);
+        let changed = visible
+            .iter()
+            .filter(|&&ix| diff.lines[ix].tag != ChangeTag::Equal)
+            .count();
+        (changed, visible.len())
+    }
+
+    /// The contents of the visible lines whose indices into
+    /// [`FileDiff::lines`] fall in `range`, one per line.
+    pub fn copy_text(&self, diff: &FileDiff, range: RangeInclusive<usize>) -> String {
+        self.visible_line_indices(diff)
+            .into_iter()
+            .filter(|ix| range.contains(ix))
+            .map(|ix| diff.lines[ix].content.as_ref())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn rows(&self, diff: &FileDiff) -> Vec<Row> {
+        if self.view_mode == ViewMode::ChangesOnly {
+            return self
+                .visible_line_indices(diff)
+                .into_iter()
+                .map(Row::Line)
+                .collect();
+        }
+
+        let line_index: HashMap<(Option<usize>, Option<usize>), usize> = diff
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(ix, l)| ((l.old_lineno, l.new_lineno), ix))
+            .collect();
+        let index_of = |line: &Option<DiffLine>| {
+            line.as_ref()
+                .and_then(|l| line_index.get(&(l.old_lineno, l.new_lineno)).copied())
+        };
+
+        let mut rows = Vec::new();
+        for (hunk, (header, range)) in self.sections(diff).into_iter().enumerate() {
+            rows.extend(header.map(|header| Row::HunkHeader { hunk, header }));
+            let visible = range.filter(|&ix| self.is_line_visible(&diff.lines[ix]));
+            if self.view_mode == ViewMode::Unified {
+                rows.extend(visible.map(Row::Line));
+                continue;
+            }
+            let visible_lines: Vec<DiffLine> = visible.map(|ix| diff.lines[ix].clone()).collect();
+            let sbs_lines = match self.alignment {
+                SideBySideAlignment::Similarity => to_side_by_side_matched(&visible_lines),
+                SideBySideAlignment::Grouped => to_side_by_side_grouped(&visible_lines),
+            };
+            rows.extend(sbs_lines.into_iter().map(|line| {
+                let indices = (index_of(&line.left), index_of(&line.right));
+                Row::SideBySide { line, indices }
+            }));
+        }
+        rows
+    }
+}
+
+/// The window title for a set of diffs: the two paths when comparing a
+/// single file pair, otherwise a file count with the total line changes.
+pub fn window_title(diffs: &[FileDiff], pair_mode: bool) -> String {
+    if let [diff] = diffs {
+        if pair_mode {
+            return format!("{} ↔ {}", diff.old_path, diff.new_path);
+        }
+    }
+    if diffs.is_empty() {
+        return "diff".to_string();
+    }
+    let count = |tag| {
+        diffs
+            .iter()
+            .flat_map(|diff| &diff.lines)
+            .filter(|line| line.tag == tag)
+            .count()
+    };
+    let files = if diffs.len() == 1 { "file" } else { "files" };
+    format!(
+        "diff: {} {files} (+{} −{})",
+        diffs.len(),
+        count(ChangeTag::Insert),
+        count(ChangeTag::Delete)
+    )
+}
+
+/// The widest `+`/`-` bar in [`summary_text`]; larger changes are scaled.
+const SUMMARY_BAR_WIDTH: usize = 40;
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    let word = if count == 1 { singular } else { plural };
+    format!("{count} {word}")
+}
+
+/// A `git diff --stat`-style summary: one line per file with its number of
+/// changed lines and a bar of `+` and `-`, then a totals line.
+pub fn summary_text(diffs: &[FileDiff]) -> String {
+    let names: Vec<String> = diffs
+        .iter()
+        .map(|diff| {
+            if diff.old_path == diff.new_path {
+                diff.new_path.to_string()
+            } else {
+                format!("{} → {}", diff.old_path, diff.new_path)
+            }
+        })
+        .collect();
+    let counts: Vec<(usize, usize)> = diffs.iter().map(FileDiff::change_counts).collect();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_total = counts
+        .iter()
+        .map(|(adds, dels)| adds + dels)
+        .max()
+        .unwrap_or(0);
+    let count_width = max_total.to_string().len();
+    let bar = |n: usize| {
+        if max_total > SUMMARY_BAR_WIDTH {
+            (n * SUMMARY_BAR_WIDTH).div_ceil(max_total)
+        } else {
+            n
+        }
+    };
+
+    let mut out = String::new();
+    for ((diff, name), &(adds, dels)) in diffs.iter().zip(&names).zip(&counts) {
+        let detail = if diff.image {
+            "Bin".to_string()
+        } else if let Some(size) = diff.too_large {
+            format!("skipped ({size} bytes)")
+        } else {
+            let total = adds + dels;
+            format!(
+                "{total:>count_width$} {}{}",
+                "+".repeat(bar(adds)),
+                "-".repeat(bar(dels))
+            )
+        };
+        writeln!(out, " {name:<name_width$} | {}", detail.trim_end()).unwrap();
+    }
+    let (adds, dels) = counts
+        .iter()
+        .fold((0, 0), |(a, d), &(adds, dels)| (a + adds, d + dels));
+    writeln!(
+        out,
+        " {} changed, {}, {}",
+        plural(diffs.len(), "file", "files"),
+        plural(adds, "insertion(+)", "insertions(+)"),
+        plural(dels, "deletion(-)", "deletions(-)")
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    use super::*;
+
+    fn sign(tag: ChangeTag) -> char {
+        match tag {
+            ChangeTag::Equal => ' ',
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+        }
+    }
+
+    fn lineno(n: Option<usize>) -> String {
+        n.map(|n| n.to_string()).unwrap_or_default()
+    }
+
+    fn side_text(line: &Option<DiffLine>, old_side: bool) -> String {
+        match line {
+            Some(line) => {
+                let number = lineno(if old_side {
+                    line.old_lineno
+                } else {
+                    line.new_lineno
+                });
+                format!("{number:>4} {}{}", sign(line.tag), line.content)
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Serializes what the viewer would show for `diff` as plain text, one row
+    /// per line, so rendering can be snapshot-tested without a window.
+    fn render_text(diff: &FileDiff, options: &LayoutOptions) -> String {
+        let mut out = format!("--- {}\n+++ {}\n", diff.old_path, diff.new_path);
+        if let Some(message) = diff.type_change_message() {
+            writeln!(out, "[{message}]").unwrap();
+        }
+        if diff.image {
+            out.push_str("[image]\n");
+            return out;
+        }
+        if let Some(size) = diff.too_large {
+            writeln!(out, "[too large: {size} bytes]").unwrap();
+            return out;
+        }
+
+        let rows = options.rows(diff);
+        let left_width = rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::SideBySide { line, .. } => Some(side_text(&line.left, true).chars().count()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        for row in rows {
+            match row {
+                Row::HunkHeader { header, .. } => writeln!(out, "{header}"),
+                Row::Line(ix) if options.view_mode == ViewMode::ChangesOnly => {
+                    let line = &diff.lines[ix];
+                    let reference = diff.line_reference(line);
+                    writeln!(out, "{reference}: {}{}", sign(line.tag), line.content)
+                }
+                Row::Line(ix) => {
+                    let line = &diff.lines[ix];
+                    let (old, new) = (lineno(line.old_lineno), lineno(line.new_lineno));
+                    writeln!(out, "{old:>4} {new:>4} {}{}", sign(line.tag), line.content)
+                }
+                Row::SideBySide { line, .. } => {
+                    let left = side_text(&line.left, true);
+                    let right = side_text(&line.right, false);
+                    writeln!(out, "{left:<left_width$} | {right}")
+                }
+            }
+            .unwrap();
+        }
+        out
+    }
+
+    fn golden_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+    }
+
+    // Run with UPDATE_GOLDEN=1 to rewrite the expected files after an
+    // intended rendering change.
+    fn check_golden(fixture: &str, snapshot: &str, options: LayoutOptions) {
+        let dir = golden_dir();
+        let read = |name: String| {
+            fs::read_to_string(dir.join(&name)).unwrap_or_else(|e| panic!("{name}: {e}"))
+        };
+        let old = read(format!("{fixture}.old"));
+        let new = read(format!("{fixture}.new"));
+        let diff = FileDiff::from_contents("old.txt", "new.txt", &old, &new);
+        let actual = render_text(&diff, &options);
+
+        let expected_path = dir.join(format!("{fixture}.{snapshot}.txt"));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&expected_path, &actual).unwrap();
+            return;
+        }
+        let expected = read(format!("{fixture}.{snapshot}.txt"));
+        assert_eq!(
+            actual, expected,
+            "{fixture}.{snapshot} changed; rerun with UPDATE_GOLDEN=1 if intended"
+        );
+    }
+
+    #[test]
+    fn test_golden_views() {
+        let unified = LayoutOptions::default();
+        let side_by_side = LayoutOptions {
+            view_mode: ViewMode::SideBySide,
+            ..unified
+        };
+        check_golden("sample", "unified", unified);
+        check_golden(
+            "sample",
+            "unified-context",
+            LayoutOptions {
+                context_lines: Some(1),
+                ..unified
+            },
+        );
+        check_golden(
+            "sample",
+            "ignore-blank",
+            LayoutOptions {
+                ignore_blank_lines: true,
+                ..unified
+            },
+        );
+        check_golden("sample", "side-by-side", side_by_side);
+        check_golden(
+            "sample",
+            "side-by-side-grouped",
+            LayoutOptions {
+                alignment: SideBySideAlignment::Grouped,
+                context_lines: Some(1),
+                ..side_by_side
+            },
+        );
+        check_golden(
+            "sample",
+            "changes-only",
+            LayoutOptions {
+                view_mode: ViewMode::ChangesOnly,
+                ..unified
+            },
+        );
+    }
+
+    #[test]
+    fn test_copy_text_ignores_wrapping() {
+        let long = "x".repeat(300);
+        let old = format!("keep\n{long}\n\nend\n");
+        let new = format!("keep\n{long} changed\n\nend\n");
+        let diff = FileDiff::from_contents("a", "b", &old, &new);
+        let wrapped = LayoutOptions::default();
+        let unwrapped = LayoutOptions {
+            wrap_lines: false,
+            ..wrapped
+        };
+        let all = 0..=diff.lines.len();
+        assert_eq!(
+            wrapped.copy_text(&diff, all.clone()),
+            unwrapped.copy_text(&diff, all.clone())
+        );
+        assert_eq!(
+            wrapped.copy_text(&diff, all.clone()),
+            format!("keep\n{long}\n{long} changed\n\nend")
+        );
+
+        let hide_blank = LayoutOptions {
+            ignore_blank_lines: true,
+            view_mode: ViewMode::ChangesOnly,
+            ..wrapped
+        };
+        assert_eq!(
+            hide_blank.copy_text(&diff, 1..=2),
+            format!("{long}\n{long} changed")
+        );
+    }
+
+    #[test]
+    fn test_shown_line_counts() {
+        let old = "a\nb\nc\nd\ne\nf\n";
+        let new = "a\nb\nc\nd\ne\nF\n\n";
+        let diff = FileDiff::from_contents("a", "b", old, new);
+        let full = LayoutOptions::default();
+        assert_eq!(full.shown_line_counts(&diff), (3, 8));
+        let trimmed = LayoutOptions {
+            context_lines: Some(1),
+            ..full
+        };
+        assert_eq!(trimmed.shown_line_counts(&diff), (3, 4));
+        let hide_blank = LayoutOptions {
+            ignore_blank_lines: true,
+            ..trimmed
+        };
+        assert_eq!(hide_blank.shown_line_counts(&diff), (2, 3));
+    }
+
+    #[test]
+    fn test_window_title() {
+        let a = || FileDiff::from_contents("a.txt", "b.txt", "one\ntwo\n", "one\n2\nthree\n");
+        let b = FileDiff::from_contents("c.txt", "c.txt", "x\n", "");
+        assert_eq!(window_title(&[], false), "diff");
+        assert_eq!(window_title(&[a()], true), "a.txt ↔ b.txt");
+        assert_eq!(window_title(&[a()], false), "diff: 1 file (+2 −1)");
+        assert_eq!(window_title(&[a(), b], true), "diff: 2 files (+2 −2)");
+    }
+
+    #[test]
+    fn test_summary_text() {
+        let big = "x\n".repeat(80);
+        let diffs = [
+            FileDiff::from_contents("a.txt", "a.txt", "one\ntwo\n", "one\n2\nthree\n"),
+            FileDiff::from_contents("big.txt", "big.txt", "", &big),
+            FileDiff::from_contents("old.rs", "new.rs", "a\n", "a\n"),
+        ];
+        let bar = "+".repeat(40);
+        assert_eq!(
+            summary_text(&diffs),
+            format!(
+                " a.txt           |  3 +-\n \
+                 big.txt         | 80 {bar}\n \
+                 old.rs → new.rs |  0\n \
+                 3 files changed, 82 insertions(+), 1 deletion(-)\n"
+            )
+        );
+        assert_eq!(
+            summary_text(&[]),
+            " 0 files changed, 0 insertions(+), 0 deletions(-)\n"
+        );
+    }
+
+    #[test]
+    fn test_rows_index_into_diff_lines() {
+        let diff = FileDiff::from_contents("a", "b", "one\nlet x = 1;\n", "one\nlet x = 2;\n");
+        let options = LayoutOptions {
+            view_mode: ViewMode::SideBySide,
+            ..LayoutOptions::default()
+        };
+        let indices: Vec<_> = options
+            .rows(&diff)
+            .into_iter()
+            .filter_map(|row| match row {
+                Row::SideBySide { indices, .. } => Some(indices),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(indices, vec![(Some(0), Some(0)), (Some(1), Some(2))]);
+    }
+
+    #[test]
+    fn test_line_anchor_maps_between_layouts() {
+        let diff = FileDiff::from_contents(
+            "a",
+            "b",
+            "one\nlet x = 1;\ntwo\nlet y = 1;\n",
+            "one\nlet x = 2;\ntwo\nlet y = 2;\n",
+        );
+        let unified = LayoutOptions::default().rows(&diff);
+        let side_by_side = LayoutOptions {
+            view_mode: ViewMode::SideBySide,
+            ..LayoutOptions::default()
+        }
+        .rows(&diff);
+        assert_eq!((unified.len(), side_by_side.len()), (6, 4));
+
+        // Unified row 2 is the added "let x = 2;", which shares a
+        // side-by-side row with the line it replaced.
+        let line = line_at_row(&unified, 2).unwrap();
+        assert_eq!(diff.lines[line].content.as_ref(), "let x = 2;");
+        assert_eq!(row_of_line(&side_by_side, line), Some(1));
+        assert_eq!(line_at_row(&side_by_side, 3), Some(4));
+        assert_eq!(row_of_line(&unified, 4), Some(4));
+        assert_eq!(line_at_row(&unified, 6), None);
+    }
+}
@@ -0,0 +1,16 @@
+//! Line diffing and side-by-side alignment used by the gpui-diff-tool viewer.
+//!
+//! The `git` feature (enabled by default) also exposes the helpers that build
+//! [`FileDiff`]s from a git repository.
+
+pub mod diff;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod interdiff;
+pub mod patch;
+pub mod semantic;
+
+pub use diff::{
+    to_side_by_side, to_side_by_side_grouped, to_side_by_side_matched, DiffLine, DiffOptions,
+    FileDiff, FileMode, FileStatus, Hunk, SideBySideLine, Staging,
+};
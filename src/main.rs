@@ -1,71 +1,404 @@
-mod diff;
-mod git;
+mod cli;
+mod layout;
+mod palette;
+mod search;
+mod state;
+mod theme;
+mod truncate;
 mod viewer;
+mod watch;
 
-use gpui::{px, size, App, AppContext, Application, Bounds, WindowBounds, WindowOptions};
-use std::env;
+use gpui::{
+    px, size, App, AppContext, Application, Bounds, TitlebarOptions, WindowBounds, WindowOptions,
+};
+use gpui_diff_tool::{diff, git, interdiff, patch};
+use std::sync::atomic::Ordering;
+use std::{env, fs, io, path::PathBuf, process::Stdio};
 
-use crate::git::git_diff_files;
-use crate::viewer::DiffViewer;
+use crate::cli::{
+    parse_args, parse_manifest, split_opts, version_string, with_defaults, Command, Mode, HELP,
+    OPTS_ENV, STDIN_LABEL,
+};
+use crate::diff::{resolve_file_path, strip_ansi_colors, DiffOptions, FileDiff};
+use crate::git::{git_target_files, git_toplevel, SystemGit};
+use crate::interdiff::interdiff;
+use crate::layout::{summary_text, window_title};
+use crate::patch::parse_patch;
+use crate::theme::Theme;
+use crate::viewer::{bind_keys, DiffViewer, LOAD_FAILED};
+use crate::watch::WatchTarget;
 
-enum Mode {
-    FilePairs(Vec<(String, String)>),
-    Git { staged: bool },
+/// Reads and parses a patch from `file`, or from stdin for `-`.
+fn read_patch(file: &str) -> Result<Vec<FileDiff>, String> {
+    let text = if file == "-" {
+        io::read_to_string(io::stdin()).map_err(|e| format!("Failed to read stdin: {e}"))?
+    } else {
+        fs::read_to_string(file).map_err(|e| format!("Failed to read {file}: {e}"))?
+    };
+    parse_patch(&strip_ansi_colors(&text))
 }
 
-fn parse_args() -> Mode {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        eprintln!("Usage:");
-        eprintln!("  gpui-diff-tool --git            Show unstaged git changes");
-        eprintln!("  gpui-diff-tool --git --staged    Show staged git changes");
-        eprintln!("  gpui-diff-tool <old> <new> ...   Diff file pairs");
-        std::process::exit(1);
+/// Runs `command` through the shell and returns its stdout along with the
+/// label for its side of the diff. A nonzero exit is noted in the label and
+/// on stderr, but whatever the command printed is still diffed.
+fn run_command(command: &str) -> Result<(String, String), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run `{command}`: {e}"))?;
+    let mut label = format!("$ {command}");
+    if !output.status.success() {
+        let status = match output.status.code() {
+            Some(code) => format!("exit {code}"),
+            None => "killed".to_string(),
+        };
+        eprintln!("Warning: `{command}` failed ({status})");
+        label.push_str(&format!(" ({status})"));
     }
+    let stdout = strip_ansi_colors(&String::from_utf8_lossy(&output.stdout)).into_owned();
+    Ok((stdout, label))
+}
 
-    if args.iter().any(|a| a == "--git") {
-        let staged = args.iter().any(|a| a == "--staged");
-        return Mode::Git { staged };
-    }
+/// Reads the file pairs listed in a `--manifest` file.
+fn read_manifest(file: &str) -> Result<Vec<(String, String)>, String> {
+    let text = fs::read_to_string(file).map_err(|e| format!("Failed to read {file}: {e}"))?;
+    parse_manifest(&text)
+}
 
-    if args.len() < 3 || args.len() % 2 == 0 {
-        eprintln!("Usage: gpui-diff-tool <old-file> <new-file> [<old-file2> <new-file2> ...]");
+/// Diffs each file pair, exiting with an error if any path can't be read.
+fn file_pair_diffs(pairs: &[(String, String)], max_size: Option<u64>) -> Vec<FileDiff> {
+    let mut paths = pairs.iter().flat_map(|(old, new)| [old, new]);
+    if let Some(e) = paths.find_map(|path| resolve_file_path(path).err()) {
+        eprintln!("Error: {e}");
         std::process::exit(1);
     }
-
-    let mut pairs = Vec::new();
-    let mut i = 1;
-    while i + 1 < args.len() {
-        pairs.push((args[i].clone(), args[i + 1].clone()));
-        i += 2;
-    }
-    Mode::FilePairs(pairs)
+    pairs
+        .iter()
+        .map(|(old, new)| FileDiff::from_files_with_limit(old, new, max_size))
+        .collect()
 }
 
 fn main() {
-    let mode = parse_args();
+    // Defaults from the environment go first so the command line overrides them.
+    let opts = env::var(OPTS_ENV).unwrap_or_default();
+    let (mut default_args, mut warnings) = split_opts(&opts);
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        default_args.insert(0, "--no-color".to_string());
+    }
+    let cli_args: Vec<String> = env::args().skip(1).collect();
+    let (raw_args, default_warnings) = with_defaults(&default_args, &cli_args);
+    warnings.extend(default_warnings);
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+    let args = match parse_args(&raw_args) {
+        Ok(Command::Run(args)) => *args,
+        Ok(Command::Help) => {
+            print!("{HELP}");
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("{}", version_string());
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            if !opts.trim().is_empty() {
+                eprintln!("Note: {OPTS_ENV} is set to '{opts}'.");
+            }
+            eprintln!("Run 'gpui-diff-tool --help' for usage.");
+            std::process::exit(1);
+        }
+    };
 
-    let viewer = match mode {
-        Mode::FilePairs(pairs) => DiffViewer::from_file_pairs(pairs),
-        Mode::Git { staged } => match git_diff_files(staged) {
-            Ok(diffs) => DiffViewer::from_diffs(diffs),
+    let mut git_target = None;
+    let mut loaded = false;
+    let mut watched_pairs = None;
+    let mut watched_tree = None;
+    let (diffs, repo_root) = match args.mode {
+        Mode::FilePairs(pairs) => {
+            let diffs = file_pair_diffs(&pairs, args.max_size);
+            watched_pairs = args.watch.then_some(pairs);
+            (diffs, None)
+        }
+        Mode::Manifest(file) => match read_manifest(&file) {
+            Ok(pairs) => {
+                let pairs: Vec<(String, String)> = pairs
+                    .into_iter()
+                    .filter(|(old, new)| !args.exclude.is_match(old) && !args.exclude.is_match(new))
+                    .collect();
+                let diffs = file_pair_diffs(&pairs, args.max_size);
+                watched_pairs = args.watch.then_some(pairs);
+                (diffs, None)
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        },
+        Mode::Git(target) => match git_toplevel(&SystemGit) {
+            Ok(toplevel) => {
+                // Output modes and --exit-on-empty decide what to do from the
+                // files, so load them now instead of in an open window.
+                let diffs = if args.json || args.summary || args.exit_on_empty {
+                    let diffs = git_target_files(
+                        &SystemGit,
+                        &target,
+                        &args.exclude,
+                        args.untracked,
+                        &mut |_, _| {},
+                    )
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error: {e}");
+                        std::process::exit(1);
+                    });
+                    if diffs.is_empty() && args.exit_on_empty {
+                        eprintln!("Error: {}", target.empty_message());
+                        std::process::exit(1);
+                    }
+                    loaded = true;
+                    diffs
+                } else {
+                    Vec::new()
+                };
+                git_target = Some(target);
+                watched_tree = args.watch.then(|| PathBuf::from(&toplevel));
+                (diffs, Some(toplevel))
+            }
             Err(e) => {
                 eprintln!("Error: {e}");
                 std::process::exit(1);
             }
         },
+        Mode::LineRanges { old, new } => {
+            match FileDiff::from_file_ranges(&old.0, old.1, &new.0, new.1) {
+                Ok(diff) => (vec![diff], None),
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Mode::Stdin { file, stdin_is_new } => {
+            let stdin_content = io::read_to_string(io::stdin()).unwrap_or_else(|e| {
+                eprintln!("Error: Failed to read stdin: {e}");
+                std::process::exit(1);
+            });
+            let stdin_content = strip_ansi_colors(&stdin_content);
+            let file_content = fs::read_to_string(&file).unwrap_or_else(|e| {
+                eprintln!("Error: Failed to read {file}: {e}");
+                std::process::exit(1);
+            });
+            let diff = if stdin_is_new {
+                FileDiff::from_contents(&file, STDIN_LABEL, &file_content, &stdin_content)
+            } else {
+                FileDiff::from_contents(STDIN_LABEL, &file, &stdin_content, &file_content)
+            };
+            (vec![diff], None)
+        }
+        Mode::Commands { old, new } => match (run_command(&old), run_command(&new)) {
+            (Ok((old_output, old_label)), Ok((new_output, new_label))) => {
+                let diff =
+                    FileDiff::from_contents(&old_label, &new_label, &old_output, &new_output);
+                (vec![diff], None)
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        },
+        Mode::Patch(file) => match read_patch(&file) {
+            Ok(diffs) => {
+                let diffs = diffs
+                    .into_iter()
+                    .filter(|diff| {
+                        !args.exclude.is_match(diff.old_path.as_ref())
+                            && !args.exclude.is_match(diff.new_path.as_ref())
+                    })
+                    .collect();
+                (diffs, None)
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        },
+        Mode::Interdiff { old, new } => match (read_patch(&old), read_patch(&new)) {
+            (Ok(old), Ok(new)) => {
+                let diffs = interdiff(&old, &new)
+                    .into_iter()
+                    .filter(|diff| !args.exclude.is_match(diff.new_path.as_ref()))
+                    .collect();
+                (diffs, None)
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        },
     };
 
+    if args.json || args.summary {
+        let mut diffs = diffs;
+        for diff in &mut diffs {
+            diff.generated = args.generated.is_match(diff.new_path.as_ref())
+                || args.generated.is_match(diff.old_path.as_ref());
+            if args.ignore_case || args.semantic {
+                let options = DiffOptions {
+                    ignore_case: args.ignore_case,
+                    semantic: args.semantic,
+                    ..diff.options
+                };
+                diff.recompute(options).ok();
+            }
+        }
+        if args.summary {
+            print!("{}", summary_text(&diffs));
+            return;
+        }
+        match serde_json::to_string_pretty(&diffs) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Error: Failed to serialize diffs: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let title = window_title(&diffs, repo_root.is_none());
     Application::new().run(move |cx: &mut App| {
+        bind_keys(cx);
+        // Callers such as `git difftool` wait for the process, so exit once the
+        // window is gone rather than staying resident.
+        cx.on_window_closed(|cx| {
+            if cx.windows().is_empty() {
+                cx.quit();
+            }
+        })
+        .detach();
         let bounds = Bounds::centered(None, size(px(900.0), px(700.0)), cx);
-        cx.open_window(
+        let window = cx.open_window(
             WindowOptions {
                 window_bounds: Some(WindowBounds::Windowed(bounds)),
+                titlebar: Some(TitlebarOptions {
+                    title: Some(title.into()),
+                    ..Default::default()
+                }),
                 ..Default::default()
             },
-            |_, cx| cx.new(|_| viewer),
-        )
-        .unwrap();
+            |window, cx| {
+                let viewer = cx.new(|cx| {
+                    let mut viewer = match repo_root {
+                        Some(repo_root) => DiffViewer::from_repo_diffs(diffs, repo_root, cx),
+                        None => DiffViewer::from_diffs(diffs, cx),
+                    };
+                    viewer.show_line_numbers = args.line_numbers;
+                    viewer.show_indent_guides = args.indent_guides;
+                    viewer.tab_width = args.tab_width;
+                    if let Some(font) = args.font {
+                        viewer.font_family = font.into();
+                    }
+                    viewer.font_size = args.font_size;
+                    viewer.ignore_blank_lines = args.ignore_blank_lines;
+                    viewer.show_unchanged = !args.hide_unchanged;
+                    viewer.semantic = args.semantic;
+                    viewer.set_ignore_case(args.ignore_case, cx);
+                    viewer.context_lines = args.context_lines;
+                    viewer.panel_side = args.panel_side;
+                    if let Some(mode) = args.panel_mode {
+                        viewer.set_panel_mode(mode);
+                    }
+                    viewer.sbs_alignment = args.sbs_alignment;
+                    viewer.observe_window_resize(window, cx);
+                    viewer.set_generated_patterns(args.generated);
+                    viewer.theme = Theme::preset(args.theme);
+                    if let Some(sign) = args.insert_sign {
+                        viewer.theme.insert_sign = sign.into();
+                    }
+                    if let Some(sign) = args.delete_sign {
+                        viewer.theme.delete_sign = sign.into();
+                    }
+                    viewer.side_labels = (
+                        args.label_old.map(Into::into),
+                        args.label_new.map(Into::into),
+                    );
+                    if let Some(target) = git_target {
+                        viewer.source_label = Some(target.label().into());
+                        viewer.blame_source = target.blame_source();
+                        viewer.empty_message = Some(target.empty_message().into());
+                        if let Some(work_tree) = watched_tree {
+                            let (target, exclude) = (target.clone(), args.exclude.clone());
+                            let untracked = args.untracked;
+                            viewer.watch(
+                                WatchTarget::WorkTree(work_tree),
+                                move || {
+                                    git_target_files(
+                                        &SystemGit,
+                                        &target,
+                                        &exclude,
+                                        untracked,
+                                        &mut |_, _| {},
+                                    )
+                                },
+                                cx,
+                            );
+                        }
+                        if !loaded {
+                            let (exclude, untracked) = (args.exclude, args.untracked);
+                            viewer.load_in_background(
+                                move |progress| {
+                                    git_target_files(
+                                        &SystemGit, &target, &exclude, untracked, progress,
+                                    )
+                                },
+                                cx,
+                            );
+                        }
+                    }
+                    if let Some(pairs) = watched_pairs {
+                        let files = pairs
+                            .iter()
+                            .flat_map(|(old, new)| [old, new])
+                            .map(PathBuf::from)
+                            .collect();
+                        let max_size = args.max_size;
+                        viewer.watch(
+                            WatchTarget::Files(files),
+                            move || {
+                                Ok(pairs
+                                    .iter()
+                                    .map(|(old, new)| {
+                                        FileDiff::from_files_with_limit(old, new, max_size)
+                                    })
+                                    .collect())
+                            },
+                            cx,
+                        );
+                    }
+                    viewer
+                });
+                viewer.read(cx).focus_handle.focus(window);
+                viewer
+            },
+        );
+        if let Err(e) = window {
+            eprintln!("Error: Failed to open window: {e}");
+            std::process::exit(1);
+        }
     });
+    if LOAD_FAILED.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,98 @@
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = candidate[pos..].iter().position(|&c| c == q)? + pos;
+        let gap = match last_match {
+            Some(last) => found - last - 1,
+            None => found,
+        };
+        let word_start = found == 0 || !candidate[found - 1].is_alphanumeric();
+        score += if word_start { gap } else { gap + 1 };
+        last_match = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+pub fn filter_by_query(query: &str, names: &[&str]) -> Vec<usize> {
+    let mut matches: Vec<(usize, usize)> = names
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, name)| Some((fuzzy_score(query, name)?, ix)))
+        .collect();
+    matches.sort();
+    matches.into_iter().map(|(_, ix)| ix).collect()
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Formats a key binding such as `cmd-shift-p` for display, as `Cmd+Shift+P`.
+pub fn format_keystrokes(keys: &str) -> String {
+    let (modifiers, key) = match keys.strip_suffix("--") {
+        Some(modifiers) => (modifiers, "-"),
+        None => keys.rsplit_once('-').unwrap_or(("", keys)),
+    };
+    let key = match key {
+        "up" => "↑".to_string(),
+        "down" => "↓".to_string(),
+        "left" => "←".to_string(),
+        "right" => "→".to_string(),
+        "pageup" => "Page Up".to_string(),
+        "pagedown" => "Page Down".to_string(),
+        _ if key.chars().count() == 1 && modifiers.is_empty() => key.to_string(),
+        _ => capitalize(key),
+    };
+    modifiers
+        .split('-')
+        .filter(|modifier| !modifier.is_empty())
+        .map(capitalize)
+        .chain([key])
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score_subsequence() {
+        assert!(fuzzy_score("tln", "Toggle Line Numbers").is_some());
+        assert!(fuzzy_score("xyz", "Toggle Line Numbers").is_none());
+        assert_eq!(fuzzy_score("", "Anything"), Some(0));
+    }
+
+    #[test]
+    fn test_filter_ranks_word_starts_first() {
+        let names = [
+            "Copy File Path",
+            "Toggle Line Numbers",
+            "Toggle List / Tree Panel",
+        ];
+        assert_eq!(filter_by_query("line", &names)[0], 1);
+        assert!(!filter_by_query("line", &names).contains(&0));
+        assert_eq!(filter_by_query("tl", &names)[0], 1);
+        assert_eq!(filter_by_query("", &names), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_format_keystrokes() {
+        assert_eq!(format_keystrokes("cmd-shift-p"), "Cmd+Shift+P");
+        assert_eq!(format_keystrokes("ctrl--"), "Ctrl+-");
+        assert_eq!(format_keystrokes("shift-g"), "Shift+G");
+        assert_eq!(format_keystrokes("g"), "g");
+        assert_eq!(format_keystrokes("?"), "?");
+        assert_eq!(format_keystrokes("alt-left"), "Alt+←");
+        assert_eq!(format_keystrokes("pagedown"), "Page Down");
+        assert_eq!(format_keystrokes("shift-f8"), "Shift+F8");
+    }
+}
@@ -0,0 +1,351 @@
+//! Reads unified diffs, such as the output of `git diff` or `diff -u`, into
+//! [`FileDiff`]s.
+
+use std::sync::Arc;
+
+use similar::ChangeTag;
+
+use crate::diff::{DiffLine, DiffOptions, FileDiff, FileStatus};
+
+const DEV_NULL: &str = "/dev/null";
+
+#[derive(Default)]
+struct Entry {
+    old_path: Option<String>,
+    new_path: Option<String>,
+    status: Option<FileStatus>,
+    lines: Vec<DiffLine>,
+    /// Whether the `---` line was seen, so a plain (non-git) diff can start
+    /// the next entry at the next one.
+    seen_old_header: bool,
+}
+
+impl Entry {
+    fn into_diff(self) -> Result<FileDiff, String> {
+        let old_path = self.old_path.clone().or_else(|| self.new_path.clone());
+        let new_path = self.new_path.or(self.old_path);
+        let (Some(old_path), Some(new_path)) = (old_path, new_path) else {
+            return Err("Patch entry has no file name".to_string());
+        };
+        let options = DiffOptions {
+            keep_contents: false,
+            ..DiffOptions::default()
+        };
+        let mut diff = FileDiff::from_contents_with(&old_path, &new_path, "", "", options);
+        diff.set_lines(self.lines);
+        diff.status = Some(self.status.unwrap_or(FileStatus::Modified));
+        Ok(diff)
+    }
+}
+
+/// Decodes a path git quoted because it has special characters: C-style
+/// escapes (`\t`, `\"`, `\\`, ...) and octal escapes for non-ASCII bytes.
+/// Returns the path and the text after the closing quote.
+fn take_quoted(text: &str) -> Result<(String, &str), String> {
+    let unterminated = || format!("Unterminated quoted path: {text}");
+    let mut bytes = Vec::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((ix, c)) = chars.next() {
+        match c {
+            '"' => {
+                return Ok((
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                    &text[ix + 1..],
+                ))
+            }
+            '\\' => {
+                let (_, escaped) = chars.next().ok_or_else(unterminated)?;
+                let byte = match escaped {
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    't' => b'\t',
+                    'n' => b'\n',
+                    'v' => 0x0b,
+                    'f' => 0x0c,
+                    'r' => b'\r',
+                    '0'..='7' => {
+                        let mut value = escaped.to_digit(8).unwrap_or(0);
+                        for _ in 0..2 {
+                            let (_, digit) = chars.next().ok_or_else(unterminated)?;
+                            let digit = digit
+                                .to_digit(8)
+                                .ok_or_else(|| format!("Invalid escape in quoted path: {text}"))?;
+                            value = value * 8 + digit;
+                        }
+                        u8::try_from(value)
+                            .map_err(|_| format!("Invalid escape in quoted path: {text}"))?
+                    }
+                    other => {
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+                        continue;
+                    }
+                };
+                bytes.push(byte);
+            }
+            other => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    Err(unterminated())
+}
+
+fn strip_prefix(path: String, prefix: &str) -> String {
+    match path.strip_prefix(prefix) {
+        Some(stripped) => stripped.to_string(),
+        None => path,
+    }
+}
+
+/// Parses the path on a `---`/`+++` line, dropping git's `a/`/`b/` prefix and
+/// anything after a tab (a timestamp from `diff -u`, or git's marker for
+/// paths with spaces). `None` means `/dev/null`.
+fn parse_header_path(text: &str, prefix: &str) -> Result<Option<String>, String> {
+    let path = if text.starts_with('"') {
+        take_quoted(text)?.0
+    } else {
+        text.split('\t').next().unwrap_or_default().to_string()
+    };
+    if path == DEV_NULL {
+        return Ok(None);
+    }
+    Ok(Some(strip_prefix(path, prefix)))
+}
+
+/// Parses the path after `rename from` and similar lines, which git writes
+/// without a prefix.
+fn parse_bare_path(text: &str) -> Result<String, String> {
+    if text.starts_with('"') {
+        Ok(take_quoted(text)?.0)
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// Splits the paths of a `diff --git a/<old> b/<new>` line. Unquoted paths
+/// may contain spaces, so prefer the split where both sides name the same
+/// file, as they do unless the file was renamed.
+fn parse_git_header(text: &str) -> Result<(String, String), String> {
+    let (old, rest) = if text.starts_with('"') {
+        take_quoted(text)?
+    } else if let Some(ix) = text.find(" \"") {
+        (text[..ix].to_string(), &text[ix..])
+    } else {
+        let splits = text
+            .match_indices(" b/")
+            .map(|(ix, _)| (&text[..ix], &text[ix + 1..]));
+        let (old, new) = splits
+            .clone()
+            .find(|(old, new)| old.strip_prefix("a/") == new.strip_prefix("b/"))
+            .or_else(|| splits.last())
+            .ok_or_else(|| format!("Invalid diff header: diff --git {text}"))?;
+        return Ok((
+            strip_prefix(old.into(), "a/"),
+            strip_prefix(new.into(), "b/"),
+        ));
+    };
+    let new = parse_bare_path(rest.trim_start())?;
+    Ok((strip_prefix(old, "a/"), strip_prefix(new, "b/")))
+}
+
+/// Parses `@@ -<start>[,<len>] +<start>[,<len>] @@`, returning each side's
+/// start and length.
+fn parse_hunk_header(line: &str) -> Option<((usize, usize), (usize, usize))> {
+    let ranges = line.strip_prefix("@@ -")?.split(" @@").next()?;
+    let (old, new) = ranges.split_once(" +")?;
+    let side = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    Some((side(old)?, side(new)?))
+}
+
+/// Parses a unified diff with one or more files. Git's extended headers are
+/// honored: `a/`/`b/` prefixes are stripped, quoted paths are decoded, and a
+/// `/dev/null` side or a `new file`/`deleted file` line marks the file added
+/// or deleted, with that side left empty and named after the other one.
+pub fn parse_patch(text: &str) -> Result<Vec<FileDiff>, String> {
+    let mut diffs = Vec::new();
+    let mut entry: Option<Entry> = None;
+    // Lines still expected in the current hunk, and the next line numbers.
+    let mut remaining = (0usize, 0usize);
+    let mut lineno = (0usize, 0usize);
+
+    for line in text.lines() {
+        if remaining.0 > 0 || remaining.1 > 0 {
+            let Some(current) = entry.as_mut() else {
+                unreachable!("hunks only start inside an entry");
+            };
+            let (tag, content) = match line.chars().next() {
+                Some('+') => (ChangeTag::Insert, &line[1..]),
+                Some('-') => (ChangeTag::Delete, &line[1..]),
+                Some(' ') => (ChangeTag::Equal, &line[1..]),
+                // Editors often strip the space from blank context lines.
+                None => (ChangeTag::Equal, ""),
+                Some('\\') => continue,
+                Some(_) => return Err(format!("Invalid line in hunk: {line}")),
+            };
+            let old_lineno = (tag != ChangeTag::Insert).then_some(lineno.0);
+            let new_lineno = (tag != ChangeTag::Delete).then_some(lineno.1);
+            if old_lineno.is_some() {
+                lineno.0 += 1;
+                remaining.0 = remaining.0.saturating_sub(1);
+            }
+            if new_lineno.is_some() {
+                lineno.1 += 1;
+                remaining.1 = remaining.1.saturating_sub(1);
+            }
+            current.lines.push(DiffLine {
+                tag,
+                old_lineno,
+                new_lineno,
+                content: Arc::from(content),
+            });
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            if let Some(done) = entry.take() {
+                diffs.push(done.into_diff()?);
+            }
+            let (old, new) = parse_git_header(rest)?;
+            entry = Some(Entry {
+                old_path: Some(old),
+                new_path: Some(new),
+                ..Entry::default()
+            });
+        } else if let Some(rest) = line.strip_prefix("--- ") {
+            if entry.as_ref().is_none_or(|e| e.seen_old_header) {
+                if let Some(done) = entry.take() {
+                    diffs.push(done.into_diff()?);
+                }
+                entry = Some(Entry::default());
+            }
+            let current = entry.as_mut().expect("entry was just started");
+            current.seen_old_header = true;
+            current.old_path = parse_header_path(rest, "a/")?;
+            if current.old_path.is_none() {
+                current.status = Some(FileStatus::Added);
+            }
+        } else if let Some(current) = entry.as_mut() {
+            if let Some(rest) = line.strip_prefix("+++ ") {
+                current.new_path = parse_header_path(rest, "b/")?;
+                if current.new_path.is_none() {
+                    current.status = Some(FileStatus::Deleted);
+                }
+            } else if line.starts_with("@@ ") {
+                let (old, new) = parse_hunk_header(line)
+                    .ok_or_else(|| format!("Invalid hunk header: {line}"))?;
+                remaining = (old.1, new.1);
+                lineno = (old.0, new.0);
+            } else if line.starts_with("new file mode ") {
+                current.status = Some(FileStatus::Added);
+            } else if line.starts_with("deleted file mode ") {
+                current.status = Some(FileStatus::Deleted);
+            } else if let Some(rest) = line
+                .strip_prefix("rename from ")
+                .or_else(|| line.strip_prefix("copy from "))
+            {
+                current.old_path = Some(parse_bare_path(rest)?);
+            } else if let Some(rest) = line
+                .strip_prefix("rename to ")
+                .or_else(|| line.strip_prefix("copy to "))
+            {
+                current.new_path = Some(parse_bare_path(rest)?);
+            }
+        }
+    }
+
+    if remaining.0 > 0 || remaining.1 > 0 {
+        return Err("Patch ends in the middle of a hunk".to_string());
+    }
+    if let Some(done) = entry {
+        diffs.push(done.into_diff()?);
+    }
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<FileDiff> {
+        let path = format!("{}/tests/patches/{name}", env!("CARGO_MANIFEST_DIR"));
+        let text = std::fs::read_to_string(&path).unwrap();
+        parse_patch(&text).unwrap()
+    }
+
+    fn numbered(diff: &FileDiff) -> Vec<(Option<usize>, Option<usize>, &str)> {
+        diff.lines
+            .iter()
+            .map(|l| (l.old_lineno, l.new_lineno, l.content.as_ref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_created_file() {
+        let diffs = fixture("created.patch");
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].old_path.as_ref(), "src/greeting.rs");
+        assert_eq!(diffs[0].new_path.as_ref(), "src/greeting.rs");
+        assert_eq!(diffs[0].status, Some(FileStatus::Added));
+        assert_eq!(diffs[0].line_number_sides(), (false, true));
+        assert_eq!(numbered(&diffs[0])[2], (None, Some(3), "}"));
+    }
+
+    #[test]
+    fn test_deleted_file_with_space_in_path() {
+        let diffs = fixture("deleted.patch");
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].old_path.as_ref(), "docs/old notes.md");
+        assert_eq!(diffs[0].new_path.as_ref(), "docs/old notes.md");
+        assert_eq!(diffs[0].status, Some(FileStatus::Deleted));
+        assert_eq!(
+            numbered(&diffs[0]),
+            vec![
+                (Some(1), None, "# Notes"),
+                (Some(2), None, "Nothing to see here.")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renamed_files() {
+        let diffs = fixture("renamed.patch");
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].old_path.as_ref(), "src/util.rs");
+        assert_eq!(diffs[0].new_path.as_ref(), "src/helpers.rs");
+        assert_eq!(diffs[0].status, Some(FileStatus::Modified));
+        let lines = numbered(&diffs[0]);
+        assert_eq!(lines[1], (Some(2), None, "    v.max(0)"));
+        assert_eq!(lines[3], (Some(3), Some(3), "}"));
+        assert_eq!(lines[4], (Some(4), Some(4), ""));
+        assert_eq!(
+            lines[6],
+            (None, Some(11), "    // Saturates instead of overflowing.")
+        );
+        assert_eq!(lines[7], (Some(11), Some(12), "    v.saturating_mul(2)"));
+
+        assert_eq!(diffs[1].old_path.as_ref(), "café \"menu\".txt");
+        assert_eq!(diffs[1].new_path.as_ref(), "café\tmenu.txt");
+        assert!(diffs[1].lines.is_empty());
+    }
+
+    #[test]
+    fn test_plain_unified_diff() {
+        let text = "--- old.txt\t2024-01-01 00:00:00\n+++ new.txt\t2024-01-02 00:00:00\n\
+                    @@ -1 +1 @@\n-a\n+b\n--- x\n+++ y\n@@ -1,0 +2 @@\n+c\n\\ No newline at end of file\n";
+        let diffs = parse_patch(text).unwrap();
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].old_path.as_ref(), "old.txt");
+        assert_eq!(diffs[0].new_path.as_ref(), "new.txt");
+        assert_eq!(numbered(&diffs[1]), vec![(None, Some(2), "c")]);
+
+        assert!(parse_patch("--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n").is_err());
+        assert!(parse_patch("diff --git \"a/x b/x\n").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::ops::Range;
+
+use crate::diff::FileDiff;
+
+/// A search hit: an index into [`FileDiff::lines`] and the byte range of the
+/// match within that line's content.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub range: Range<usize>,
+}
+
+fn eq_ignoring_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Non-overlapping occurrences of `query` in `content`, left to right.
+pub fn find_in_line(content: &str, query: &str, ignore_case: bool) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    if !ignore_case {
+        return content
+            .match_indices(query)
+            .map(|(start, m)| start..start + m.len())
+            .collect();
+    }
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < content.len() {
+        let mut haystack = content[start..].char_indices();
+        let mut end = Some(start);
+        for q in query.chars() {
+            end = match haystack.next() {
+                Some((offset, c)) if eq_ignoring_case(c, q) => Some(start + offset + c.len_utf8()),
+                _ => None,
+            };
+            if end.is_none() {
+                break;
+            }
+        }
+        let next_char = content[start..].chars().next().map_or(1, char::len_utf8);
+        match end {
+            Some(end) => {
+                ranges.push(start..end);
+                start = end;
+            }
+            None => start += next_char,
+        }
+    }
+    ranges
+}
+
+/// All matches of `query` in the given lines of `diff`, in the order the
+/// lines are listed.
+pub fn find_matches(
+    diff: &FileDiff,
+    lines: &[usize],
+    query: &str,
+    ignore_case: bool,
+) -> Vec<SearchMatch> {
+    lines
+        .iter()
+        .flat_map(|&line| {
+            find_in_line(&diff.lines[line].content, query, ignore_case)
+                .into_iter()
+                .map(move |range| SearchMatch { line, range })
+        })
+        .collect()
+}
+
+/// Moves from the `active` match to the next or previous one of `count`,
+/// wrapping around at either end. Also returns whether it wrapped.
+pub fn step_match(active: Option<usize>, count: usize, forward: bool) -> Option<(usize, bool)> {
+    if count == 0 {
+        return None;
+    }
+    Some(match (active, forward) {
+        (None, true) => (0, false),
+        (None, false) => (count - 1, false),
+        (Some(ix), true) if ix + 1 >= count => (0, true),
+        (Some(ix), true) => (ix + 1, false),
+        (Some(0), false) => (count - 1, true),
+        (Some(ix), false) => (ix.min(count) - 1, false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_in_line() {
+        assert_eq!(find_in_line("foo bar foo", "foo", false), vec![0..3, 8..11]);
+        assert!(find_in_line("Foo fOO", "foo", false).is_empty());
+        assert_eq!(find_in_line("Foo fOO", "foo", true), vec![0..3, 4..7]);
+        assert_eq!(find_in_line("aaaa", "aa", true), vec![0..2, 2..4]);
+        assert_eq!(find_in_line("Ünïcode ü", "ü", true), vec![0..2, 10..12]);
+        assert!(find_in_line("anything", "", true).is_empty());
+    }
+
+    #[test]
+    fn test_find_matches_follows_line_order() {
+        let diff = FileDiff::from_contents("a", "b", "x\nkeep x\n", "y x\nkeep x\n");
+        let visible: Vec<usize> = (0..diff.lines.len()).collect();
+        let lines: Vec<usize> = find_matches(&diff, &visible, "x", false)
+            .into_iter()
+            .map(|m| m.line)
+            .collect();
+        assert_eq!(lines, vec![0, 1, 2]);
+        assert!(find_matches(&diff, &[2], "y", false).is_empty());
+    }
+
+    #[test]
+    fn test_step_match_wraps() {
+        assert_eq!(step_match(None, 0, true), None);
+        assert_eq!(step_match(None, 3, true), Some((0, false)));
+        assert_eq!(step_match(Some(1), 3, true), Some((2, false)));
+        assert_eq!(step_match(Some(2), 3, true), Some((0, true)));
+        assert_eq!(step_match(Some(0), 3, false), Some((2, true)));
+        assert_eq!(step_match(None, 3, false), Some((2, false)));
+    }
+}
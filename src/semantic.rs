@@ -0,0 +1,110 @@
+//! Rewrites structured config files into a canonical form before they are
+//! line-diffed, so changes that only reorder keys or reformat disappear.
+
+use serde_json::Value;
+
+/// A file type whose contents can be parsed and written back canonically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// The format named by `path`'s extension, if any.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let (_, ext) = path.rsplit_once('.')?;
+        if ext.eq_ignore_ascii_case("json") {
+            Some(Self::Json)
+        } else if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") {
+            Some(Self::Yaml)
+        } else {
+            None
+        }
+    }
+
+    /// Parses `content` and writes it back with object keys sorted and
+    /// the format's standard indentation. Blank content stays empty, so an
+    /// added or deleted file still diffs against nothing.
+    pub fn canonicalize(self, content: &str) -> Result<String, String> {
+        if content.trim().is_empty() {
+            return Ok(String::new());
+        }
+        let mut value: Value = match self {
+            Self::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+            Self::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?,
+        };
+        value.sort_all_objects();
+        match self {
+            Self::Json => {
+                let text = serde_json::to_string_pretty(&value).map_err(|e| e.to_string())?;
+                Ok(text + "\n")
+            }
+            Self::Yaml => serde_yaml::to_string(&value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Both sides of a file rewritten by [`Format::canonicalize`], picking the
+/// format from the new path, or the old one for files that lost their
+/// extension. `None` if neither path names a format or either side fails
+/// to parse, in which case the raw texts should be diffed instead.
+pub fn canonicalize_pair(
+    old_path: &str,
+    new_path: &str,
+    old_content: &str,
+    new_content: &str,
+) -> Option<(String, String)> {
+    let format = Format::from_path(new_path).or_else(|| Format::from_path(old_path))?;
+    let old = format.canonicalize(old_content).ok()?;
+    let new = format.canonicalize(new_content).ok()?;
+    Some((old, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("package.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("ci/deploy.YML"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("config.yaml"), Some(Format::Yaml));
+        assert_eq!(Format::from_path("main.rs"), None);
+        assert_eq!(Format::from_path("Makefile"), None);
+    }
+
+    #[test]
+    fn test_canonicalize_sorts_keys() {
+        let (old, new) = canonicalize_pair(
+            "a.json",
+            "a.json",
+            r#"{"b": 1, "a": {"y": [1, 2], "x": null}}"#,
+            "{\n  \"a\": {\"x\": null, \"y\": [1, 2]},\n  \"b\": 1\n}\n",
+        )
+        .unwrap();
+        assert_eq!(old, new);
+        assert_eq!(
+            old,
+            "{\n  \"a\": {\n    \"x\": null,\n    \"y\": [\n      1,\n      2\n    ]\n  },\n  \"b\": 1\n}\n"
+        );
+
+        let (old, new) =
+            canonicalize_pair("a.yml", "a.yml", "b: 1\na: two\n", "a: two\nb: 2\n").unwrap();
+        assert_eq!(old, "a: two\nb: 1\n");
+        assert_eq!(new, "a: two\nb: 2\n");
+    }
+
+    #[test]
+    fn test_canonicalize_pair_falls_back() {
+        assert_eq!(
+            canonicalize_pair("a.json", "a.json", "{}", "{\"a\": "),
+            None
+        );
+        assert_eq!(canonicalize_pair("a.txt", "a.txt", "{}", "{}"), None);
+        assert_eq!(
+            canonicalize_pair("a.json", "a.json", "", "{\"a\": 1}"),
+            Some((String::new(), "{\n  \"a\": 1\n}\n".to_string()))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoState {
+    pub collapsed_dirs: HashSet<String>,
+    pub reviewed_files: HashSet<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub zoom: f32,
+    /// Whether the file panel starts as a tree rather than a list. Unset
+    /// until the user picks one; the viewer then decides by file count.
+    pub tree_panel: Option<bool>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            tree_panel: None,
+        }
+    }
+}
+
+fn state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("gpui-diff-tool"))
+}
+
+// FNV-1a, so the file name for a repo stays stable across builds.
+fn path_hash(path: &str) -> u64 {
+    path.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn repo_state_path(repo_root: &str) -> Option<PathBuf> {
+    Some(
+        state_dir()?
+            .join("repos")
+            .join(format!("{:016x}.json", path_hash(repo_root))),
+    )
+}
+
+fn preferences_path() -> Option<PathBuf> {
+    Some(state_dir()?.join("preferences.json"))
+}
+
+fn load_json<T: DeserializeOwned + Default>(path: Option<PathBuf>) -> T {
+    path.and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn save_json<T: Serialize>(value: &T, path: Option<PathBuf>) -> Result<(), String> {
+    let path = path.ok_or("No state directory available")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+    let text = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize state: {e}"))?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+impl RepoState {
+    pub fn load(repo_root: &str) -> Self {
+        load_json(repo_state_path(repo_root))
+    }
+
+    pub fn save(&self, repo_root: &str) -> Result<(), String> {
+        save_json(self, repo_state_path(repo_root))
+    }
+}
+
+impl Preferences {
+    pub fn load() -> Self {
+        load_json(preferences_path())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        save_json(self, preferences_path())
+    }
+}
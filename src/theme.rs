@@ -0,0 +1,186 @@
+use gpui::{rgb, Rgba, SharedString};
+use similar::ChangeTag;
+
+use crate::diff::DiffLine;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThemePreset {
+    Default,
+    Colorblind,
+    Monochrome,
+}
+
+#[derive(Clone, Copy)]
+pub struct LineColors {
+    pub bg: Rgba,
+    pub text: Rgba,
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    pub insert: LineColors,
+    pub delete: LineColors,
+    pub equal: LineColors,
+    pub conflict: LineColors,
+    /// Background for leading or trailing whitespace that differs between
+    /// a removed line and the added line it's paired with.
+    pub whitespace_change: Rgba,
+    pub insert_sign: SharedString,
+    pub delete_sign: SharedString,
+}
+
+impl Theme {
+    pub fn preset(preset: ThemePreset) -> Self {
+        let (insert, delete) = match preset {
+            ThemePreset::Default => (
+                LineColors {
+                    bg: rgb(0x1b2e1b),
+                    text: rgb(0xa7ffa7),
+                },
+                LineColors {
+                    bg: rgb(0x3d1117),
+                    text: rgb(0xffa7a7),
+                },
+            ),
+            ThemePreset::Colorblind => (
+                LineColors {
+                    bg: rgb(0x102a3d),
+                    text: rgb(0x8ec5ff),
+                },
+                LineColors {
+                    bg: rgb(0x3d2a10),
+                    text: rgb(0xffb86c),
+                },
+            ),
+            ThemePreset::Monochrome => (
+                LineColors {
+                    bg: rgb(0x2a2a2a),
+                    text: rgb(0xd4d4d4),
+                },
+                LineColors {
+                    bg: rgb(0x252525),
+                    text: rgb(0xd4d4d4),
+                },
+            ),
+        };
+        let conflict = match preset {
+            ThemePreset::Monochrome => LineColors {
+                bg: rgb(0x333333),
+                text: rgb(0xffffff),
+            },
+            _ => LineColors {
+                bg: rgb(0x3b3214),
+                text: rgb(0xe5c07b),
+            },
+        };
+        let whitespace_change = match preset {
+            ThemePreset::Monochrome => rgb(0x5c5c5c),
+            _ => rgb(0x5b3f8c),
+        };
+        Self {
+            insert,
+            delete,
+            equal: LineColors {
+                bg: rgb(0x1e1e1e),
+                text: rgb(0xd4d4d4),
+            },
+            conflict,
+            whitespace_change,
+            insert_sign: "+".into(),
+            delete_sign: "-".into(),
+        }
+    }
+
+    /// The background, text color and gutter sign for a line with `tag`.
+    pub fn tag_style(&self, tag: ChangeTag) -> (Rgba, Rgba, SharedString) {
+        let (colors, sign) = match tag {
+            ChangeTag::Insert => (self.insert, self.insert_sign.clone()),
+            ChangeTag::Delete => (self.delete, self.delete_sign.clone()),
+            ChangeTag::Equal => (self.equal, " ".into()),
+        };
+        (colors.bg, colors.text, sign)
+    }
+
+    /// Like [`Theme::tag_style`], but merge conflict markers get the
+    /// conflict colors.
+    pub fn line_style(&self, line: &DiffLine) -> (Rgba, Rgba, SharedString) {
+        let (bg, text, sign) = self.tag_style(line.tag);
+        if line.is_conflict_marker() {
+            return (self.conflict.bg, self.conflict.text, sign);
+        }
+        (bg, text, sign)
+    }
+}
+
+/// Heatmap colors for lines last changed at `times` (see
+/// `git_blame_times`): cool blue for the file's oldest line through warm
+/// orange for its newest. Lines not committed yet count as newest.
+pub fn age_colors(times: &[Option<i64>]) -> Vec<Rgba> {
+    let (cool, warm) = (rgb(0x2f5d8a), rgb(0xd9822b));
+    let oldest = times.iter().flatten().min().copied().unwrap_or(0);
+    let newest = times.iter().flatten().max().copied().unwrap_or(0);
+    let span = (newest - oldest).max(1) as f32;
+    times
+        .iter()
+        .map(|time| {
+            let t = time.map_or(1.0, |time| (time - oldest) as f32 / span);
+            let mix = |from: f32, to: f32| from * (1.0 - t) + to * t;
+            Rgba {
+                r: mix(cool.r, warm.r),
+                g: mix(cool.g, warm.g),
+                b: mix(cool.b, warm.b),
+                a: 1.0,
+            }
+        })
+        .collect()
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::preset(ThemePreset::Default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::FileDiff;
+
+    #[test]
+    fn test_tag_style() {
+        let mut theme = Theme::preset(ThemePreset::Colorblind);
+        theme.insert_sign = "▶".into();
+        let (bg, text, sign) = theme.tag_style(ChangeTag::Insert);
+        assert_eq!(
+            (bg, text, sign.as_ref()),
+            (rgb(0x102a3d), rgb(0x8ec5ff), "▶")
+        );
+        let (bg, text, sign) = theme.tag_style(ChangeTag::Delete);
+        assert_eq!(
+            (bg, text, sign.as_ref()),
+            (rgb(0x3d2a10), rgb(0xffb86c), "-")
+        );
+        let (bg, text, sign) = theme.tag_style(ChangeTag::Equal);
+        assert_eq!(
+            (bg, text, sign.as_ref()),
+            (rgb(0x1e1e1e), rgb(0xd4d4d4), " ")
+        );
+
+        let diff = FileDiff::from_contents("a", "b", "x\n", "x\n=======\n");
+        let (bg, text, sign) = theme.line_style(&diff.lines[1]);
+        assert_eq!(
+            (bg, text, sign.as_ref()),
+            (rgb(0x3b3214), rgb(0xe5c07b), "▶")
+        );
+    }
+
+    #[test]
+    fn test_age_colors() {
+        let colors = age_colors(&[Some(100), Some(200), None, Some(150)]);
+        assert_eq!(colors[0], rgb(0x2f5d8a));
+        assert_eq!(colors[1], rgb(0xd9822b));
+        assert_eq!(colors[2], colors[1]);
+        assert!(colors[3].r > colors[0].r && colors[3].r < colors[1].r);
+        assert_eq!(age_colors(&[Some(5), Some(5)]), vec![rgb(0x2f5d8a); 2]);
+    }
+}
@@ -0,0 +1,34 @@
+pub fn truncate_middle(path: &str, max_chars: usize) -> String {
+    let len = path.chars().count();
+    if len <= max_chars {
+        return path.to_string();
+    }
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let file_len = file.chars().count();
+    if dir.is_empty() || file_len + 2 > max_chars {
+        let tail: String = path.chars().skip(len + 1 - max_chars.max(1)).collect();
+        return format!("…{tail}");
+    }
+    let prefix: String = dir.chars().take(max_chars - file_len - 2).collect();
+    let prefix = match prefix.rfind('/') {
+        Some(ix) => &prefix[..=ix],
+        None => &prefix,
+    };
+    format!("{prefix}…/{file}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_middle_keeps_file_name() {
+        let path = "src/very/long/nested/directory/file.rs";
+        assert_eq!(truncate_middle(path, 100), path);
+        assert_eq!(truncate_middle(path, 20), "src/very/…/file.rs");
+        assert_eq!(truncate_middle(path, 12), "src…/file.rs");
+        assert_eq!(truncate_middle(path, 6), "…le.rs");
+        assert_eq!(truncate_middle("a_very_long_file_name.rs", 8), "…name.rs");
+        assert_eq!(truncate_middle("dir/ünïcödé.rs", 13), "d…/ünïcödé.rs");
+    }
+}
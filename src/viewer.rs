@@ -1,30 +1,657 @@
-use std::collections::{BTreeMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::{channel::mpsc, StreamExt};
+use globset::GlobSet;
 use gpui::{
-    div, prelude::*, px, rgb, CursorStyle, ElementId, Pixels, SharedString, Window,
-    Context,
+    actions, anchored, deferred, div, font, img, point, prelude::*, px, rgb, rgba, Action, App,
+    ClickEvent, ClipboardItem, Context, CursorStyle, Div, ElementId, FocusHandle, HighlightStyle,
+    Img, InteractiveText, KeyBinding, KeyDownEvent, MouseButton, MouseDownEvent,
+    NavigationDirection, Pixels, Point, Rgba, ScrollHandle, SharedString, Stateful, StyledText,
+    Subscription, TextRun, Window,
 };
+use notify::RecommendedWatcher;
 use similar::ChangeTag;
 
-use crate::diff::{to_side_by_side, DiffLine, FileDiff, SideBySideLine};
+use crate::diff::{DiffLine, DiffOptions, FileDiff, FileStatus, SideBySideLine, Staging};
+use crate::git::{git_blame_times, BlameSource, SystemGit};
+use crate::layout::{
+    line_at_row, row_of_line, window_title, LayoutOptions, Row, SideBySideAlignment, ViewMode,
+};
+use crate::palette::{filter_by_query, format_keystrokes};
+use crate::search::{find_in_line, find_matches, step_match, SearchMatch};
+use crate::state::{Preferences, RepoState};
+use crate::theme::{age_colors, Theme};
+use crate::truncate::truncate_middle;
+use crate::watch::{settled_change, start_watcher, WatchTarget, WATCH_DEBOUNCE};
 
 pub const MIN_PANEL_WIDTH: f32 = 100.0;
-pub const MAX_PANEL_WIDTH: f32 = 600.0;
+pub const MAX_PANEL_FRACTION: f32 = 0.7;
 pub const DEFAULT_PANEL_WIDTH: f32 = 220.0;
-pub const DRAG_HANDLE_WIDTH: f32 = 4.0;
+/// Width of the panel resize handle's hit zone. Only a
+/// [`DRAG_HANDLE_LINE_WIDTH`] line down its middle is drawn.
+pub const DRAG_HANDLE_WIDTH: f32 = 10.0;
+pub const DRAG_HANDLE_LINE_WIDTH: f32 = 4.0;
+pub const TOAST_DURATION: Duration = Duration::from_secs(2);
+pub const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+#[cfg(target_os = "macos")]
+pub const DEFAULT_FONT_FAMILY: &str = "Menlo";
+#[cfg(target_os = "windows")]
+pub const DEFAULT_FONT_FAMILY: &str = "Consolas";
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub const DEFAULT_FONT_FAMILY: &str = "DejaVu Sans Mono";
+pub const DEFAULT_FONT_SIZE: f32 = 13.0;
+pub const ZOOM_STEP: f32 = 0.1;
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 3.0;
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+pub const MAX_HISTORY: usize = 50;
+pub const FILE_NAME_TEXT_SIZE: f32 = 12.0;
+pub const FILE_ITEM_CHROME_WIDTH: f32 = 64.0;
+/// Width of a unified diff row besides its gutters and text: the focus
+/// border, sign column, padding and copy button.
+pub const DIFF_LINE_CHROME_WIDTH: f32 = 56.0;
+/// Changesets with more files than this start with the tree panel unless the
+/// user picked a panel mode.
+pub const AUTO_TREE_MIN_FILES: usize = 20;
+/// Lines of context added or removed by the controls on `@@` headers.
+pub const CONTEXT_STEP: usize = 3;
+/// Set when loading in the window fails; the app then quits and the process
+/// exits with an error status.
+pub static LOAD_FAILED: AtomicBool = AtomicBool::new(false);
+/// Toolbar buttons reachable with Tab, in tab order, with the label shown
+/// when hovering them. The diff and then the file panel follow.
+const TOOLBAR_BUTTONS: &[(&str, &str)] = &[
+    ("btn-unified", "Switch to unified view"),
+    ("btn-side-by-side", "Switch to side-by-side view"),
+    ("btn-changes-only", "Show only added and removed lines"),
+    ("btn-all-files", "Show every file in one scroll"),
+    ("btn-line-numbers", "Show or hide line numbers"),
+    (
+        "btn-ignore-blank-lines",
+        "Hide added or removed blank lines",
+    ),
+    ("btn-reverse", "Swap the old and new sides"),
+    (
+        "btn-deleted-content",
+        "Show the deleted file as its old content",
+    ),
+    ("btn-full-file", "Show every line of this file"),
+    ("btn-copy-old", "Copy the old version of this file"),
+    ("btn-copy-new", "Copy the new version of this file"),
+];
+
+actions!(
+    diff_viewer,
+    [
+        SelectPrevLine,
+        SelectNextLine,
+        ClearWordHighlight,
+        OpenCommandPalette,
+        PalettePrev,
+        PaletteNext,
+        ConfirmPalette,
+        DismissPalette,
+        ReverseDiff,
+        NextConflictMarker,
+        PrevConflictMarker,
+        SelectPrevTreeNode,
+        SelectNextTreeNode,
+        ExpandTreeNode,
+        CollapseTreeNode,
+        OpenTreeNode,
+        BlurFileTree,
+        ScrollToTop,
+        ScrollToBottom,
+        ScrollPageUp,
+        ScrollPageDown,
+        NavigateBack,
+        NavigateForward,
+        ZoomIn,
+        ZoomOut,
+        ResetZoom,
+        ExtendSelectionUp,
+        ExtendSelectionDown,
+        CopySelection,
+        ToggleLineWrap,
+        TogglePanel,
+        OpenSearch,
+        SearchNext,
+        SearchPrev,
+        DismissSearch,
+        ToggleSearchCase,
+        ToggleShortcutHelp,
+        FocusNext,
+        FocusPrevious
+    ]
+);
+
+/// A key binding and the text listing it in the shortcut help.
+struct Shortcut {
+    binding: KeyBinding,
+    keys: &'static str,
+    context: &'static str,
+    description: &'static str,
+}
+
+/// A shortcut description with every key combination bound to it.
+type ShortcutKeys = (&'static str, Vec<String>);
+
+fn shortcut<A: Action>(
+    keys: &'static str,
+    action: A,
+    context: &'static str,
+    description: &'static str,
+) -> Shortcut {
+    Shortcut {
+        binding: KeyBinding::new(keys, action, Some(context)),
+        keys,
+        context,
+        description,
+    }
+}
+
+/// Every key binding, used both to bind keys and to list them in the
+/// shortcut help.
+fn shortcuts() -> Vec<Shortcut> {
+    vec![
+        shortcut(
+            "up",
+            SelectPrevLine,
+            "DiffViewer",
+            "Focus the previous line",
+        ),
+        shortcut("down", SelectNextLine, "DiffViewer", "Focus the next line"),
+        shortcut(
+            "shift-up",
+            ExtendSelectionUp,
+            "DiffViewer",
+            "Extend the selection up",
+        ),
+        shortcut(
+            "shift-down",
+            ExtendSelectionDown,
+            "DiffViewer",
+            "Extend the selection down",
+        ),
+        shortcut(
+            "cmd-c",
+            CopySelection,
+            "DiffViewer",
+            "Copy the selected lines",
+        ),
+        shortcut(
+            "ctrl-c",
+            CopySelection,
+            "DiffViewer",
+            "Copy the selected lines",
+        ),
+        shortcut("alt-z", ToggleLineWrap, "DiffViewer", "Toggle line wrap"),
+        shortcut("cmd-b", TogglePanel, "DiffViewer", "Toggle the file panel"),
+        shortcut(
+            "cmd-f",
+            OpenSearch,
+            "DiffViewer",
+            "Search in the current file",
+        ),
+        shortcut(
+            "ctrl-f",
+            OpenSearch,
+            "DiffViewer",
+            "Search in the current file",
+        ),
+        shortcut("f3", SearchNext, "DiffViewer", "Next match"),
+        shortcut("shift-f3", SearchPrev, "DiffViewer", "Previous match"),
+        shortcut("ctrl-b", TogglePanel, "DiffViewer", "Toggle the file panel"),
+        shortcut(
+            "escape",
+            ClearWordHighlight,
+            "DiffViewer",
+            "Clear the word highlight",
+        ),
+        shortcut(
+            "cmd-shift-p",
+            OpenCommandPalette,
+            "DiffViewer",
+            "Open the command palette",
+        ),
+        shortcut(
+            "ctrl-shift-p",
+            OpenCommandPalette,
+            "DiffViewer",
+            "Open the command palette",
+        ),
+        shortcut(
+            "?",
+            ToggleShortcutHelp,
+            "DiffViewer",
+            "Show keyboard shortcuts",
+        ),
+        shortcut("tab", FocusNext, "DiffViewer", "Focus the next control"),
+        shortcut(
+            "shift-tab",
+            FocusPrevious,
+            "DiffViewer",
+            "Focus the previous control",
+        ),
+        shortcut("r", ReverseDiff, "DiffViewer", "Reverse the current diff"),
+        shortcut(
+            "f8",
+            NextConflictMarker,
+            "DiffViewer",
+            "Next conflict marker",
+        ),
+        shortcut(
+            "shift-f8",
+            PrevConflictMarker,
+            "DiffViewer",
+            "Previous conflict marker",
+        ),
+        shortcut("g", ScrollToTop, "DiffViewer", "Scroll to the top"),
+        shortcut("home", ScrollToTop, "DiffViewer", "Scroll to the top"),
+        shortcut(
+            "shift-g",
+            ScrollToBottom,
+            "DiffViewer",
+            "Scroll to the bottom",
+        ),
+        shortcut("end", ScrollToBottom, "DiffViewer", "Scroll to the bottom"),
+        shortcut("pageup", ScrollPageUp, "DiffViewer", "Scroll up one page"),
+        shortcut(
+            "pagedown",
+            ScrollPageDown,
+            "DiffViewer",
+            "Scroll down one page",
+        ),
+        shortcut(
+            "cmd-[",
+            NavigateBack,
+            "DiffViewer",
+            "Go back to the previous file",
+        ),
+        shortcut(
+            "cmd-]",
+            NavigateForward,
+            "DiffViewer",
+            "Go forward to the next file",
+        ),
+        shortcut(
+            "alt-left",
+            NavigateBack,
+            "DiffViewer",
+            "Go back to the previous file",
+        ),
+        shortcut(
+            "alt-right",
+            NavigateForward,
+            "DiffViewer",
+            "Go forward to the next file",
+        ),
+        shortcut("cmd-=", ZoomIn, "DiffViewer", "Zoom in"),
+        shortcut("cmd-+", ZoomIn, "DiffViewer", "Zoom in"),
+        shortcut("cmd--", ZoomOut, "DiffViewer", "Zoom out"),
+        shortcut("cmd-0", ResetZoom, "DiffViewer", "Reset zoom"),
+        shortcut("ctrl-=", ZoomIn, "DiffViewer", "Zoom in"),
+        shortcut("ctrl-+", ZoomIn, "DiffViewer", "Zoom in"),
+        shortcut("ctrl--", ZoomOut, "DiffViewer", "Zoom out"),
+        shortcut("ctrl-0", ResetZoom, "DiffViewer", "Reset zoom"),
+        shortcut("up", SelectPrevTreeNode, "FileTree", "Move up"),
+        shortcut("down", SelectNextTreeNode, "FileTree", "Move down"),
+        shortcut(
+            "right",
+            ExpandTreeNode,
+            "FileTree",
+            "Expand a folder or step into it",
+        ),
+        shortcut(
+            "left",
+            CollapseTreeNode,
+            "FileTree",
+            "Collapse a folder or go to its parent",
+        ),
+        shortcut("enter", OpenTreeNode, "FileTree", "Open the file"),
+        shortcut("escape", BlurFileTree, "FileTree", "Return to the diff"),
+        shortcut("up", PalettePrev, "CommandPalette", "Previous command"),
+        shortcut("down", PaletteNext, "CommandPalette", "Next command"),
+        shortcut("enter", ConfirmPalette, "CommandPalette", "Run the command"),
+        shortcut(
+            "escape",
+            DismissPalette,
+            "CommandPalette",
+            "Close the palette",
+        ),
+        shortcut("enter", SearchNext, "SearchBar", "Next match"),
+        shortcut("shift-enter", SearchPrev, "SearchBar", "Previous match"),
+        shortcut("escape", DismissSearch, "SearchBar", "Close the search bar"),
+        shortcut(
+            "alt-c",
+            ToggleSearchCase,
+            "SearchBar",
+            "Toggle case sensitivity",
+        ),
+        shortcut("?", ToggleShortcutHelp, "ShortcutHelp", "Close this help"),
+        shortcut(
+            "escape",
+            ToggleShortcutHelp,
+            "ShortcutHelp",
+            "Close this help",
+        ),
+    ]
+}
+
+pub fn bind_keys(cx: &mut App) {
+    cx.bind_keys(shortcuts().into_iter().map(|shortcut| shortcut.binding));
+}
+
+struct PaletteCommand {
+    name: &'static str,
+    run: fn(&mut DiffViewer, &mut Window, &mut Context<DiffViewer>),
+}
+
+fn palette_commands() -> Vec<PaletteCommand> {
+    vec![
+        PaletteCommand {
+            name: "Show Keyboard Shortcuts",
+            run: |this, window, cx| this.toggle_shortcut_help(&ToggleShortcutHelp, window, cx),
+        },
+        PaletteCommand {
+            name: "Toggle Unified / Side-by-Side",
+            run: |this, _window, _cx| {
+                this.set_view_mode(match this.view_mode {
+                    ViewMode::Unified => ViewMode::SideBySide,
+                    ViewMode::SideBySide | ViewMode::ChangesOnly => ViewMode::Unified,
+                });
+            },
+        },
+        PaletteCommand {
+            name: "Swap Side-by-Side Columns",
+            run: |this, _window, _cx| this.sbs_new_left = !this.sbs_new_left,
+        },
+        PaletteCommand {
+            name: "Show Changes Only",
+            run: |this, _window, _cx| this.set_view_mode(ViewMode::ChangesOnly),
+        },
+        PaletteCommand {
+            name: "Toggle All Files View",
+            run: |this, _window, _cx| this.toggle_all_files(),
+        },
+        PaletteCommand {
+            name: "Toggle List / Tree Panel",
+            run: |this, _window, _cx| {
+                this.choose_panel_mode(match this.panel_mode {
+                    PanelMode::List => PanelMode::Tree,
+                    PanelMode::Tree => PanelMode::List,
+                });
+            },
+        },
+        PaletteCommand {
+            name: "Focus File Tree",
+            run: |this, window, _cx| {
+                this.panel_visible = true;
+                this.panel_mode = PanelMode::Tree;
+                this.tree_cursor.get_or_insert(0);
+                this.tree_focus_handle.focus(window);
+            },
+        },
+        PaletteCommand {
+            name: "Go Back",
+            run: |this, _window, cx| this.navigate_history(true, cx),
+        },
+        PaletteCommand {
+            name: "Go Forward",
+            run: |this, _window, cx| this.navigate_history(false, cx),
+        },
+        PaletteCommand {
+            name: "Zoom In",
+            run: |this, _window, cx| this.set_zoom(this.zoom + ZOOM_STEP, cx),
+        },
+        PaletteCommand {
+            name: "Zoom Out",
+            run: |this, _window, cx| this.set_zoom(this.zoom - ZOOM_STEP, cx),
+        },
+        PaletteCommand {
+            name: "Reset Zoom",
+            run: |this, _window, cx| this.set_zoom(1.0, cx),
+        },
+        PaletteCommand {
+            name: "Toggle Image Overlay",
+            run: |this, _window, _cx| this.toggle_image_compare(),
+        },
+        PaletteCommand {
+            name: "Toggle Line Numbers",
+            run: |this, _window, _cx| this.show_line_numbers = !this.show_line_numbers,
+        },
+        PaletteCommand {
+            name: "Toggle Indent Guides",
+            run: |this, _window, _cx| this.show_indent_guides = !this.show_indent_guides,
+        },
+        PaletteCommand {
+            name: "Toggle Ignore Blank Lines",
+            run: |this, _window, _cx| {
+                this.ignore_blank_lines = !this.ignore_blank_lines;
+                this.clear_hidden_focus();
+            },
+        },
+        PaletteCommand {
+            name: "Toggle Ignore Case",
+            run: |this, _window, cx| this.set_ignore_case(!this.ignore_case, cx),
+        },
+        PaletteCommand {
+            name: "Toggle Semantic JSON/YAML Diff",
+            run: |this, _window, cx| this.set_semantic(!this.semantic, cx),
+        },
+        PaletteCommand {
+            name: "Toggle File Reviewed",
+            run: |this, _window, cx| {
+                if let Some(index) = this.selected_index {
+                    this.toggle_reviewed(index, cx);
+                }
+            },
+        },
+        PaletteCommand {
+            name: "Toggle Deleted File as Plain Content",
+            run: |this, _window, cx| {
+                if let Some(index) = this.selected_index {
+                    this.toggle_deleted_content(index, cx);
+                }
+            },
+        },
+        PaletteCommand {
+            name: "Copy Old Content",
+            run: |this, _window, cx| this.copy_side_content(false, cx),
+        },
+        PaletteCommand {
+            name: "Copy New Content",
+            run: |this, _window, cx| this.copy_side_content(true, cx),
+        },
+        PaletteCommand {
+            name: "Toggle Age Heatmap",
+            run: |this, _window, cx| this.toggle_age_heatmap(cx),
+        },
+        PaletteCommand {
+            name: "Toggle Full File",
+            run: |this, _window, cx| {
+                if let Some(index) = this.selected_index {
+                    this.toggle_full_file(index, cx);
+                }
+            },
+        },
+        PaletteCommand {
+            name: "Toggle All Deleted Files as Plain Content",
+            run: |this, _window, _cx| {
+                this.deleted_as_content = !this.deleted_as_content;
+                this.deleted_content_overrides.clear();
+            },
+        },
+        PaletteCommand {
+            name: "Toggle Reviewed Files Last",
+            run: |this, _window, _cx| this.reviewed_last = !this.reviewed_last,
+        },
+        PaletteCommand {
+            name: "Next Conflict Marker",
+            run: |this, _window, cx| this.jump_to_conflict_marker(true, cx),
+        },
+        PaletteCommand {
+            name: "Previous Conflict Marker",
+            run: |this, _window, cx| this.jump_to_conflict_marker(false, cx),
+        },
+        PaletteCommand {
+            name: "Reverse Diff",
+            run: |this, _window, cx| this.reverse_selected_diff(cx),
+        },
+        PaletteCommand {
+            name: "Toggle Generated Files",
+            run: |this, _window, cx| this.toggle_generated(cx),
+        },
+        PaletteCommand {
+            name: "Find in File",
+            run: |this, window, cx| this.open_search(&OpenSearch, window, cx),
+        },
+        PaletteCommand {
+            name: "Toggle File Panel",
+            run: |this, window, cx| this.toggle_panel(&TogglePanel, window, cx),
+        },
+        PaletteCommand {
+            name: "Toggle Unchanged Files",
+            run: |this, _window, cx| this.toggle_unchanged(cx),
+        },
+        PaletteCommand {
+            name: "Toggle Changed / Shown Lines",
+            run: |this, _window, _cx| this.show_line_ratio = !this.show_line_ratio,
+        },
+        PaletteCommand {
+            name: "Clear Word Highlight",
+            run: |this, _window, _cx| this.highlighted_word = None,
+        },
+        PaletteCommand {
+            name: "Copy Focused Line",
+            run: |this, _window, cx| {
+                let content = this
+                    .selected_diff()
+                    .zip(this.focused_line)
+                    .and_then(|(diff, ix)| diff.lines.get(ix))
+                    .map(|line| line.content.to_string());
+                if let Some(content) = content {
+                    this.copy_to_clipboard(content, "Copied line".to_string(), cx);
+                }
+            },
+        },
+        PaletteCommand {
+            name: "Copy Selected Lines",
+            run: |this, _window, cx| this.copy_selection(cx),
+        },
+        PaletteCommand {
+            name: "Copy Selected Hunks as Patch",
+            run: |this, _window, cx| this.copy_selected_hunks(cx),
+        },
+        PaletteCommand {
+            name: "Show More Context",
+            run: |this, _window, cx| {
+                if let Some(context) = this.context_lines {
+                    this.set_context_lines(Some(context.saturating_add(CONTEXT_STEP)), cx);
+                }
+            },
+        },
+        PaletteCommand {
+            name: "Show Less Context",
+            run: |this, _window, cx| {
+                let context = this
+                    .context_lines
+                    .map_or(CONTEXT_STEP, |context| context.saturating_sub(CONTEXT_STEP));
+                this.set_context_lines(Some(context), cx);
+            },
+        },
+        PaletteCommand {
+            name: "Clear Hunk Selection",
+            run: |this, _window, _cx| this.selected_hunks.clear(),
+        },
+        PaletteCommand {
+            name: "Toggle Line Wrap",
+            run: |this, _window, _cx| this.wrap_lines = !this.wrap_lines,
+        },
+        PaletteCommand {
+            name: "Copy File Path",
+            run: |this, _window, cx| {
+                if let Some(path) = this.selected_diff().map(|d| d.new_path.to_string()) {
+                    let message = format!("Copied {path}");
+                    this.copy_to_clipboard(path, message, cx);
+                }
+            },
+        },
+    ]
+}
+
+struct CommandPalette {
+    query: String,
+    selected: usize,
+    focus_handle: FocusHandle,
+}
+
+struct SearchBar {
+    query: String,
+    ignore_case: bool,
+    active: Option<SearchMatch>,
+    wrapped: bool,
+    focus_handle: FocusHandle,
+}
+
+struct FileContextMenu {
+    index: usize,
+    position: Point<Pixels>,
+}
+
+fn absolute_path(repo_root: Option<&str>, path: &str) -> String {
+    let path = match repo_root {
+        Some(root) => Path::new(root).join(path),
+        None => std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)),
+    };
+    path.to_string_lossy().into_owned()
+}
+
+fn word_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        let is_word = c.is_alphanumeric() || c == '_';
+        match (is_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push(s..text.len());
+    }
+    ranges
+}
 
 #[derive(Clone, Copy, PartialEq)]
-pub enum ViewMode {
-    Unified,
+pub enum ImageCompareMode {
     SideBySide,
+    Overlay,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PanelMode {
     List,
     Tree,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PanelSide {
+    Left,
+    Right,
+}
+
 pub struct PanelResizeDrag {
     pub initial_width: Pixels,
 }
@@ -35,6 +662,38 @@ impl Render for PanelResizeDrag {
     }
 }
 
+/// Sizes shared by every row of a unified diff.
+struct LineMetrics {
+    /// Index of the file the lines belong to.
+    file: usize,
+    /// Old and new line-number column widths; 0 hides a column.
+    gutter_widths: (f32, f32),
+    guide_step: Option<f32>,
+    /// When lines don't wrap, how many characters fit before a line is cut off.
+    visible_chars: Option<usize>,
+    /// Draw lines without change colors or signs, as plain file content.
+    plain: bool,
+}
+
+pub struct TextTooltip {
+    pub text: SharedString,
+}
+
+impl Render for TextTooltip {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .px(px(6.0))
+            .py(px(3.0))
+            .rounded(px(3.0))
+            .bg(rgb(0x3c3c3c))
+            .border_1()
+            .border_color(rgb(0x505050))
+            .text_size(px(11.0))
+            .text_color(rgb(0xcccccc))
+            .child(self.text.clone())
+    }
+}
+
 #[derive(Debug)]
 enum TreeNode {
     Directory {
@@ -46,6 +705,12 @@ enum TreeNode {
     },
 }
 
+#[derive(Clone, Debug, PartialEq)]
+enum TreeRow {
+    Dir(String),
+    File(usize),
+}
+
 fn insert_into_tree(root: &mut BTreeMap<String, TreeNode>, parts: &[&str], diff_index: usize) {
     if parts.is_empty() {
         return;
@@ -68,11 +733,79 @@ fn insert_into_tree(root: &mut BTreeMap<String, TreeNode>, parts: &[&str], diff_
     }
 }
 
-fn build_file_tree(diffs: &[FileDiff]) -> BTreeMap<String, TreeNode> {
+fn flatten_tree(
+    nodes: &BTreeMap<String, TreeNode>,
+    parent_path: &str,
+    collapsed_dirs: &HashSet<String>,
+    rows: &mut Vec<TreeRow>,
+) {
+    for node in nodes.values() {
+        if let TreeNode::Directory { name, children } = node {
+            let dir_path = if parent_path.is_empty() {
+                name.clone()
+            } else {
+                format!("{parent_path}/{name}")
+            };
+            let is_collapsed = collapsed_dirs.contains(&dir_path);
+            rows.push(TreeRow::Dir(dir_path.clone()));
+            if !is_collapsed {
+                flatten_tree(children, &dir_path, collapsed_dirs, rows);
+            }
+        }
+    }
+    for node in nodes.values() {
+        if let TreeNode::File { diff_index } = node {
+            rows.push(TreeRow::File(*diff_index));
+        }
+    }
+}
+
+fn dir_ancestors(path: &str) -> Vec<String> {
+    let parts: Vec<&str> = path.split('/').collect();
+    (1..parts.len()).map(|n| parts[..n].join("/")).collect()
+}
+
+fn all_dir_paths(diffs: &[FileDiff]) -> HashSet<String> {
+    diffs
+        .iter()
+        .flat_map(|d| dir_ancestors(&d.new_path))
+        .collect()
+}
+
+fn auto_panel_mode(file_count: usize) -> PanelMode {
+    if file_count > AUTO_TREE_MIN_FILES {
+        PanelMode::Tree
+    } else {
+        PanelMode::List
+    }
+}
+
+fn clamp_panel_width(width: Pixels, window_width: Pixels) -> Pixels {
+    let max_width = (window_width * MAX_PANEL_FRACTION).max(px(MIN_PANEL_WIDTH));
+    width.max(px(MIN_PANEL_WIDTH)).min(max_width)
+}
+
+fn measure_text_width(text: &str, family: &SharedString, size: f32, window: &Window) -> Pixels {
+    let run = TextRun {
+        len: text.len(),
+        font: font(family.clone()),
+        color: window.text_style().color,
+        background_color: None,
+        underline: None,
+        strikethrough: None,
+    };
+    let text = SharedString::from(text.to_string());
+    window
+        .text_system()
+        .shape_line(text, px(size), &[run], None)
+        .width
+}
+
+fn build_file_tree(diffs: &[FileDiff], indices: &[usize]) -> BTreeMap<String, TreeNode> {
     let mut root: BTreeMap<String, TreeNode> = BTreeMap::new();
 
-    for (i, diff) in diffs.iter().enumerate() {
-        let path = diff.new_path.to_string();
+    for &i in indices {
+        let path = diffs[i].new_path.to_string();
         let parts: Vec<&str> = path.split('/').collect();
         insert_into_tree(&mut root, &parts, i);
     }
@@ -80,264 +813,2794 @@ fn build_file_tree(diffs: &[FileDiff]) -> BTreeMap<String, TreeNode> {
     root
 }
 
+/// A file's rendered rows and the layout options they were built for.
+type CachedRows = (LayoutOptions, Rc<Vec<Row>>);
+
 pub struct DiffViewer {
     pub diffs: Vec<FileDiff>,
     pub selected_index: Option<usize>,
     pub panel_width: Pixels,
     pub view_mode: ViewMode,
     pub panel_mode: PanelMode,
+    panel_mode_fixed: bool,
     pub collapsed_dirs: HashSet<String>,
+    pub tree_cursor: Option<usize>,
+    pub tree_focus_handle: FocusHandle,
+    toolbar_focus: HashMap<&'static str, FocusHandle>,
+    pub repo_root: Option<String>,
+    window_title: String,
+    pub focused_line: Option<usize>,
+    selection_anchor: Option<usize>,
+    pub focus_handle: FocusHandle,
+    pub toast: Option<SharedString>,
+    toast_epoch: usize,
+    pub loading: Option<(usize, usize)>,
+    pub show_line_numbers: bool,
+    pub show_indent_guides: bool,
+    pub tab_width: usize,
+    pub font_family: SharedString,
+    pub font_size: f32,
+    pub zoom: f32,
+    pub generated_patterns: GlobSet,
+    pub show_generated: bool,
+    pub show_unchanged: bool,
+    pub source_label: Option<SharedString>,
+    pub side_labels: (Option<SharedString>, Option<SharedString>),
+    pub image_compare: ImageCompareMode,
+    pub ignore_blank_lines: bool,
+    pub ignore_case: bool,
+    /// Diff JSON and YAML files by their canonical text; see
+    /// [`DiffOptions::semantic`].
+    pub semantic: bool,
+    pub wrap_lines: bool,
+    pub context_lines: Option<usize>,
+    pub panel_side: PanelSide,
+    pub panel_visible: bool,
+    pub sbs_alignment: SideBySideAlignment,
+    /// Show the new side in the left column of the side-by-side view.
+    pub sbs_new_left: bool,
+    pub highlighted_word: Option<SharedString>,
+    /// Shown instead of a diff when loading finds no changes.
+    pub empty_message: Option<SharedString>,
+    pub reviewed: HashSet<Arc<str>>,
+    pub reviewed_last: bool,
+    /// Show fully deleted files as their old content instead of a diff.
+    pub deleted_as_content: bool,
+    /// Files whose deleted-content view is flipped from the global setting.
+    deleted_content_overrides: HashSet<Arc<str>>,
+    /// Files shown in full while the rest are trimmed to `context_lines`.
+    full_files: HashSet<Arc<str>>,
+    /// Show every file's diff in one scroll instead of only the selected one.
+    pub all_files: bool,
+    /// Show how many of the shown lines are changed, per file and in total.
+    pub show_line_ratio: bool,
+    /// Where the new side of git diffs can be blamed from; `None` outside
+    /// git mode or when that side can't be blamed.
+    pub blame_source: Option<BlameSource>,
+    /// Tint each line by how recently it was changed, from `git blame`.
+    pub show_age_heatmap: bool,
+    /// Heatmap colors per new-side line, by file. Empty while blame runs
+    /// or if it failed.
+    age_colors: HashMap<Arc<str>, Vec<Rgba>>,
+    /// File to scroll to once the all-files view has been laid out.
+    pending_file_scroll: Option<usize>,
+    /// Rendered rows by file index. Pairing side-by-side lines is too slow
+    /// to redo on every frame.
+    row_cache: RefCell<HashMap<usize, CachedRows>>,
+    pub theme: Theme,
+    _window_resize: Option<Subscription>,
+    _watcher: Option<RecommendedWatcher>,
+    diff_scroll: ScrollHandle,
+    back_history: Vec<usize>,
+    forward_history: Vec<usize>,
+    last_word_click: Option<(SharedString, Instant)>,
+    palette: Option<CommandPalette>,
+    /// Focus handle of the keyboard shortcut overlay while it's open.
+    shortcut_help: Option<FocusHandle>,
+    search: Option<SearchBar>,
+    file_menu: Option<FileContextMenu>,
+    /// Hunk indices ticked for export, by file index.
+    selected_hunks: BTreeMap<usize, BTreeSet<usize>>,
 }
 
 impl DiffViewer {
-    pub fn from_file_pairs(file_pairs: Vec<(String, String)>) -> Self {
-        let diffs: Vec<FileDiff> = file_pairs
-            .iter()
-            .map(|(old, new)| FileDiff::from_files(old, new))
-            .collect();
+    pub fn from_diffs(diffs: Vec<FileDiff>, cx: &mut Context<Self>) -> Self {
         let selected = if diffs.is_empty() { None } else { Some(0) };
+        let preferences = Preferences::load();
+        let panel_mode = match preferences.tree_panel {
+            Some(true) => PanelMode::Tree,
+            Some(false) => PanelMode::List,
+            None => auto_panel_mode(diffs.len()),
+        };
         Self {
             diffs,
             selected_index: selected,
             panel_width: px(DEFAULT_PANEL_WIDTH),
             view_mode: ViewMode::Unified,
-            panel_mode: PanelMode::List,
+            panel_mode,
+            panel_mode_fixed: preferences.tree_panel.is_some(),
             collapsed_dirs: HashSet::new(),
+            tree_cursor: None,
+            tree_focus_handle: cx
+                .focus_handle()
+                .tab_index(TOOLBAR_BUTTONS.len() as isize + 1)
+                .tab_stop(true),
+            toolbar_focus: TOOLBAR_BUTTONS
+                .iter()
+                .enumerate()
+                .map(|(ix, (id, _))| (*id, cx.focus_handle().tab_index(ix as isize).tab_stop(true)))
+                .collect(),
+            repo_root: None,
+            window_title: String::new(),
+            focused_line: None,
+            selection_anchor: None,
+            focus_handle: cx
+                .focus_handle()
+                .tab_index(TOOLBAR_BUTTONS.len() as isize)
+                .tab_stop(true),
+            toast: None,
+            toast_epoch: 0,
+            loading: None,
+            show_line_numbers: true,
+            show_indent_guides: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            font_family: DEFAULT_FONT_FAMILY.into(),
+            font_size: DEFAULT_FONT_SIZE,
+            zoom: preferences.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            generated_patterns: GlobSet::empty(),
+            show_generated: false,
+            show_unchanged: true,
+            source_label: None,
+            side_labels: (None, None),
+            image_compare: ImageCompareMode::SideBySide,
+            ignore_blank_lines: false,
+            ignore_case: false,
+            semantic: false,
+            wrap_lines: true,
+            context_lines: None,
+            panel_side: PanelSide::Right,
+            panel_visible: true,
+            sbs_alignment: SideBySideAlignment::Similarity,
+            sbs_new_left: false,
+            highlighted_word: None,
+            empty_message: None,
+            reviewed: HashSet::new(),
+            reviewed_last: false,
+            deleted_as_content: false,
+            deleted_content_overrides: HashSet::new(),
+            full_files: HashSet::new(),
+            all_files: false,
+            show_line_ratio: false,
+            blame_source: None,
+            show_age_heatmap: false,
+            age_colors: HashMap::new(),
+            pending_file_scroll: None,
+            row_cache: RefCell::default(),
+            theme: Theme::default(),
+            _window_resize: None,
+            _watcher: None,
+            diff_scroll: ScrollHandle::new(),
+            back_history: Vec::new(),
+            forward_history: Vec::new(),
+            last_word_click: None,
+            palette: None,
+            shortcut_help: None,
+            search: None,
+            file_menu: None,
+            selected_hunks: BTreeMap::new(),
         }
     }
 
-    pub fn from_diffs(diffs: Vec<FileDiff>) -> Self {
-        let selected = if diffs.is_empty() { None } else { Some(0) };
-        Self {
-            diffs,
-            selected_index: selected,
-            panel_width: px(DEFAULT_PANEL_WIDTH),
-            view_mode: ViewMode::Unified,
-            panel_mode: PanelMode::List,
-            collapsed_dirs: HashSet::new(),
-        }
+    pub fn from_repo_diffs(
+        diffs: Vec<FileDiff>,
+        repo_root: String,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let mut viewer = Self::from_diffs(diffs, cx);
+        viewer.repo_root = Some(repo_root);
+        viewer.restore_repo_state();
+        viewer
     }
 
-    fn file_display_name(diff: &FileDiff) -> SharedString {
-        if diff.old_path == diff.new_path {
-            diff.old_path.clone()
-        } else {
-            SharedString::from(format!("{} → {}", diff.old_path, diff.new_path))
-        }
+    pub fn load_in_background<F>(&mut self, load: F, cx: &mut Context<Self>)
+    where
+        F: FnOnce(&mut dyn FnMut(usize, usize)) -> Result<Vec<FileDiff>, String> + Send + 'static,
+    {
+        let (progress_tx, mut progress_rx) = mpsc::unbounded();
+        self.loading = Some((0, 0));
+        let task = cx.background_spawn(async move {
+            load(&mut |done, total| {
+                progress_tx.unbounded_send((done, total)).ok();
+            })
+        });
+        cx.spawn(async move |this, cx| {
+            while let Some(progress) = progress_rx.next().await {
+                this.update(cx, |this, cx| {
+                    this.loading = Some(progress);
+                    cx.notify();
+                })
+                .ok();
+            }
+            let result = task.await;
+            this.update(cx, |this, cx| this.finish_loading(result, cx))
+                .ok();
+        })
+        .detach();
     }
 
-    fn render_diff_line(&self, line: &DiffLine, gutter_width: f32) -> impl IntoElement {
-        let (bg, text_color, sign) = match line.tag {
-            ChangeTag::Delete => (rgb(0x3d1117), rgb(0xffa7a7), "-"),
-            ChangeTag::Insert => (rgb(0x1b2e1b), rgb(0xa7ffa7), "+"),
-            ChangeTag::Equal => (rgb(0x1e1e1e), rgb(0xd4d4d4), " "),
-        };
+    fn finish_loading(&mut self, result: Result<Vec<FileDiff>, String>, cx: &mut Context<Self>) {
+        self.loading = None;
+        match result {
+            Ok(diffs) => {
+                self.selected_index = if diffs.is_empty() { None } else { Some(0) };
+                self.clear_history();
+                self.focused_line = None;
+                self.selection_anchor = None;
+                self.diffs = diffs;
+                self.age_colors.clear();
+                if !self.panel_mode_fixed {
+                    self.panel_mode = auto_panel_mode(self.diffs.len());
+                }
+                self.mark_generated();
+                self.apply_diff_options();
+                self.restore_repo_state();
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                LOAD_FAILED.store(true, Ordering::Relaxed);
+                cx.quit();
+            }
+        }
+        cx.notify();
+    }
 
-        let old_ln = line.old_lineno.map(|n| format!("{n}")).unwrap_or_default();
-        let new_ln = line.new_lineno.map(|n| format!("{n}")).unwrap_or_default();
+    pub fn observe_window_resize(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let subscription = cx.observe_window_bounds(window, |this, window, cx| {
+            let clamped = clamp_panel_width(this.panel_width, window.bounds().size.width);
+            if clamped != this.panel_width {
+                this.panel_width = clamped;
+                cx.notify();
+            }
+        });
+        self._window_resize = Some(subscription);
+    }
 
-        div()
-            .flex()
-            .flex_row()
-            .w_full()
-            .bg(bg)
-            .child(
-                div()
-                    .w(px(gutter_width))
-                    .flex_shrink_0()
-                    .text_right()
-                    .pr(px(4.0))
-                    .text_color(rgb(0x666666))
-                    .child(old_ln),
-            )
-            .child(
-                div()
-                    .w(px(gutter_width))
-                    .flex_shrink_0()
-                    .text_right()
-                    .pr(px(4.0))
-                    .text_color(rgb(0x666666))
-                    .child(new_ln),
-            )
-            .child(
-                div()
-                    .w(px(16.0))
-                    .flex_shrink_0()
-                    .text_center()
-                    .text_color(text_color)
-                    .child(sign),
-            )
-            .child(
-                div()
-                    .pl(px(4.0))
-                    .flex_grow()
-                    .text_color(text_color)
-                    .child(line.content.clone()),
-            )
+    pub fn watch<L>(&mut self, target: WatchTarget, load: L, cx: &mut Context<Self>)
+    where
+        L: Fn() -> Result<Vec<FileDiff>, String> + Send + Sync + 'static,
+    {
+        let (tx, mut changes) = mpsc::unbounded();
+        let watching = target
+            .canonicalize()
+            .map_err(notify::Error::io)
+            .and_then(|target| Ok((start_watcher(&target, tx)?, target)));
+        let target = match watching {
+            Ok((watcher, target)) => {
+                self._watcher = Some(watcher);
+                target
+            }
+            Err(e) => {
+                self.show_toast(format!("Can't watch for changes: {e}"), cx);
+                return;
+            }
+        };
+        let load = Arc::new(load);
+        cx.spawn(async move |this, cx| {
+            let executor = cx.background_executor().clone();
+            while let Some(paths) =
+                settled_change(&mut changes, || executor.timer(WATCH_DEBOUNCE)).await
+            {
+                let (load, target) = (load.clone(), target.clone());
+                let reload = async move { target.needs_reload(&paths).then(|| load()) };
+                let Some(result) = executor.spawn(reload).await else {
+                    continue;
+                };
+                if this
+                    .update(cx, |this, cx| this.apply_reload(result, cx))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        })
+        .detach();
     }
 
-    fn render_file_diff(&self, diff: &FileDiff) -> impl IntoElement {
-        let max_lineno = diff.lines.iter().fold(0usize, |acc, l| {
-            acc.max(l.old_lineno.unwrap_or(0))
-                .max(l.new_lineno.unwrap_or(0))
+    fn apply_reload(&mut self, result: Result<Vec<FileDiff>, String>, cx: &mut Context<Self>) {
+        let diffs = match result {
+            Ok(diffs) => diffs,
+            Err(e) => {
+                self.show_toast(format!("Reload failed: {e}"), cx);
+                return;
+            }
+        };
+        let selected = self
+            .selected_diff()
+            .map(|diff| (diff.new_path.clone(), diff.staging));
+        let same_file = selected.and_then(|(path, staging)| {
+            diffs
+                .iter()
+                .position(|diff| diff.new_path == path && diff.staging == staging)
         });
-        let gutter_width = format!("{max_lineno}").len() as f32 * 8.0 + 12.0;
+        if same_file.is_none() {
+            self.focused_line = None;
+            self.selection_anchor = None;
+        }
+        self.selected_index = same_file.or(if diffs.is_empty() { None } else { Some(0) });
+        self.diffs = diffs;
+        self.age_colors.clear();
+        self.clear_history();
+        self.mark_generated();
+        self.apply_diff_options();
+        if let Some(len) = self.selected_diff().map(|diff| diff.lines.len()) {
+            self.focused_line = self.focused_line.filter(|&ix| ix < len);
+            self.selection_anchor = self.selection_anchor.filter(|&ix| ix < len);
+        }
+        self.clear_hidden_focus();
+        cx.notify();
+    }
 
-        let mut content = div().flex().flex_col().w_full();
-        for line in &diff.lines {
-            content = content.child(self.render_diff_line(line, gutter_width));
+    pub fn set_generated_patterns(&mut self, patterns: GlobSet) {
+        self.generated_patterns = patterns;
+        self.mark_generated();
+    }
+
+    fn mark_generated(&mut self) {
+        for diff in &mut self.diffs {
+            diff.generated = self.generated_patterns.is_match(diff.new_path.as_ref())
+                || self.generated_patterns.is_match(diff.old_path.as_ref());
         }
+        self.ensure_visible_selection();
+    }
 
-        div()
-            .flex()
-            .flex_col()
-            .w_full()
-            .mb(px(16.0))
-            .child(div().w_full().p(px(4.0)).child(content))
+    pub fn set_ignore_case(&mut self, ignore_case: bool, cx: &mut Context<Self>) {
+        self.ignore_case = ignore_case;
+        self.apply_diff_options();
+        self.ensure_visible_selection();
+        self.focused_line = None;
+        self.selection_anchor = None;
+        cx.notify();
     }
 
-    fn render_side_by_side_line(
-        &self,
-        sbs_line: &SideBySideLine,
-        gutter_width: f32,
-    ) -> impl IntoElement {
-        let (left_bg, left_text, left_ln, left_content) = match &sbs_line.left {
-            Some(line) => {
-                let (bg, tc) = match line.tag {
-                    ChangeTag::Delete => (rgb(0x3d1117), rgb(0xffa7a7)),
-                    ChangeTag::Equal => (rgb(0x1e1e1e), rgb(0xd4d4d4)),
-                    _ => (rgb(0x1e1e1e), rgb(0xd4d4d4)),
-                };
-                let ln = line.old_lineno.map(|n| format!("{n}")).unwrap_or_default();
-                (bg, tc, ln, line.content.clone())
+    pub fn set_semantic(&mut self, semantic: bool, cx: &mut Context<Self>) {
+        self.semantic = semantic;
+        self.apply_diff_options();
+        self.ensure_visible_selection();
+        self.focused_line = None;
+        self.selection_anchor = None;
+        cx.notify();
+    }
+
+    fn apply_diff_options(&mut self) {
+        // Reloaded or recomputed lines can split into different hunks.
+        self.selected_hunks.clear();
+        self.row_cache.get_mut().clear();
+        for diff in &mut self.diffs {
+            let options = DiffOptions {
+                ignore_case: self.ignore_case,
+                semantic: self.semantic,
+                ..diff.options
+            };
+            if diff.options != options {
+                diff.recompute(options).ok();
             }
-            None => (
-                rgb(0x262626),
-                rgb(0x666666),
-                String::new(),
-                SharedString::from(""),
-            ),
+        }
+    }
+
+    fn ensure_visible_selection(&mut self) {
+        let selected_hidden = self
+            .selected_diff()
+            .is_some_and(|diff| !self.is_file_visible(diff));
+        if selected_hidden {
+            self.selected_index = self.diffs.iter().position(|d| self.is_file_visible(d));
+            self.focused_line = None;
+            self.selection_anchor = None;
+        }
+    }
+
+    fn is_file_visible(&self, diff: &FileDiff) -> bool {
+        (self.show_generated || !diff.generated) && (self.show_unchanged || !diff.is_unchanged())
+    }
+
+    fn toggle_generated(&mut self, cx: &mut Context<Self>) {
+        self.show_generated = !self.show_generated;
+        self.ensure_visible_selection();
+        cx.notify();
+    }
+
+    fn toggle_unchanged(&mut self, cx: &mut Context<Self>) {
+        self.show_unchanged = !self.show_unchanged;
+        self.ensure_visible_selection();
+        cx.notify();
+    }
+
+    fn restore_repo_state(&mut self) {
+        let Some(repo_root) = &self.repo_root else {
+            return;
         };
+        let state = RepoState::load(repo_root);
+        let dirs = all_dir_paths(&self.diffs);
+        self.collapsed_dirs = state
+            .collapsed_dirs
+            .into_iter()
+            .filter(|dir| dirs.contains(dir))
+            .collect();
+        let paths: HashSet<&str> = self.diffs.iter().map(|d| d.new_path.as_ref()).collect();
+        self.reviewed = state
+            .reviewed_files
+            .into_iter()
+            .filter(|path| paths.contains(path.as_str()))
+            .map(Arc::from)
+            .collect();
+    }
 
-        let (right_bg, right_text, right_ln, right_content) = match &sbs_line.right {
-            Some(line) => {
-                let (bg, tc) = match line.tag {
-                    ChangeTag::Insert => (rgb(0x1b2e1b), rgb(0xa7ffa7)),
-                    ChangeTag::Equal => (rgb(0x1e1e1e), rgb(0xd4d4d4)),
-                    _ => (rgb(0x1e1e1e), rgb(0xd4d4d4)),
-                };
-                let ln = line.new_lineno.map(|n| format!("{n}")).unwrap_or_default();
-                (bg, tc, ln, line.content.clone())
-            }
-            None => (
-                rgb(0x262626),
-                rgb(0x666666),
-                String::new(),
-                SharedString::from(""),
-            ),
+    fn save_repo_state(&self) {
+        let Some(repo_root) = &self.repo_root else {
+            return;
+        };
+        let state = RepoState {
+            collapsed_dirs: self.collapsed_dirs.clone(),
+            reviewed_files: self.reviewed.iter().map(|path| path.to_string()).collect(),
         };
+        if let Err(e) = state.save(repo_root) {
+            eprintln!("Warning: {e}");
+        }
+    }
 
-        div()
-            .flex()
-            .flex_row()
-            .w_full()
-            .child(
-                div()
-                    .flex()
-                    .flex_row()
-                    .flex_1()
-                    .min_w(px(0.0))
-                    .bg(left_bg)
-                    .child(
-                        div()
-                            .w(px(gutter_width))
-                            .flex_shrink_0()
-                            .text_right()
-                            .pr(px(4.0))
-                            .text_color(rgb(0x666666))
-                            .child(left_ln),
-                    )
-                    .child(
-                        div()
-                            .pl(px(4.0))
-                            .flex_grow()
-                            .min_w(px(0.0))
-                            .overflow_x_hidden()
-                            .text_color(left_text)
-                            .child(left_content),
-                    ),
-            )
-            .child(
-                div()
-                    .w(px(1.0))
-                    .flex_shrink_0()
-                    .bg(rgb(0x404040)),
-            )
-            .child(
-                div()
-                    .flex()
-                    .flex_row()
-                    .flex_1()
-                    .min_w(px(0.0))
-                    .bg(right_bg)
-                    .child(
-                        div()
-                            .w(px(gutter_width))
-                            .flex_shrink_0()
-                            .text_right()
-                            .pr(px(4.0))
-                            .text_color(rgb(0x666666))
-                            .child(right_ln),
-                    )
-                    .child(
-                        div()
-                            .pl(px(4.0))
-                            .flex_grow()
-                            .min_w(px(0.0))
-                            .overflow_x_hidden()
-                            .text_color(right_text)
-                            .child(right_content),
-                    ),
-            )
+    fn select_file(&mut self, index: usize) {
+        if let Some(previous) = self.selected_index.filter(|&previous| previous != index) {
+            if self.back_history.last() != Some(&previous) {
+                self.back_history.push(previous);
+            }
+            if self.back_history.len() > MAX_HISTORY {
+                self.back_history.remove(0);
+            }
+            self.forward_history.clear();
+        }
+        self.show_file(index);
     }
 
-    fn render_side_by_side_diff(&self, diff: &FileDiff) -> impl IntoElement {
-        let sbs_lines = to_side_by_side(&diff.lines);
+    fn show_file(&mut self, index: usize) {
+        if self.selected_index != Some(index) {
+            self.reset_file_state();
+            if self.all_files {
+                self.scroll_to_file_block(index);
+            } else {
+                self.diff_scroll.set_offset(point(px(0.0), px(0.0)));
+            }
+        }
+        self.selected_index = Some(index);
+    }
 
-        let max_lineno = diff.lines.iter().fold(0usize, |acc, l| {
-            acc.max(l.old_lineno.unwrap_or(0))
-                .max(l.new_lineno.unwrap_or(0))
-        });
-        let gutter_width = format!("{max_lineno}").len() as f32 * 8.0 + 12.0;
+    fn reset_file_state(&mut self) {
+        self.focused_line = None;
+        self.selection_anchor = None;
+        if let Some(search) = &mut self.search {
+            search.active = None;
+            search.wrapped = false;
+        }
+    }
 
-        let mut content = div().flex().flex_col().w_full();
-        for sbs_line in &sbs_lines {
-            content = content.child(self.render_side_by_side_line(sbs_line, gutter_width));
+    /// Files in the order the all-files view stacks them, matching the list
+    /// panel.
+    fn all_files_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        for (_, mut indices) in self.panel_sections() {
+            if self.reviewed_last {
+                indices.sort_by_key(|&i| self.reviewed.contains(&self.diffs[i].new_path));
+            }
+            order.extend(indices);
         }
+        order
+    }
 
-        div()
-            .flex()
-            .flex_col()
-            .w_full()
-            .mb(px(16.0))
-            .child(div().w_full().p(px(4.0)).child(content))
+    /// Scrolls the all-files view so `index`'s block starts at the top, using
+    /// the block positions from the last layout.
+    fn scroll_to_file_block(&mut self, index: usize) {
+        let Some(position) = self.all_files_order().iter().position(|&i| i == index) else {
+            return;
+        };
+        if let Some(bounds) = self.diff_scroll.bounds_for_item(position) {
+            let max_y = self.diff_scroll.max_offset().height;
+            let y = (bounds.top() - self.diff_scroll.bounds().top())
+                .max(px(0.0))
+                .min(max_y);
+            self.diff_scroll.set_offset(point(px(0.0), -y));
+        }
+    }
+
+    /// Makes a file in the all-files view the selected one without scrolling,
+    /// e.g. when clicking into its block.
+    fn select_file_block(&mut self, index: usize, cx: &mut Context<Self>) {
+        if self.selected_index != Some(index) {
+            self.reset_file_state();
+            self.selected_index = Some(index);
+            cx.notify();
+        }
+    }
+
+    fn toggle_all_files(&mut self) {
+        self.all_files = !self.all_files;
+        self.diff_scroll.set_offset(point(px(0.0), px(0.0)));
+        if self.all_files {
+            self.pending_file_scroll = self.selected_index;
+        }
+    }
+
+    fn clear_history(&mut self) {
+        self.back_history.clear();
+        self.forward_history.clear();
+    }
+
+    fn navigate_history(&mut self, back: bool, cx: &mut Context<Self>) {
+        loop {
+            let next = if back {
+                self.back_history.pop()
+            } else {
+                self.forward_history.pop()
+            };
+            let Some(index) = next else {
+                return;
+            };
+            let valid = self
+                .diffs
+                .get(index)
+                .is_some_and(|diff| self.is_file_visible(diff));
+            if !valid || self.selected_index == Some(index) {
+                continue;
+            }
+            if let Some(current) = self.selected_index {
+                if back {
+                    self.forward_history.push(current);
+                } else {
+                    self.back_history.push(current);
+                }
+            }
+            self.show_file(index);
+            cx.notify();
+            return;
+        }
+    }
+
+    fn navigate_back(&mut self, _: &NavigateBack, _window: &mut Window, cx: &mut Context<Self>) {
+        self.navigate_history(true, cx);
+    }
+
+    fn navigate_forward(
+        &mut self,
+        _: &NavigateForward,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.navigate_history(false, cx);
+    }
+
+    fn move_focused_line(&mut self, forward: bool, extend: bool, cx: &mut Context<Self>) {
+        let Some(diff) = self.selected_diff() else {
+            return;
+        };
+        let visible = self.visible_line_indices(diff);
+        let target = if forward {
+            let start = self.focused_line.map_or(0, |ix| ix + 1);
+            visible.iter().find(|&&ix| ix >= start)
+        } else {
+            let end = self.focused_line.unwrap_or(diff.lines.len());
+            visible.iter().rev().find(|&&ix| ix < end)
+        };
+        if let Some(&ix) = target {
+            self.set_focused_line(ix, extend);
+        }
+        cx.notify();
+    }
+
+    fn set_focused_line(&mut self, ix: usize, extend: bool) {
+        self.selection_anchor = if extend {
+            self.selection_anchor.or(self.focused_line)
+        } else {
+            None
+        };
+        self.focused_line = Some(ix);
+    }
+
+    fn selected_lines(&self) -> Option<RangeInclusive<usize>> {
+        let focused = self.focused_line?;
+        let anchor = self.selection_anchor.unwrap_or(focused);
+        Some(anchor.min(focused)..=anchor.max(focused))
+    }
+
+    fn is_line_selected(&self, file: usize, ix: usize) -> bool {
+        self.selected_index == Some(file)
+            && self
+                .selected_lines()
+                .is_some_and(|range| range.contains(&ix))
+    }
+
+    fn copy_selection(&mut self, cx: &mut Context<Self>) {
+        let Some(range) = self.selected_lines() else {
+            return;
+        };
+        let Some(diff) = self.selected_diff() else {
+            return;
+        };
+        let text = self.file_layout_options(diff).copy_text(diff, range);
+        let count = text.lines().count().max(1);
+        let message = if count == 1 {
+            "Copied line".to_string()
+        } else {
+            format!("Copied {count} lines")
+        };
+        self.copy_to_clipboard(text, message, cx);
+    }
+
+    fn select_prev_line(
+        &mut self,
+        _: &SelectPrevLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_focused_line(false, false, cx);
+    }
+
+    fn select_next_line(
+        &mut self,
+        _: &SelectNextLine,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_focused_line(true, false, cx);
+    }
+
+    fn extend_selection_up(
+        &mut self,
+        _: &ExtendSelectionUp,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_focused_line(false, true, cx);
+    }
+
+    fn extend_selection_down(
+        &mut self,
+        _: &ExtendSelectionDown,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.move_focused_line(true, true, cx);
+    }
+
+    fn copy_selection_action(
+        &mut self,
+        _: &CopySelection,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.copy_selection(cx);
+    }
+
+    fn toggle_hunk(&mut self, file: usize, hunk: usize, cx: &mut Context<Self>) {
+        let hunks = self.selected_hunks.entry(file).or_default();
+        if !hunks.remove(&hunk) {
+            hunks.insert(hunk);
+        }
+        if hunks.is_empty() {
+            self.selected_hunks.remove(&file);
+        }
+        cx.notify();
+    }
+
+    fn copy_selected_hunks(&mut self, cx: &mut Context<Self>) {
+        let Some(context) = self
+            .context_lines
+            .filter(|_| !self.selected_hunks.is_empty())
+        else {
+            self.show_toast("No hunks selected", cx);
+            return;
+        };
+        let patch: String = self
+            .selected_hunks
+            .iter()
+            .filter_map(|(&file, hunks)| Some(self.diffs.get(file)?.partial_patch(context, hunks)))
+            .collect();
+        let count: usize = self.selected_hunks.values().map(BTreeSet::len).sum();
+        let message = if count == 1 {
+            "Copied 1 hunk as a patch".to_string()
+        } else {
+            format!("Copied {count} hunks as a patch")
+        };
+        self.copy_to_clipboard(patch, message, cx);
+    }
+
+    fn focus_next(&mut self, _: &FocusNext, window: &mut Window, cx: &mut Context<Self>) {
+        if self.is_typing(window) {
+            cx.propagate();
+            return;
+        }
+        window.focus_next();
+    }
+
+    fn focus_previous(&mut self, _: &FocusPrevious, window: &mut Window, cx: &mut Context<Self>) {
+        if self.is_typing(window) {
+            cx.propagate();
+            return;
+        }
+        window.focus_prev();
+    }
+
+    fn toggle_line_wrap(
+        &mut self,
+        _: &ToggleLineWrap,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.wrap_lines = !self.wrap_lines;
+        cx.notify();
+    }
+
+    fn scroll_diff_to(&mut self, y: Pixels, cx: &mut Context<Self>) {
+        let max_y = self.diff_scroll.max_offset().height;
+        let y = y.max(px(0.0)).min(max_y);
+        self.diff_scroll.set_offset(point(px(0.0), -y));
+        cx.notify();
+    }
+
+    fn scroll_to_top(&mut self, _: &ScrollToTop, window: &mut Window, cx: &mut Context<Self>) {
+        if self.is_typing(window) {
+            cx.propagate();
+            return;
+        }
+        self.scroll_diff_to(px(0.0), cx);
+    }
+
+    fn scroll_to_bottom(
+        &mut self,
+        _: &ScrollToBottom,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.is_typing(window) {
+            cx.propagate();
+            return;
+        }
+        let max_y = self.diff_scroll.max_offset().height;
+        self.scroll_diff_to(max_y, cx);
+    }
+
+    fn scroll_page(&mut self, pages: f32, cx: &mut Context<Self>) {
+        let current = -self.diff_scroll.offset().y;
+        let page = self.diff_scroll.bounds().size.height;
+        self.scroll_diff_to(current + page * pages, cx);
+    }
+
+    fn scroll_page_up(&mut self, _: &ScrollPageUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.scroll_page(-1.0, cx);
+    }
+
+    fn scroll_page_down(
+        &mut self,
+        _: &ScrollPageDown,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.scroll_page(1.0, cx);
+    }
+
+    fn text_size(&self) -> f32 {
+        (self.font_size * self.zoom).round()
+    }
+
+    fn set_zoom(&mut self, zoom: f32, cx: &mut Context<Self>) {
+        self.zoom = ((zoom / ZOOM_STEP).round() * ZOOM_STEP).clamp(MIN_ZOOM, MAX_ZOOM);
+        let mut preferences = Preferences::load();
+        preferences.zoom = self.zoom;
+        if let Err(e) = preferences.save() {
+            eprintln!("Warning: {e}");
+        }
+        let percent = (self.zoom * 100.0).round();
+        self.show_toast(format!("Zoom {percent}%"), cx);
+    }
+
+    fn zoom_in(&mut self, _: &ZoomIn, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_zoom(self.zoom + ZOOM_STEP, cx);
+    }
+
+    fn zoom_out(&mut self, _: &ZoomOut, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_zoom(self.zoom - ZOOM_STEP, cx);
+    }
+
+    fn reset_zoom(&mut self, _: &ResetZoom, _window: &mut Window, cx: &mut Context<Self>) {
+        self.set_zoom(1.0, cx);
+    }
+
+    fn next_conflict_marker(
+        &mut self,
+        _: &NextConflictMarker,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.jump_to_conflict_marker(true, cx);
+    }
+
+    fn prev_conflict_marker(
+        &mut self,
+        _: &PrevConflictMarker,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.jump_to_conflict_marker(false, cx);
+    }
+
+    fn jump_to_conflict_marker(&mut self, forward: bool, cx: &mut Context<Self>) {
+        let Some(diff) = self.selected_diff() else {
+            return;
+        };
+        let markers: Vec<usize> = self
+            .visible_line_indices(diff)
+            .into_iter()
+            .filter(|&ix| diff.lines[ix].is_conflict_marker())
+            .collect();
+        let target = match (forward, self.focused_line) {
+            (true, Some(focused)) => markers.iter().find(|&&ix| ix > focused),
+            (false, Some(focused)) => markers.iter().rev().find(|&&ix| ix < focused),
+            (_, None) => None,
+        };
+        let wrapped = if forward {
+            markers.first()
+        } else {
+            markers.last()
+        };
+        match target.or(wrapped) {
+            Some(&ix) => {
+                self.set_focused_line(ix, false);
+                cx.notify();
+            }
+            None => self.show_toast("No conflict markers in this file", cx),
+        }
+    }
+
+    fn toggle_reviewed(&mut self, index: usize, cx: &mut Context<Self>) {
+        let Some(path) = self.diffs.get(index).map(|d| d.new_path.clone()) else {
+            return;
+        };
+        if !self.reviewed.remove(&path) {
+            self.reviewed.insert(path);
+        }
+        self.save_repo_state();
+        cx.notify();
+    }
+
+    fn shows_deleted_content(&self, diff: &FileDiff) -> bool {
+        diff.is_full_deletion()
+            && self.deleted_as_content != self.deleted_content_overrides.contains(&diff.new_path)
+    }
+
+    fn toggle_deleted_content(&mut self, index: usize, cx: &mut Context<Self>) {
+        let Some(path) = self.diffs.get(index).map(|d| d.new_path.clone()) else {
+            return;
+        };
+        if !self.deleted_content_overrides.remove(&path) {
+            self.deleted_content_overrides.insert(path);
+        }
+        self.focused_line = None;
+        self.selection_anchor = None;
+        cx.notify();
+    }
+
+    fn shows_full_file(&self, diff: &FileDiff) -> bool {
+        self.context_lines.is_some() && self.full_files.contains(&diff.new_path)
+    }
+
+    fn toggle_full_file(&mut self, index: usize, cx: &mut Context<Self>) {
+        let Some(path) = self.diffs.get(index).map(|d| d.new_path.clone()) else {
+            return;
+        };
+        if !self.full_files.remove(&path) {
+            self.full_files.insert(path);
+        }
+        self.clear_hidden_focus();
+        cx.notify();
+    }
+
+    fn toggle_age_heatmap(&mut self, cx: &mut Context<Self>) {
+        if self.blame_source.is_none() {
+            let message = "The age heatmap needs a work tree, stash or range git diff";
+            self.show_toast(message, cx);
+            return;
+        }
+        self.show_age_heatmap = !self.show_age_heatmap;
+        cx.notify();
+    }
+
+    /// Starts `git blame` on the selected file for the age heatmap, unless
+    /// it has already been blamed since the diff was loaded.
+    fn request_line_ages(&mut self, cx: &mut Context<Self>) {
+        let (Some(source), Some(root)) = (self.blame_source.clone(), self.repo_root.clone()) else {
+            return;
+        };
+        let Some(path) = self.selected_diff().map(|diff| diff.new_path.clone()) else {
+            return;
+        };
+        if self.age_colors.contains_key(&path) {
+            return;
+        }
+        self.age_colors.insert(path.clone(), Vec::new());
+        let blame_path = path.clone();
+        let blame = async move { git_blame_times(&SystemGit, &root, &source, &blame_path) };
+        let task = cx.background_spawn(blame);
+        cx.spawn(async move |this, cx| {
+            let times = task.await.unwrap_or_default();
+            this.update(cx, |this, cx| {
+                this.age_colors.insert(path, age_colors(&times));
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    /// The heatmap strip beside a line number, colored by how recently the
+    /// line's new side was changed, while the heatmap is on.
+    fn render_age_strip(&self, file: usize, line: Option<&DiffLine>) -> Option<Div> {
+        self.show_age_heatmap.then(|| {
+            let color = line.and_then(|line| {
+                let colors = self.age_colors.get(&self.diffs.get(file)?.new_path)?;
+                colors.get(line.new_lineno?.checked_sub(1)?).copied()
+            });
+            div()
+                .w(px(3.0))
+                .flex_shrink_0()
+                .when_some(color, |strip, color| strip.bg(color))
+        })
+    }
+
+    fn tree_rows(&self) -> Vec<TreeRow> {
+        let mut rows = Vec::new();
+        for (_, indices) in self.panel_sections() {
+            let tree = build_file_tree(&self.diffs, &indices);
+            flatten_tree(&tree, "", &self.collapsed_dirs, &mut rows);
+        }
+        rows
+    }
+
+    fn tree_row_path(&self, row: &TreeRow) -> String {
+        match row {
+            TreeRow::Dir(path) => path.clone(),
+            TreeRow::File(index) => self.diffs[*index].new_path.to_string(),
+        }
+    }
+
+    fn set_tree_cursor(&mut self, row: &TreeRow) {
+        self.tree_cursor = self.tree_rows().iter().position(|r| r == row);
+    }
+
+    fn toggle_dir(&mut self, dir_path: &str) {
+        if !self.collapsed_dirs.remove(dir_path) {
+            self.collapsed_dirs.insert(dir_path.to_string());
+        }
+        self.save_repo_state();
+    }
+
+    fn tree_cursor_row(&self) -> Option<(usize, TreeRow)> {
+        let rows = self.tree_rows();
+        let cursor = self.tree_cursor?.min(rows.len().checked_sub(1)?);
+        Some((cursor, rows[cursor].clone()))
+    }
+
+    fn select_prev_tree_node(
+        &mut self,
+        _: &SelectPrevTreeNode,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let len = self.tree_rows().len();
+        if len == 0 {
+            return;
+        }
+        self.tree_cursor = Some(match self.tree_cursor {
+            Some(cursor) => cursor.min(len - 1).saturating_sub(1),
+            None => len - 1,
+        });
+        cx.notify();
+    }
+
+    fn select_next_tree_node(
+        &mut self,
+        _: &SelectNextTreeNode,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let len = self.tree_rows().len();
+        if len == 0 {
+            return;
+        }
+        let next = self.tree_cursor.map_or(0, |cursor| cursor + 1);
+        self.tree_cursor = Some(next.min(len - 1));
+        cx.notify();
+    }
+
+    fn expand_tree_node(
+        &mut self,
+        _: &ExpandTreeNode,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some((cursor, TreeRow::Dir(dir_path))) = self.tree_cursor_row() else {
+            return;
+        };
+        if self.collapsed_dirs.contains(&dir_path) {
+            self.toggle_dir(&dir_path);
+        } else {
+            self.tree_cursor = Some(cursor + 1);
+        }
+        cx.notify();
+    }
+
+    fn collapse_tree_node(
+        &mut self,
+        _: &CollapseTreeNode,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some((cursor, row)) = self.tree_cursor_row() else {
+            return;
+        };
+        if let TreeRow::Dir(dir_path) = &row {
+            if !self.collapsed_dirs.contains(dir_path) {
+                self.toggle_dir(dir_path);
+                cx.notify();
+                return;
+            }
+        }
+        let path = self.tree_row_path(&row);
+        let Some((parent, _)) = path.rsplit_once('/') else {
+            return;
+        };
+        let rows = self.tree_rows();
+        let parent_row = TreeRow::Dir(parent.to_string());
+        if let Some(ix) = rows[..cursor].iter().rposition(|r| *r == parent_row) {
+            self.tree_cursor = Some(ix);
+            cx.notify();
+        }
+    }
+
+    fn open_tree_node(&mut self, _: &OpenTreeNode, _window: &mut Window, cx: &mut Context<Self>) {
+        match self.tree_cursor_row() {
+            Some((_, TreeRow::File(index))) => self.select_file(index),
+            Some((_, TreeRow::Dir(dir_path))) => self.toggle_dir(&dir_path),
+            None => return,
+        }
+        cx.notify();
+    }
+
+    fn blur_file_tree(&mut self, _: &BlurFileTree, window: &mut Window, cx: &mut Context<Self>) {
+        self.focus_handle.focus(window);
+        cx.notify();
+    }
+
+    fn toggle_panel(&mut self, _: &TogglePanel, window: &mut Window, cx: &mut Context<Self>) {
+        self.panel_visible = !self.panel_visible;
+        if !self.panel_visible && self.tree_focus_handle.is_focused(window) {
+            self.focus_handle.focus(window);
+        }
+        cx.notify();
+    }
+
+    fn reverse_diff(&mut self, _: &ReverseDiff, window: &mut Window, cx: &mut Context<Self>) {
+        if self.is_typing(window) {
+            cx.propagate();
+            return;
+        }
+        self.reverse_selected_diff(cx);
+    }
+
+    fn reverse_selected_diff(&mut self, cx: &mut Context<Self>) {
+        let Some(index) = self.selected_index else {
+            return;
+        };
+        match self.diffs[index].reversed() {
+            Some(reversed) => {
+                self.diffs[index] = reversed;
+                self.row_cache.get_mut().remove(&index);
+                self.focused_line = None;
+                self.selection_anchor = None;
+                cx.notify();
+            }
+            None => self.show_toast("Can't reverse a file that was not diffed", cx),
+        }
+    }
+
+    fn click_word(&mut self, word: SharedString, cx: &mut Context<Self>) {
+        let now = Instant::now();
+        let is_double = self.last_word_click.as_ref().is_some_and(|(last, at)| {
+            *last == word && now.duration_since(*at) <= DOUBLE_CLICK_INTERVAL
+        });
+        if is_double {
+            self.highlighted_word = Some(word);
+            self.last_word_click = None;
+            cx.notify();
+        } else {
+            self.last_word_click = Some((word, now));
+        }
+    }
+
+    fn clear_word_highlight(
+        &mut self,
+        _: &ClearWordHighlight,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.highlighted_word = None;
+        cx.notify();
+    }
+
+    fn render_line_text(
+        &self,
+        id: ElementId,
+        line: Option<(usize, usize)>,
+        content: &Arc<str>,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let words = word_ranges(content);
+        let mut highlights: Vec<(Range<usize>, HighlightStyle)> = match &self.search {
+            Some(search) => find_in_line(content, &search.query, search.ignore_case)
+                .into_iter()
+                .map(|range| {
+                    let is_active = search.active.as_ref().is_some_and(|m| {
+                        line == self.selected_index.map(|file| (file, m.line)) && m.range == range
+                    });
+                    let color = if is_active { 0x9e6a03 } else { 0x4b3a12 };
+                    let style = HighlightStyle {
+                        background_color: Some(rgb(color).into()),
+                        ..Default::default()
+                    };
+                    (range, style)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let whitespace_changes = line
+            .and_then(|(file, ix)| {
+                Some(self.diffs.get(file)?.whitespace_changes(ix, self.tab_width))
+            })
+            .unwrap_or_default();
+        for range in whitespace_changes {
+            let overlaps_search = highlights
+                .iter()
+                .any(|(r, _)| r.start < range.end && range.start < r.end);
+            if !overlaps_search {
+                let style = HighlightStyle {
+                    background_color: Some(self.theme.whitespace_change.into()),
+                    ..Default::default()
+                };
+                highlights.push((range, style));
+            }
+        }
+        highlights.sort_by_key(|(range, _)| range.start);
+        if let Some(word) = &self.highlighted_word {
+            let overlaps_search = |range: &Range<usize>| {
+                highlights
+                    .iter()
+                    .any(|(r, _)| r.start < range.end && range.start < r.end)
+            };
+            let word_highlights: Vec<_> = words
+                .iter()
+                .filter(|range| &content[(*range).clone()] == word.as_ref())
+                .filter(|range| !overlaps_search(range))
+                .map(|range| {
+                    let style = HighlightStyle {
+                        background_color: Some(rgb(0x613d00).into()),
+                        ..Default::default()
+                    };
+                    (range.clone(), style)
+                })
+                .collect();
+            highlights.extend(word_highlights);
+            highlights.sort_by_key(|(range, _)| range.start);
+        }
+
+        let viewer = cx.entity();
+        let text = content.clone();
+        let click_ranges = words.clone();
+        InteractiveText::new(
+            id,
+            StyledText::new(SharedString::from(content.clone())).with_highlights(highlights),
+        )
+        .on_click(click_ranges, move |range_ix, _window, cx| {
+            let word = SharedString::from(text[words[range_ix].clone()].to_string());
+            viewer.update(cx, |this, cx| this.click_word(word, cx));
+        })
+    }
+
+    fn is_typing(&self, window: &Window) -> bool {
+        self.palette.is_some()
+            || self
+                .search
+                .as_ref()
+                .is_some_and(|search| search.focus_handle.is_focused(window))
+    }
+
+    fn search_matches(&self) -> Vec<SearchMatch> {
+        match (&self.search, self.selected_diff()) {
+            (Some(search), Some(diff)) => find_matches(
+                diff,
+                &self.visible_line_indices(diff),
+                &search.query,
+                search.ignore_case,
+            ),
+            _ => Vec::new(),
+        }
+    }
+
+    fn open_search(&mut self, _: &OpenSearch, window: &mut Window, cx: &mut Context<Self>) {
+        let search = self.search.get_or_insert_with(|| SearchBar {
+            query: String::new(),
+            ignore_case: true,
+            active: None,
+            wrapped: false,
+            focus_handle: cx.focus_handle(),
+        });
+        search.focus_handle.focus(window);
+        cx.notify();
+    }
+
+    fn dismiss_search(&mut self, _: &DismissSearch, window: &mut Window, cx: &mut Context<Self>) {
+        self.search = None;
+        self.focus_handle.focus(window);
+        cx.notify();
+    }
+
+    /// Re-runs the search after the query or options change, making the first
+    /// match at or below the focused line active.
+    fn refresh_search(&mut self, cx: &mut Context<Self>) {
+        let matches = self.search_matches();
+        let from = self.focused_line.unwrap_or(0);
+        let active = matches
+            .iter()
+            .find(|m| m.line >= from)
+            .or(matches.first())
+            .cloned();
+        if let Some(m) = &active {
+            self.set_focused_line(m.line, false);
+        }
+        if let Some(search) = &mut self.search {
+            search.active = active;
+            search.wrapped = false;
+        }
+        cx.notify();
+    }
+
+    fn step_search(&mut self, forward: bool, cx: &mut Context<Self>) {
+        let matches = self.search_matches();
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let current = search
+            .active
+            .as_ref()
+            .and_then(|active| matches.iter().position(|m| m == active));
+        let Some((ix, wrapped)) = step_match(current, matches.len(), forward) else {
+            return;
+        };
+        search.active = Some(matches[ix].clone());
+        search.wrapped = wrapped;
+        self.set_focused_line(matches[ix].line, false);
+        cx.notify();
+    }
+
+    fn search_next(&mut self, _: &SearchNext, _window: &mut Window, cx: &mut Context<Self>) {
+        self.step_search(true, cx);
+    }
+
+    fn search_prev(&mut self, _: &SearchPrev, _window: &mut Window, cx: &mut Context<Self>) {
+        self.step_search(false, cx);
+    }
+
+    fn toggle_search_case(
+        &mut self,
+        _: &ToggleSearchCase,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if let Some(search) = &mut self.search {
+            search.ignore_case = !search.ignore_case;
+            self.refresh_search(cx);
+        }
+    }
+
+    fn search_key_down(
+        &mut self,
+        event: &KeyDownEvent,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let keystroke = &event.keystroke;
+        if keystroke.modifiers.control || keystroke.modifiers.platform {
+            return;
+        }
+        if keystroke.key == "backspace" {
+            search.query.pop();
+        } else if let Some(text) = &keystroke.key_char {
+            search.query.push_str(text);
+        } else {
+            return;
+        }
+        cx.stop_propagation();
+        self.refresh_search(cx);
+    }
+
+    fn render_search_bar(&self, search: &SearchBar, cx: &mut Context<Self>) -> impl IntoElement {
+        let matches = self.search_matches();
+        let position = search
+            .active
+            .as_ref()
+            .and_then(|active| matches.iter().position(|m| m == active));
+        let count = match (position, matches.len()) {
+            _ if search.query.is_empty() => String::new(),
+            (_, 0) => "No results".to_string(),
+            (Some(ix), total) => format!("{} of {total}", ix + 1),
+            (None, total) => format!("{total} matches"),
+        };
+        let case_bg = if search.ignore_case {
+            rgb(0x3c3c3c)
+        } else {
+            rgb(0x007acc)
+        };
+
+        div()
+            .key_context("SearchBar")
+            .track_focus(&search.focus_handle)
+            .on_action(cx.listener(Self::dismiss_search))
+            .on_action(cx.listener(Self::toggle_search_case))
+            .on_key_down(cx.listener(Self::search_key_down))
+            .absolute()
+            .top(px(8.0))
+            .right(px(16.0))
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap(px(8.0))
+            .w(px(320.0))
+            .px(px(10.0))
+            .py(px(4.0))
+            .bg(rgb(0x252526))
+            .border_1()
+            .border_color(rgb(0x454545))
+            .rounded(px(4.0))
+            .shadow_lg()
+            .text_size(px(12.0))
+            .child(
+                div()
+                    .flex_1()
+                    .min_w(px(0.0))
+                    .overflow_x_hidden()
+                    .text_color(rgb(0xcccccc))
+                    .child(SharedString::from(format!("Find: {}", search.query))),
+            )
+            .when(search.wrapped, |bar| {
+                bar.child(div().text_color(rgb(0x888888)).child("↻"))
+            })
+            .child(
+                div()
+                    .flex_shrink_0()
+                    .text_color(rgb(0x888888))
+                    .child(SharedString::from(count)),
+            )
+            .child(
+                div()
+                    .id("search-case")
+                    .flex_shrink_0()
+                    .px(px(4.0))
+                    .bg(case_bg)
+                    .rounded(px(3.0))
+                    .cursor_pointer()
+                    .text_color(rgb(0xffffff))
+                    .child("Aa")
+                    .on_click(cx.listener(|this, _event, window, cx| {
+                        this.toggle_search_case(&ToggleSearchCase, window, cx);
+                    })),
+            )
+    }
+
+    fn open_command_palette(
+        &mut self,
+        _: &OpenCommandPalette,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let focus_handle = cx.focus_handle();
+        focus_handle.focus(window);
+        self.palette = Some(CommandPalette {
+            query: String::new(),
+            selected: 0,
+            focus_handle,
+        });
+        cx.notify();
+    }
+
+    fn close_palette(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.palette = None;
+        self.focus_handle.focus(window);
+        cx.notify();
+    }
+
+    fn dismiss_palette(&mut self, _: &DismissPalette, window: &mut Window, cx: &mut Context<Self>) {
+        self.close_palette(window, cx);
+    }
+
+    fn palette_matches(&self, commands: &[PaletteCommand]) -> Vec<usize> {
+        let names: Vec<&str> = commands.iter().map(|c| c.name).collect();
+        let query = self.palette.as_ref().map_or("", |p| p.query.as_str());
+        filter_by_query(query, &names)
+    }
+
+    fn palette_prev(&mut self, _: &PalettePrev, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(palette) = &mut self.palette {
+            palette.selected = palette.selected.saturating_sub(1);
+            cx.notify();
+        }
+    }
+
+    fn palette_next(&mut self, _: &PaletteNext, _window: &mut Window, cx: &mut Context<Self>) {
+        let count = self.palette_matches(&palette_commands()).len();
+        if let Some(palette) = &mut self.palette {
+            palette.selected = (palette.selected + 1).min(count.saturating_sub(1));
+            cx.notify();
+        }
+    }
+
+    fn confirm_palette(&mut self, _: &ConfirmPalette, window: &mut Window, cx: &mut Context<Self>) {
+        let selected = self.palette.as_ref().map_or(0, |p| p.selected);
+        self.run_palette_command(selected, window, cx);
+    }
+
+    fn run_palette_command(
+        &mut self,
+        position: usize,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let commands = palette_commands();
+        let Some(&ix) = self.palette_matches(&commands).get(position) else {
+            return;
+        };
+        self.close_palette(window, cx);
+        (commands[ix].run)(self, window, cx);
+    }
+
+    fn palette_key_down(
+        &mut self,
+        event: &KeyDownEvent,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        let Some(palette) = &mut self.palette else {
+            return;
+        };
+        let keystroke = &event.keystroke;
+        if keystroke.modifiers.control || keystroke.modifiers.platform {
+            return;
+        }
+        if keystroke.key == "backspace" {
+            palette.query.pop();
+        } else if let Some(text) = &keystroke.key_char {
+            palette.query.push_str(text);
+        } else {
+            return;
+        }
+        palette.selected = 0;
+        cx.stop_propagation();
+        cx.notify();
+    }
+
+    fn render_command_palette(
+        &self,
+        palette: &CommandPalette,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let commands = palette_commands();
+        let matches = self.palette_matches(&commands);
+
+        let mut list = div().flex().flex_col().py(px(4.0));
+        if matches.is_empty() {
+            list = list.child(
+                div()
+                    .px(px(10.0))
+                    .py(px(4.0))
+                    .text_color(rgb(0x888888))
+                    .child("No matching commands"),
+            );
+        }
+        for (position, &ix) in matches.iter().enumerate() {
+            let bg = if position == palette.selected {
+                rgb(0x094771)
+            } else {
+                rgb(0x252526)
+            };
+            list = list.child(
+                div()
+                    .id(ElementId::NamedInteger(
+                        "palette-item".into(),
+                        position as u64,
+                    ))
+                    .px(px(10.0))
+                    .py(px(4.0))
+                    .bg(bg)
+                    .cursor_pointer()
+                    .hover(|style| style.bg(rgb(0x2a2d2e)))
+                    .child(commands[ix].name)
+                    .on_click(cx.listener(move |this, _event, window, cx| {
+                        this.run_palette_command(position, window, cx);
+                    })),
+            );
+        }
+
+        div()
+            .absolute()
+            .top(px(40.0))
+            .left_0()
+            .right_0()
+            .flex()
+            .justify_center()
+            .child(
+                div()
+                    .key_context("CommandPalette")
+                    .track_focus(&palette.focus_handle)
+                    .on_action(cx.listener(Self::palette_prev))
+                    .on_action(cx.listener(Self::palette_next))
+                    .on_action(cx.listener(Self::confirm_palette))
+                    .on_action(cx.listener(Self::dismiss_palette))
+                    .on_key_down(cx.listener(Self::palette_key_down))
+                    .w(px(420.0))
+                    .bg(rgb(0x252526))
+                    .border_1()
+                    .border_color(rgb(0x454545))
+                    .rounded(px(4.0))
+                    .shadow_lg()
+                    .child(
+                        div()
+                            .px(px(10.0))
+                            .py(px(6.0))
+                            .border_b_1()
+                            .border_color(rgb(0x404040))
+                            .text_color(rgb(0xcccccc))
+                            .child(SharedString::from(format!("> {}", palette.query))),
+                    )
+                    .child(list),
+            )
+    }
+
+    fn toggle_shortcut_help(
+        &mut self,
+        _: &ToggleShortcutHelp,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.shortcut_help.take().is_some() {
+            self.focus_handle.focus(window);
+        } else if self.is_typing(window) {
+            cx.propagate();
+            return;
+        } else {
+            let focus_handle = cx.focus_handle();
+            focus_handle.focus(window);
+            self.shortcut_help = Some(focus_handle);
+        }
+        cx.notify();
+    }
+
+    fn render_shortcut_help(&self, focus_handle: &FocusHandle) -> impl IntoElement {
+        // Bindings that share a context and description are listed together,
+        // e.g. "Cmd+F / Ctrl+F".
+        let mut sections: Vec<(&str, Vec<ShortcutKeys>)> = Vec::new();
+        for shortcut in shortcuts() {
+            let keys = format_keystrokes(shortcut.keys);
+            let Some((_, entries)) = sections
+                .iter_mut()
+                .find(|(context, _)| *context == shortcut.context)
+            else {
+                sections.push((shortcut.context, vec![(shortcut.description, vec![keys])]));
+                continue;
+            };
+            match entries
+                .iter_mut()
+                .find(|(description, _)| *description == shortcut.description)
+            {
+                Some((_, all_keys)) => all_keys.push(keys),
+                None => entries.push((shortcut.description, vec![keys])),
+            }
+        }
+
+        let mut list = div().flex().flex_col().gap(px(2.0));
+        for (context, entries) in sections {
+            let title = match context {
+                "DiffViewer" => "Diff",
+                "FileTree" => "File tree",
+                "CommandPalette" => "Command palette",
+                "SearchBar" => "Search bar",
+                _ => "This help",
+            };
+            list = list.child(
+                div()
+                    .pt(px(8.0))
+                    .pb(px(2.0))
+                    .text_color(rgb(0x56b6c2))
+                    .child(title),
+            );
+            for (description, keys) in entries {
+                list = list.child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .gap(px(12.0))
+                        .child(
+                            div()
+                                .w(px(180.0))
+                                .flex_shrink_0()
+                                .text_color(rgb(0xffffff))
+                                .child(SharedString::from(keys.join(" / "))),
+                        )
+                        .child(description),
+                );
+            }
+        }
+
+        div()
+            .absolute()
+            .inset_0()
+            .p(px(40.0))
+            .flex()
+            .items_center()
+            .justify_center()
+            .bg(rgba(0x00000080))
+            .child(
+                div()
+                    .id("shortcut-help")
+                    .key_context("ShortcutHelp")
+                    .track_focus(focus_handle)
+                    .w(px(520.0))
+                    .max_h_full()
+                    .overflow_y_scroll()
+                    .px(px(16.0))
+                    .py(px(8.0))
+                    .bg(rgb(0x252526))
+                    .border_1()
+                    .border_color(rgb(0x454545))
+                    .rounded(px(4.0))
+                    .shadow_lg()
+                    .text_size(px(12.0))
+                    .text_color(rgb(0xcccccc))
+                    .child(
+                        div()
+                            .pb(px(4.0))
+                            .border_b_1()
+                            .border_color(rgb(0x404040))
+                            .child("Keyboard Shortcuts"),
+                    )
+                    .child(list),
+            )
+    }
+
+    fn file_menu_entries(&self, diff: &FileDiff) -> Vec<(SharedString, String)> {
+        let repo_root = self.repo_root.as_deref();
+        let mut entries = vec![
+            ("Copy Path".into(), absolute_path(repo_root, &diff.new_path)),
+            ("Copy Relative Path".into(), diff.new_path.to_string()),
+        ];
+        if diff.old_path != diff.new_path {
+            entries.push((
+                "Copy Old Path".into(),
+                absolute_path(repo_root, &diff.old_path),
+            ));
+            entries.push(("Copy Old Relative Path".into(), diff.old_path.to_string()));
+        }
+        entries
+    }
+
+    fn render_file_menu(&self, menu: &FileContextMenu, cx: &mut Context<Self>) -> impl IntoElement {
+        let entries = self
+            .diffs
+            .get(menu.index)
+            .map(|diff| self.file_menu_entries(diff))
+            .unwrap_or_default();
+        let mut list = div()
+            .id("file-menu")
+            .flex()
+            .flex_col()
+            .py(px(4.0))
+            .min_w(px(180.0))
+            .bg(rgb(0x252526))
+            .border_1()
+            .border_color(rgb(0x454545))
+            .rounded(px(4.0))
+            .shadow_lg()
+            .text_size(px(12.0))
+            .text_color(rgb(0xcccccc))
+            .on_mouse_down_out(cx.listener(|this, _event, _window, cx| {
+                this.file_menu = None;
+                cx.notify();
+            }));
+        for (position, (label, path)) in entries.into_iter().enumerate() {
+            list = list.child(
+                div()
+                    .id(ElementId::NamedInteger(
+                        "file-menu-item".into(),
+                        position as u64,
+                    ))
+                    .px(px(10.0))
+                    .py(px(4.0))
+                    .cursor_pointer()
+                    .hover(|style| style.bg(rgb(0x094771)))
+                    .child(label)
+                    .on_click(cx.listener(move |this, _event, _window, cx| {
+                        this.file_menu = None;
+                        this.copy_to_clipboard(path.clone(), format!("Copied {path}"), cx);
+                    })),
+            );
+        }
+        deferred(anchored().position(menu.position).child(list))
+    }
+
+    fn layout_options(&self) -> LayoutOptions {
+        LayoutOptions {
+            view_mode: self.view_mode,
+            alignment: self.sbs_alignment,
+            context_lines: self.context_lines,
+            ignore_blank_lines: self.ignore_blank_lines,
+            wrap_lines: self.wrap_lines,
+        }
+    }
+
+    /// "N changed / M shown" over the given files, counting only the lines
+    /// the current view options leave visible.
+    fn line_ratio_label<'a>(&self, diffs: impl IntoIterator<Item = &'a FileDiff>) -> SharedString {
+        let (changed, shown) = diffs.into_iter().fold((0, 0), |(changed, shown), diff| {
+            let counts = self.file_layout_options(diff).shown_line_counts(diff);
+            (changed + counts.0, shown + counts.1)
+        });
+        SharedString::from(format!("{changed} changed / {shown} shown"))
+    }
+
+    /// The layout of `diff` as rendered; deleted files shown as plain
+    /// content read top to bottom with nothing hidden, and files expanded
+    /// with "Full file" shown without trimming context.
+    fn file_layout_options(&self, diff: &FileDiff) -> LayoutOptions {
+        if self.shows_deleted_content(diff) {
+            LayoutOptions {
+                view_mode: ViewMode::Unified,
+                context_lines: None,
+                ignore_blank_lines: false,
+                ..self.layout_options()
+            }
+        } else if self.shows_full_file(diff) {
+            LayoutOptions {
+                context_lines: None,
+                ..self.layout_options()
+            }
+        } else {
+            self.layout_options()
+        }
+    }
+
+    /// The line at the top of the diff view and the average row height,
+    /// estimated from the scroll position since rows aren't measured.
+    fn scroll_anchor(&self) -> Option<(usize, f32)> {
+        let index = self.selected_index.filter(|_| !self.all_files)?;
+        let rows = self.file_rows(index, self.diffs.get(index)?);
+        let content_height = f32::from(self.diff_scroll.max_offset().height)
+            + f32::from(self.diff_scroll.bounds().size.height);
+        let row_height = content_height / rows.len().max(1) as f32;
+        if row_height <= 0.0 {
+            return None;
+        }
+        let top = -f32::from(self.diff_scroll.offset().y);
+        let line = line_at_row(&rows, (top / row_height) as usize)?;
+        Some((line, row_height))
+    }
+
+    /// Switches view mode, scrolling so the line that was at the top stays
+    /// in view.
+    fn set_view_mode(&mut self, mode: ViewMode) {
+        let anchor = self.scroll_anchor();
+        self.view_mode = mode;
+        if let Some((line, row_height)) = anchor {
+            let row = self.selected_index.and_then(|index| {
+                row_of_line(&self.file_rows(index, self.diffs.get(index)?), line)
+            });
+            if let Some(row) = row {
+                let y = px(row as f32 * row_height);
+                self.diff_scroll.set_offset(point(px(0.0), -y));
+            }
+        }
+        self.clear_hidden_focus();
+    }
+
+    /// The rows of the file at `index` as rendered, reused until the diff or
+    /// its layout options change.
+    fn file_rows(&self, index: usize, diff: &FileDiff) -> Rc<Vec<Row>> {
+        let options = self.file_layout_options(diff);
+        let mut cache = self.row_cache.borrow_mut();
+        match cache.get(&index) {
+            Some((cached, rows)) if *cached == options => rows.clone(),
+            _ => {
+                let rows = Rc::new(options.rows(diff));
+                cache.insert(index, (options, rows.clone()));
+                rows
+            }
+        }
+    }
+
+    fn visible_line_indices(&self, diff: &FileDiff) -> Vec<usize> {
+        self.file_layout_options(diff).visible_line_indices(diff)
+    }
+
+    /// Changes how many unchanged lines are kept around each change. Hunk
+    /// numbers shift with the context, so the hunk selection is dropped.
+    fn set_context_lines(&mut self, context: Option<usize>, cx: &mut Context<Self>) {
+        if self.context_lines == context {
+            return;
+        }
+        self.context_lines = context;
+        self.selected_hunks.clear();
+        self.clear_hidden_focus();
+        cx.notify();
+    }
+
+    fn clear_hidden_focus(&mut self) {
+        let hidden = self
+            .selected_diff()
+            .zip(self.focused_line)
+            .is_some_and(|(diff, ix)| !self.visible_line_indices(diff).contains(&ix));
+        if hidden {
+            self.focused_line = None;
+            self.selection_anchor = None;
+        }
+    }
+
+    fn show_toast(&mut self, message: impl Into<SharedString>, cx: &mut Context<Self>) {
+        self.toast = Some(message.into());
+        self.toast_epoch += 1;
+        let epoch = self.toast_epoch;
+        cx.notify();
+        cx.spawn(async move |this, cx| {
+            cx.background_executor().timer(TOAST_DURATION).await;
+            this.update(cx, |this, cx| {
+                if this.toast_epoch == epoch {
+                    this.toast = None;
+                    cx.notify();
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn copy_to_clipboard(&mut self, text: String, message: String, cx: &mut Context<Self>) {
+        cx.write_to_clipboard(ClipboardItem::new_string(text));
+        self.show_toast(message, cx);
+    }
+
+    /// Copies one side of the selected file: the text it was diffed from
+    /// when that was kept, otherwise rebuilt from the diff lines.
+    fn copy_side_content(&mut self, new: bool, cx: &mut Context<Self>) {
+        let Some(diff) = self.selected_diff() else {
+            return;
+        };
+        let (kept, side) = if new {
+            (&diff.new_content, "new")
+        } else {
+            (&diff.old_content, "old")
+        };
+        let text = match kept {
+            Some(content) => Some(content.to_string()),
+            None if new => diff.new_text(),
+            None => diff.old_text(),
+        };
+        let path = diff.new_path.clone();
+        match text {
+            Some(text) => {
+                let message = format!("Copied {side} content of {path}");
+                self.copy_to_clipboard(text, message, cx);
+            }
+            None => self.show_toast(format!("Only part of {path} is in the diff"), cx),
+        }
+    }
+
+    fn selected_diff(&self) -> Option<&FileDiff> {
+        self.selected_index.and_then(|i| self.diffs.get(i))
+    }
+
+    fn side_names(&self, diff: &FileDiff) -> (SharedString, SharedString) {
+        let (old_label, new_label) = &self.side_labels;
+        (
+            old_label
+                .clone()
+                .unwrap_or_else(|| diff.old_path.clone().into()),
+            new_label
+                .clone()
+                .unwrap_or_else(|| diff.new_path.clone().into()),
+        )
+    }
+
+    fn file_display_name(&self, diff: &FileDiff) -> SharedString {
+        let (old_name, new_name) = self.side_names(diff);
+        if old_name == new_name {
+            old_name
+        } else {
+            SharedString::from(format!("{old_name} → {new_name}"))
+        }
+    }
+
+    /// Sets the panel mode for this session without remembering it, e.g. from
+    /// `--tree` / `--list`.
+    pub fn set_panel_mode(&mut self, mode: PanelMode) {
+        self.panel_mode = mode;
+        self.panel_mode_fixed = true;
+    }
+
+    fn choose_panel_mode(&mut self, mode: PanelMode) {
+        self.set_panel_mode(mode);
+        let mut preferences = Preferences::load();
+        preferences.tree_panel = Some(mode == PanelMode::Tree);
+        if let Err(e) = preferences.save() {
+            eprintln!("Warning: {e}");
+        }
+    }
+
+    fn reveal_dir(&mut self, dir_path: &str) {
+        self.panel_mode = PanelMode::Tree;
+
+        let mut chain = dir_ancestors(dir_path);
+        chain.push(dir_path.to_string());
+
+        let all_dirs = all_dir_paths(&self.diffs);
+
+        for dir in &chain {
+            self.collapsed_dirs.remove(dir);
+            let parent = dir.rsplit_once('/').map(|(p, _)| p);
+            for other in &all_dirs {
+                let other_parent = other.rsplit_once('/').map(|(p, _)| p);
+                if other != dir && other_parent == parent && !chain.contains(other) {
+                    self.collapsed_dirs.insert(other.clone());
+                }
+            }
+        }
+        self.save_repo_state();
+    }
+
+    fn render_breadcrumbs(&self, diff: &FileDiff, cx: &mut Context<Self>) -> impl IntoElement {
+        let path = diff.new_path.to_string();
+        let file_name = path.rsplit('/').next().unwrap_or(&path).to_string();
+
+        let mut crumbs = div()
+            .flex()
+            .flex_row()
+            .items_center()
+            .justify_end()
+            .flex_grow()
+            .gap(px(2.0))
+            .text_size(px(12.0))
+            .text_color(rgb(0xcccccc));
+
+        if self.side_labels != (None, None) {
+            return crumbs.child(self.file_display_name(diff));
+        }
+
+        if diff.old_path != diff.new_path {
+            crumbs = crumbs.child(
+                div()
+                    .text_color(rgb(0x888888))
+                    .child(SharedString::from(format!("{} →", diff.old_path))),
+            );
+        }
+
+        for (i, dir_path) in dir_ancestors(&path).into_iter().enumerate() {
+            let name = dir_path.rsplit('/').next().unwrap_or(&dir_path).to_string();
+            crumbs = crumbs
+                .child(
+                    div()
+                        .id(ElementId::NamedInteger("crumb".into(), i as u64))
+                        .px(px(2.0))
+                        .rounded(px(3.0))
+                        .cursor_pointer()
+                        .hover(|style| style.bg(rgb(0x3c3c3c)))
+                        .child(SharedString::from(name))
+                        .on_click(cx.listener(move |this, _event, _window, _cx| {
+                            this.reveal_dir(&dir_path);
+                        })),
+                )
+                .child(div().text_color(rgb(0x666666)).child("/"));
+        }
+
+        crumbs.child(div().px(px(2.0)).child(SharedString::from(file_name)))
+    }
+
+    fn render_diff_line(
+        &self,
+        ix: usize,
+        diff: &FileDiff,
+        line: &DiffLine,
+        metrics: &LineMetrics,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let gutter_widths = metrics.gutter_widths;
+        let (bg, text_color, sign) = if metrics.plain {
+            self.theme.tag_style(ChangeTag::Equal)
+        } else {
+            self.theme.line_style(line)
+        };
+        let levels = line.indent_columns(self.tab_width) / self.tab_width.max(1);
+        let guides = metrics.guide_step.into_iter().flat_map(|step| {
+            (0..levels).map(move |level| {
+                div()
+                    .absolute()
+                    .top_0()
+                    .bottom_0()
+                    .left(px(4.0 + level as f32 * step))
+                    .w(px(1.0))
+                    .bg(rgba(0xffffff14))
+            })
+        });
+
+        let old_ln = line.old_lineno.map(|n| format!("{n}")).unwrap_or_default();
+        let new_ln = line.new_lineno.map(|n| format!("{n}")).unwrap_or_default();
+        let focus_border = if self.is_line_selected(metrics.file, ix) {
+            rgb(0x007acc)
+        } else {
+            bg
+        };
+        let reference = diff.line_reference(line);
+        let old_reference = reference.clone();
+        let content = line.content.clone();
+        let clipped = metrics
+            .visible_chars
+            .is_some_and(|max| line.content.chars().count() > max);
+        let full_text = SharedString::from(line.content.clone());
+        let line_text = self.render_line_text(
+            ElementId::NamedInteger("line-text".into(), ix as u64),
+            Some((metrics.file, ix)),
+            &line.content,
+            cx,
+        );
+
+        div()
+            .id(ElementId::NamedInteger("diff-line".into(), ix as u64))
+            .flex()
+            .flex_row()
+            .w_full()
+            .bg(bg)
+            .group("diff-line")
+            .border_l_2()
+            .border_color(focus_border)
+            .on_click(cx.listener(move |this, event: &ClickEvent, _window, cx| {
+                this.set_focused_line(ix, event.modifiers().shift);
+                if event.click_count() < 2 {
+                    this.highlighted_word = None;
+                }
+                cx.notify();
+            }))
+            .when(gutter_widths.0 > 0.0, |row| {
+                row.child(
+                    div()
+                        .id(ElementId::NamedInteger("old-gutter".into(), ix as u64))
+                        .w(px(gutter_widths.0))
+                        .flex_shrink_0()
+                        .text_right()
+                        .pr(px(4.0))
+                        .text_color(rgb(0x666666))
+                        .cursor_pointer()
+                        .hover(|style| style.text_color(rgb(0xcccccc)))
+                        .on_click(cx.listener(move |this, _event, _window, cx| {
+                            let message = format!("Copied {old_reference}");
+                            this.copy_to_clipboard(old_reference.clone(), message, cx);
+                        }))
+                        .child(old_ln),
+                )
+            })
+            .when(gutter_widths.1 > 0.0, |row| {
+                row.child(
+                    div()
+                        .id(ElementId::NamedInteger("new-gutter".into(), ix as u64))
+                        .w(px(gutter_widths.1))
+                        .flex_shrink_0()
+                        .text_right()
+                        .pr(px(4.0))
+                        .text_color(rgb(0x666666))
+                        .cursor_pointer()
+                        .hover(|style| style.text_color(rgb(0xcccccc)))
+                        .on_click(cx.listener(move |this, _event, _window, cx| {
+                            let message = format!("Copied {reference}");
+                            this.copy_to_clipboard(reference.clone(), message, cx);
+                        }))
+                        .child(new_ln),
+                )
+            })
+            .children(self.render_age_strip(metrics.file, Some(line)))
+            .child(
+                div()
+                    .w(px(16.0))
+                    .flex_shrink_0()
+                    .text_center()
+                    .text_color(text_color)
+                    .child(sign),
+            )
+            .child(
+                div()
+                    .id(ElementId::NamedInteger("line-content".into(), ix as u64))
+                    .relative()
+                    .pl(px(4.0))
+                    .flex_grow()
+                    .when(!self.wrap_lines, |text| {
+                        text.min_w(px(0.0)).overflow_x_hidden().whitespace_nowrap()
+                    })
+                    .when(clipped, |text| {
+                        text.tooltip(move |_window, cx| {
+                            let text = full_text.clone();
+                            cx.new(|_| TextTooltip { text }).into()
+                        })
+                    })
+                    .text_color(text_color)
+                    .children(guides)
+                    .child(line_text),
+            )
+            .child(
+                div()
+                    .id(ElementId::NamedInteger("copy-line".into(), ix as u64))
+                    .flex_shrink_0()
+                    .px(px(6.0))
+                    .invisible()
+                    .group_hover("diff-line", |style| style.visible())
+                    .cursor_pointer()
+                    .text_color(rgb(0x888888))
+                    .hover(|style| style.text_color(rgb(0xffffff)))
+                    .on_click(cx.listener(move |this, _event, _window, cx| {
+                        cx.stop_propagation();
+                        this.copy_to_clipboard(content.to_string(), "Copied line".to_string(), cx);
+                    }))
+                    .child("⧉"),
+            )
+    }
+
+    fn force_diff(&mut self, index: usize, cx: &mut Context<Self>) {
+        let Some(diff) = self.diffs.get(index) else {
+            return;
+        };
+        let mut forced = FileDiff::from_files(&diff.old_path, &diff.new_path);
+        forced.generated = diff.generated;
+        forced.status = diff.status;
+        forced.staging = diff.staging;
+        self.diffs[index] = forced;
+        self.row_cache.get_mut().remove(&index);
+        self.focused_line = None;
+        self.selection_anchor = None;
+        cx.notify();
+    }
+
+    fn toggle_image_compare(&mut self) {
+        self.image_compare = match self.image_compare {
+            ImageCompareMode::SideBySide => ImageCompareMode::Overlay,
+            ImageCompareMode::Overlay => ImageCompareMode::SideBySide,
+        };
+    }
+
+    fn render_image_diff(&self, diff: &FileDiff, cx: &mut Context<Self>) -> impl IntoElement {
+        let (old_name, new_name) = self.side_names(diff);
+        let image = |path: &Arc<str>| {
+            let path = PathBuf::from(path.as_ref());
+            path.exists().then(|| img(path).max_w_full())
+        };
+        let (old_image, new_image) = (image(&diff.old_path), image(&diff.new_path));
+        let missing = || {
+            div()
+                .text_size(px(11.0))
+                .text_color(rgb(0x666666))
+                .child("(no image)")
+        };
+        let column = |name: SharedString, image: Option<Img>| {
+            div()
+                .flex()
+                .flex_col()
+                .flex_1()
+                .min_w(px(0.0))
+                .gap(px(4.0))
+                .child(
+                    div()
+                        .text_size(px(11.0))
+                        .text_color(rgb(0x999999))
+                        .child(name),
+                )
+                .child(match image {
+                    Some(image) => image.into_any_element(),
+                    None => missing().into_any_element(),
+                })
+        };
+        let overlay = self.image_compare == ImageCompareMode::Overlay;
+        let comparison = if overlay {
+            let new_layer =
+                new_image.map(|image| div().absolute().top_0().left_0().opacity(0.5).child(image));
+            div()
+                .relative()
+                .children(old_image)
+                .children(new_layer)
+                .into_any_element()
+        } else {
+            div()
+                .flex()
+                .flex_row()
+                .gap(px(16.0))
+                .child(column(old_name, old_image))
+                .child(column(new_name, new_image))
+                .into_any_element()
+        };
+
+        div()
+            .flex()
+            .flex_col()
+            .items_start()
+            .gap(px(8.0))
+            .p(px(20.0))
+            .child(
+                div()
+                    .id("btn-image-compare")
+                    .px(px(8.0))
+                    .py(px(2.0))
+                    .bg(rgb(0x3c3c3c))
+                    .rounded(px(3.0))
+                    .cursor_pointer()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0xffffff))
+                    .hover(|style| style.bg(rgb(0x007acc)))
+                    .child(if overlay { "Side by side" } else { "Overlay" })
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.toggle_image_compare();
+                        cx.notify();
+                    })),
+            )
+            .child(comparison)
+    }
+
+    fn render_too_large(
+        &self,
+        index: usize,
+        size: u64,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .items_start()
+            .gap(px(8.0))
+            .p(px(20.0))
+            .text_color(rgb(0x888888))
+            .child(SharedString::from(format!(
+                "File too large to diff ({size} bytes)"
+            )))
+            .child(
+                div()
+                    .id("btn-force-diff")
+                    .px(px(8.0))
+                    .py(px(2.0))
+                    .bg(rgb(0x3c3c3c))
+                    .rounded(px(3.0))
+                    .cursor_pointer()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0xffffff))
+                    .hover(|style| style.bg(rgb(0x007acc)))
+                    .child("Diff anyway")
+                    .on_click(cx.listener(move |this, _event, _window, cx| {
+                        this.force_diff(index, cx);
+                    })),
+            )
+    }
+
+    fn render_hunk_header(
+        &self,
+        file: usize,
+        hunk: usize,
+        header: SharedString,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let is_selected = self
+            .selected_hunks
+            .get(&file)
+            .is_some_and(|hunks| hunks.contains(&hunk));
+        div()
+            .w_full()
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap(px(6.0))
+            .px(px(8.0))
+            .py(px(2.0))
+            .my(px(2.0))
+            .bg(rgb(0x1f2a33))
+            .text_color(rgb(0x56b6c2))
+            .child(
+                div()
+                    .id(ElementId::NamedInteger("hunk-select".into(), hunk as u64))
+                    .cursor_pointer()
+                    .text_color(rgb(0x888888))
+                    .hover(|style| style.text_color(rgb(0xffffff)))
+                    .child(if is_selected { "☑" } else { "☐" })
+                    .on_click(cx.listener(move |this, _event, _window, cx| {
+                        this.toggle_hunk(file, hunk, cx);
+                    })),
+            )
+            .child(header)
+            .children(self.context_lines.map(|context| {
+                let control = |id: &'static str, label: &'static str| {
+                    div()
+                        .id(ElementId::NamedInteger(id.into(), hunk as u64))
+                        .px(px(4.0))
+                        .cursor_pointer()
+                        .text_color(rgb(0x888888))
+                        .hover(|style| style.text_color(rgb(0xffffff)))
+                        .child(label)
+                };
+                div()
+                    .ml_auto()
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .text_size(px(11.0))
+                    .child(control("context-less", "−").on_click(cx.listener(
+                        move |this, _event, _window, cx| {
+                            let less = context.saturating_sub(CONTEXT_STEP);
+                            this.set_context_lines(Some(less), cx);
+                        },
+                    )))
+                    .child(
+                        div()
+                            .text_color(rgb(0x888888))
+                            .child(SharedString::from(format!("{context} context"))),
+                    )
+                    .child(control("context-more", "+").on_click(cx.listener(
+                        move |this, _event, _window, cx| {
+                            this.set_context_lines(Some(context.saturating_add(CONTEXT_STEP)), cx);
+                        },
+                    )))
+            }))
+    }
+
+    fn gutter_width(&self, diff: &FileDiff, window: &Window) -> f32 {
+        if !self.show_line_numbers {
+            return 0.0;
+        }
+        let width = measure_text_width(
+            &diff.max_lineno().to_string(),
+            &self.font_family,
+            self.text_size(),
+            window,
+        );
+        f32::from(width) + 12.0
+    }
+
+    fn render_file_diff(
+        &self,
+        index: usize,
+        diff: &FileDiff,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        // Drop a line-number column that would be blank on every line, as in
+        // added or deleted files.
+        let gutter_width = self.gutter_width(diff, window);
+        let (has_old, has_new) = diff.line_number_sides();
+        let gutter_widths = (
+            if has_old { gutter_width } else { 0.0 },
+            if has_new { gutter_width } else { 0.0 },
+        );
+        let char_width = f32::from(measure_text_width(
+            " ",
+            &self.font_family,
+            self.text_size(),
+            window,
+        ));
+        let guide_step = self
+            .show_indent_guides
+            .then_some(char_width * self.tab_width as f32);
+        let visible_chars = (!self.wrap_lines).then(|| {
+            let panel = if self.panel_visible {
+                f32::from(self.panel_width) + DRAG_HANDLE_WIDTH
+            } else {
+                0.0
+            };
+            let available = f32::from(window.bounds().size.width)
+                - panel
+                - gutter_widths.0
+                - gutter_widths.1
+                - DIFF_LINE_CHROME_WIDTH;
+            (available / char_width).max(1.0) as usize
+        });
+        let plain = self.shows_deleted_content(diff);
+        let metrics = LineMetrics {
+            file: index,
+            gutter_widths,
+            guide_step,
+            visible_chars,
+            plain,
+        };
+
+        let mut content = div().flex().flex_col().w_full();
+        for row in self.file_rows(index, diff).iter() {
+            match row {
+                Row::HunkHeader { hunk, header } => {
+                    content =
+                        content.child(self.render_hunk_header(index, *hunk, header.clone(), cx));
+                }
+                Row::Line(ix) => {
+                    content = content.child(self.render_diff_line(
+                        *ix,
+                        diff,
+                        &diff.lines[*ix],
+                        &metrics,
+                        cx,
+                    ));
+                }
+                Row::SideBySide { .. } => {}
+            }
+        }
+
+        div()
+            .flex()
+            .flex_col()
+            .w_full()
+            .mb(px(16.0))
+            .child(div().w_full().p(px(4.0)).child(content))
+    }
+
+    fn render_changes_only(
+        &self,
+        index: usize,
+        diff: &FileDiff,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let mut content = div().flex().flex_col().w_full();
+        for ix in self.visible_line_indices(diff) {
+            let line = &diff.lines[ix];
+            let (bg, text_color, sign) = self.theme.line_style(line);
+            let reference = SharedString::from(format!("{}:", diff.line_reference(line)));
+            let focus_border = if self.is_line_selected(index, ix) {
+                rgb(0x007acc)
+            } else {
+                bg
+            };
+            content = content.child(
+                div()
+                    .id(ElementId::NamedInteger("change-line".into(), ix as u64))
+                    .flex()
+                    .flex_row()
+                    .w_full()
+                    .bg(bg)
+                    .border_l_2()
+                    .border_color(focus_border)
+                    .on_click(cx.listener(move |this, event: &ClickEvent, _window, cx| {
+                        this.set_focused_line(ix, event.modifiers().shift);
+                        if event.click_count() < 2 {
+                            this.highlighted_word = None;
+                        }
+                        cx.notify();
+                    }))
+                    .child(div().w(px(4.0)).flex_shrink_0().bg(text_color))
+                    .child(
+                        div()
+                            .flex_shrink_0()
+                            .pl(px(4.0))
+                            .pr(px(8.0))
+                            .text_color(rgb(0x888888))
+                            .child(reference),
+                    )
+                    .child(
+                        div()
+                            .w(px(16.0))
+                            .flex_shrink_0()
+                            .text_center()
+                            .text_color(text_color)
+                            .child(sign),
+                    )
+                    .child(
+                        div()
+                            .pl(px(4.0))
+                            .flex_grow()
+                            .min_w(px(0.0))
+                            .overflow_x_hidden()
+                            .when(!self.wrap_lines, |text| text.whitespace_nowrap())
+                            .text_color(text_color)
+                            .child(self.render_line_text(
+                                ElementId::NamedInteger("change-text".into(), ix as u64),
+                                Some((index, ix)),
+                                &line.content,
+                                cx,
+                            )),
+                    ),
+            );
+        }
+
+        div()
+            .flex()
+            .flex_col()
+            .w_full()
+            .mb(px(16.0))
+            .child(div().w_full().p(px(4.0)).child(content))
+    }
+
+    fn render_side_by_side_line(
+        &self,
+        file: usize,
+        row_ix: usize,
+        sbs_line: &SideBySideLine,
+        line_indices: (Option<usize>, Option<usize>),
+        gutter_width: f32,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let (left_bg, left_text, left_ln, left_content) = match &sbs_line.left {
+            Some(line) => {
+                let (bg, tc, _) = self.theme.line_style(line);
+                let ln = line.old_lineno.map(|n| format!("{n}")).unwrap_or_default();
+                (bg, tc, ln, line.content.clone())
+            }
+            None => (rgb(0x262626), rgb(0x666666), String::new(), Arc::from("")),
+        };
+
+        let (right_bg, right_text, right_ln, right_content) = match &sbs_line.right {
+            Some(line) => {
+                let (bg, tc, _) = self.theme.line_style(line);
+                let ln = line.new_lineno.map(|n| format!("{n}")).unwrap_or_default();
+                (bg, tc, ln, line.content.clone())
+            }
+            None => (rgb(0x262626), rgb(0x666666), String::new(), Arc::from("")),
+        };
+
+        let (left_ix, right_ix) = line_indices;
+        let is_focused = [left_ix, right_ix]
+            .into_iter()
+            .flatten()
+            .any(|ix| self.is_line_selected(file, ix));
+        let focus_border = match (is_focused, self.sbs_new_left) {
+            (true, _) => rgb(0x007acc),
+            (false, true) => right_bg,
+            (false, false) => left_bg,
+        };
+
+        let old_half = div()
+            .flex()
+            .flex_row()
+            .flex_1()
+            .min_w(px(0.0))
+            .bg(left_bg)
+            .when(self.show_line_numbers, |half| {
+                half.child(
+                    div()
+                        .w(px(gutter_width))
+                        .flex_shrink_0()
+                        .text_right()
+                        .pr(px(4.0))
+                        .text_color(rgb(0x666666))
+                        .child(left_ln),
+                )
+            })
+            .child(
+                div()
+                    .pl(px(4.0))
+                    .flex_grow()
+                    .min_w(px(0.0))
+                    .overflow_x_hidden()
+                    .text_color(left_text)
+                    .child(self.render_line_text(
+                        ElementId::NamedInteger("left-text".into(), row_ix as u64),
+                        left_ix.map(|ix| (file, ix)),
+                        &left_content,
+                        cx,
+                    )),
+            );
+
+        let new_half = div()
+            .flex()
+            .flex_row()
+            .flex_1()
+            .min_w(px(0.0))
+            .bg(right_bg)
+            .when(self.show_line_numbers, |half| {
+                half.child(
+                    div()
+                        .w(px(gutter_width))
+                        .flex_shrink_0()
+                        .text_right()
+                        .pr(px(4.0))
+                        .text_color(rgb(0x666666))
+                        .child(right_ln),
+                )
+            })
+            .children(self.render_age_strip(file, sbs_line.right.as_ref()))
+            .child(
+                div()
+                    .pl(px(4.0))
+                    .flex_grow()
+                    .min_w(px(0.0))
+                    .overflow_x_hidden()
+                    .text_color(right_text)
+                    .child(self.render_line_text(
+                        ElementId::NamedInteger("right-text".into(), row_ix as u64),
+                        right_ix.map(|ix| (file, ix)),
+                        &right_content,
+                        cx,
+                    )),
+            );
+
+        let (first, second) = if self.sbs_new_left {
+            (new_half, old_half)
+        } else {
+            (old_half, new_half)
+        };
+
+        div()
+            .id(ElementId::NamedInteger("sbs-row".into(), row_ix as u64))
+            .flex()
+            .flex_row()
+            .w_full()
+            .border_l_2()
+            .border_color(focus_border)
+            .on_click(cx.listener(move |this, event: &ClickEvent, _window, cx| {
+                if let Some(ix) = left_ix.or(right_ix) {
+                    this.set_focused_line(ix, event.modifiers().shift);
+                }
+                if event.click_count() < 2 {
+                    this.highlighted_word = None;
+                }
+                cx.notify();
+            }))
+            .child(first)
+            .child(
+                div()
+                    .w(px(1.0))
+                    .flex_shrink_0()
+                    .bg(rgb(0x404040)),
+            )
+            .child(second)
+    }
+
+    fn render_side_by_side_diff(
+        &self,
+        index: usize,
+        diff: &FileDiff,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let gutter_width = self.gutter_width(diff, window);
+
+        let (old_name, new_name) = self.side_names(diff);
+        let column_header = |name: SharedString| {
+            div()
+                .flex_1()
+                .min_w(px(0.0))
+                .px(px(8.0))
+                .py(px(2.0))
+                .overflow_x_hidden()
+                .text_size(px(11.0))
+                .text_color(rgb(0x999999))
+                .child(name)
+        };
+        let mut content = div().flex().flex_col().w_full().child(
+            div()
+                .flex()
+                .flex_row()
+                .w_full()
+                .border_b_1()
+                .border_color(rgb(0x404040))
+                .children(if self.sbs_new_left {
+                    [column_header(new_name), column_header(old_name)]
+                } else {
+                    [column_header(old_name), column_header(new_name)]
+                }),
+        );
+        let mut row_ix = 0;
+        for row in self.file_rows(index, diff).iter() {
+            match row {
+                Row::HunkHeader { hunk, header } => {
+                    content =
+                        content.child(self.render_hunk_header(index, *hunk, header.clone(), cx));
+                }
+                Row::SideBySide { line, indices } => {
+                    content = content.child(self.render_side_by_side_line(
+                        index,
+                        row_ix,
+                        line,
+                        *indices,
+                        gutter_width,
+                        cx,
+                    ));
+                    row_ix += 1;
+                }
+                Row::Line(_) => {}
+            }
+        }
+
+        div()
+            .flex()
+            .flex_col()
+            .w_full()
+            .mb(px(16.0))
+            .child(div().w_full().p(px(4.0)).child(content))
+    }
+
+    /// A toolbar button that is a Tab stop with a focus ring. gpui has no
+    /// accessibility tree to publish labels to, so the [`TOOLBAR_BUTTONS`]
+    /// label is shown as a tooltip instead.
+    fn toolbar_button(&self, id: &'static str, text: &'static str, active: bool) -> Stateful<Div> {
+        let label = TOOLBAR_BUTTONS
+            .iter()
+            .find(|(button, _)| *button == id)
+            .map(|(_, label)| SharedString::from(*label));
+        div()
+            .id(id)
+            .when_some(self.toolbar_focus.get(id), |button, handle| {
+                button.track_focus(handle)
+            })
+            .px(px(8.0))
+            .py(px(2.0))
+            .bg(if active { rgb(0x007acc) } else { rgb(0x3c3c3c) })
+            .rounded(px(3.0))
+            .border_1()
+            .border_color(rgba(0x00000000))
+            .focus(|style| style.border_color(rgb(0xffffff)))
+            .cursor_pointer()
+            .text_size(px(11.0))
+            .text_color(rgb(0xffffff))
+            .child(text)
+            .when_some(label, |button, label| {
+                button.tooltip(move |_window, cx| {
+                    let text = label.clone();
+                    cx.new(|_| TextTooltip { text }).into()
+                })
+            })
     }
 
     fn render_toolbar(&self, diff: &FileDiff, cx: &mut Context<Self>) -> impl IntoElement {
-        let header_text = Self::file_display_name(diff);
-        let unified_active = self.view_mode == ViewMode::Unified;
-        let sbs_active = self.view_mode == ViewMode::SideBySide;
-
-        let unified_bg = if unified_active {
-            rgb(0x007acc)
-        } else {
-            rgb(0x3c3c3c)
-        };
-        let sbs_bg = if sbs_active {
-            rgb(0x007acc)
-        } else {
-            rgb(0x3c3c3c)
-        };
+        let deleted_content = diff.is_full_deletion().then(|| {
+            let active = self.shows_deleted_content(diff);
+            let index = self.selected_index;
+            self.toolbar_button("btn-deleted-content", "Old content", active)
+                .on_click(cx.listener(move |this, _event, _window, cx| {
+                    if let Some(index) = index {
+                        this.toggle_deleted_content(index, cx);
+                    }
+                }))
+        });
+        let trimmed = self.context_lines.is_some() && !self.shows_deleted_content(diff);
+        let full_file = trimmed.then(|| {
+            let active = self.shows_full_file(diff);
+            let index = self.selected_index;
+            self.toolbar_button("btn-full-file", "Full file", active)
+                .on_click(cx.listener(move |this, _event, _window, cx| {
+                    if let Some(index) = index {
+                        this.toggle_full_file(index, cx);
+                    }
+                }))
+        });
+        let conflict_count = diff.lines.iter().filter(|l| l.is_conflict_marker()).count();
+        let conflict_badge = (conflict_count > 0).then(|| {
+            let label = if conflict_count == 1 {
+                "⚠ 1 conflict marker".to_string()
+            } else {
+                format!("⚠ {conflict_count} conflict markers")
+            };
+            div()
+                .id("conflict-markers")
+                .ml(px(8.0))
+                .px(px(8.0))
+                .py(px(2.0))
+                .flex_shrink_0()
+                .bg(self.theme.conflict.bg)
+                .rounded(px(3.0))
+                .cursor_pointer()
+                .text_size(px(11.0))
+                .text_color(self.theme.conflict.text)
+                .child(SharedString::from(label))
+                .on_click(cx.listener(|this, _event, _window, cx| {
+                    this.jump_to_conflict_marker(true, cx);
+                }))
+        });
 
         div()
             .w_full()
@@ -351,50 +3614,118 @@ impl DiffViewer {
             .border_color(rgb(0x404040))
             .gap(px(4.0))
             .child(
-                div()
-                    .id("btn-unified")
-                    .px(px(8.0))
-                    .py(px(2.0))
-                    .bg(unified_bg)
-                    .rounded(px(3.0))
-                    .cursor_pointer()
-                    .text_size(px(11.0))
-                    .text_color(rgb(0xffffff))
-                    .child("Unified")
-                    .on_click(cx.listener(|this, _event, _window, _cx| {
-                        this.view_mode = ViewMode::Unified;
+                self.toolbar_button(
+                    "btn-unified",
+                    "Unified",
+                    self.view_mode == ViewMode::Unified,
+                )
+                .on_click(cx.listener(|this, _event, _window, _cx| {
+                    this.set_view_mode(ViewMode::Unified);
+                })),
+            )
+            .child(
+                self.toolbar_button(
+                    "btn-side-by-side",
+                    "Side-by-Side",
+                    self.view_mode == ViewMode::SideBySide,
+                )
+                .on_click(cx.listener(|this, _event, _window, _cx| {
+                    this.set_view_mode(ViewMode::SideBySide);
+                })),
+            )
+            .child(
+                self.toolbar_button(
+                    "btn-changes-only",
+                    "Changes",
+                    self.view_mode == ViewMode::ChangesOnly,
+                )
+                .on_click(cx.listener(|this, _event, _window, _cx| {
+                    this.set_view_mode(ViewMode::ChangesOnly);
+                })),
+            )
+            .child(
+                self.toolbar_button("btn-all-files", "All files", self.all_files)
+                    .ml(px(8.0))
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.toggle_all_files();
+                        cx.notify();
                     })),
             )
             .child(
-                div()
-                    .id("btn-side-by-side")
-                    .px(px(8.0))
-                    .py(px(2.0))
-                    .bg(sbs_bg)
-                    .rounded(px(3.0))
-                    .cursor_pointer()
-                    .text_size(px(11.0))
-                    .text_color(rgb(0xffffff))
-                    .child("Side-by-Side")
-                    .on_click(cx.listener(|this, _event, _window, _cx| {
-                        this.view_mode = ViewMode::SideBySide;
+                self.toolbar_button("btn-line-numbers", "Line #", self.show_line_numbers)
+                    .ml(px(8.0))
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.show_line_numbers = !this.show_line_numbers;
+                        cx.notify();
                     })),
             )
             .child(
-                div()
-                    .flex_grow()
-                    .text_size(px(12.0))
-                    .text_color(rgb(0xcccccc))
-                    .text_right()
-                    .child(header_text),
+                self.toolbar_button(
+                    "btn-ignore-blank-lines",
+                    "Ignore blank",
+                    self.ignore_blank_lines,
+                )
+                .on_click(cx.listener(|this, _event, _window, cx| {
+                    this.ignore_blank_lines = !this.ignore_blank_lines;
+                    this.clear_hidden_focus();
+                    cx.notify();
+                })),
             )
+            .child(
+                self.toolbar_button("btn-reverse", "Reverse", false)
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.reverse_selected_diff(cx);
+                    })),
+            )
+            .child(
+                self.toolbar_button("btn-copy-old", "Copy old", false)
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.copy_side_content(false, cx);
+                    })),
+            )
+            .child(
+                self.toolbar_button("btn-copy-new", "Copy new", false)
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.copy_side_content(true, cx);
+                    })),
+            )
+            .children(deleted_content)
+            .children(full_file)
+            .child(self.render_breadcrumbs(diff, cx))
+            .children(conflict_badge)
+            .children(self.show_line_ratio.then(|| {
+                div()
+                    .ml(px(8.0))
+                    .flex_shrink_0()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0x999999))
+                    .child(self.line_ratio_label([diff]))
+            }))
+            .children(self.source_label.clone().map(|label| {
+                div()
+                    .ml_auto()
+                    .pl(px(8.0))
+                    .flex_shrink_0()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0x999999))
+                    .child(label)
+            }))
     }
 
-    fn render_file_item(&self, i: usize, diff: &FileDiff, indent: f32, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render_file_item(&self, i: usize, diff: &FileDiff, indent: f32, tree_focused: bool, window: &Window, cx: &mut Context<Self>) -> impl IntoElement {
         let is_selected = self.selected_index == Some(i);
-        let name = match self.panel_mode {
-            PanelMode::List => Self::file_display_name(diff),
-            PanelMode::Tree => {
+        let is_reviewed = self.reviewed.contains(&diff.new_path);
+        let full_name = self.file_display_name(diff);
+        let name = match (&diff.commit, self.panel_mode) {
+            (Some(commit), _) => commit.clone().into(),
+            (None, PanelMode::List) => {
+                let available = f32::from(self.panel_width) - FILE_ITEM_CHROME_WIDTH;
+                let char_width =
+                    measure_text_width("m", &self.font_family, FILE_NAME_TEXT_SIZE, window);
+                let max_chars = (available / f32::from(char_width)).max(1.0) as usize;
+                SharedString::from(truncate_middle(&full_name, max_chars))
+            }
+            (None, PanelMode::Tree) => {
                 let path = diff.new_path.to_string();
                 SharedString::from(
                     path.rsplit('/').next().unwrap_or(&path).to_string(),
@@ -406,11 +3737,45 @@ impl DiffViewer {
         let deletions = diff.lines.iter().filter(|l| l.tag == ChangeTag::Delete).count();
         let stats = SharedString::from(format!("+{additions} −{deletions}"));
 
-        let bg = if is_selected {
+        let bg = if tree_focused {
+            rgb(0x04395e)
+        } else if is_selected {
             rgb(0x37373d)
         } else {
             rgb(0x252526)
         };
+        let name_color = if diff.generated || diff.is_unchanged() {
+            rgb(0x808080)
+        } else {
+            rgb(0xcccccc)
+        };
+        let mixed_line_endings = diff.mixed_line_endings.then(|| {
+            div()
+                .id(ElementId::NamedInteger("eol-badge".into(), i as u64))
+                .flex_shrink_0()
+                .text_size(px(10.0))
+                .text_color(self.theme.conflict.text)
+                .child("⚠ EOL")
+                .tooltip(|_window, cx| {
+                    let text = "Mixes LF and CRLF line endings".into();
+                    cx.new(|_| TextTooltip { text }).into()
+                })
+        });
+        let status_badge = diff.status.map(|status| {
+            let color = match status {
+                FileStatus::Added => rgb(0x81b88b),
+                FileStatus::Modified => rgb(0xe2c08d),
+                FileStatus::Deleted => rgb(0xc74e39),
+                FileStatus::Untracked => rgb(0x73c991),
+            };
+            div()
+                .ml_auto()
+                .pl(px(4.0))
+                .flex_shrink_0()
+                .text_size(px(11.0))
+                .text_color(color)
+                .child(status.letter())
+        });
 
         div()
             .id(ElementId::NamedInteger("file-item".into(), i as u64))
@@ -421,15 +3786,45 @@ impl DiffViewer {
             .bg(bg)
             .cursor_pointer()
             .hover(|style| style.bg(rgb(0x2a2d2e)))
+            .when(is_reviewed, |item| item.opacity(0.5))
+            .tooltip(move |_window, cx| {
+                let text = full_name.clone();
+                cx.new(|_| TextTooltip { text }).into()
+            })
             .on_click(cx.listener(move |this, _event, _window, _cx| {
-                this.selected_index = Some(i);
+                this.select_file(i);
+                if this.panel_mode == PanelMode::Tree {
+                    this.set_tree_cursor(&TreeRow::File(i));
+                }
             }))
+            .on_mouse_down(
+                MouseButton::Right,
+                cx.listener(move |this, event: &MouseDownEvent, _window, cx| {
+                    this.file_menu = Some(FileContextMenu {
+                        index: i,
+                        position: event.position,
+                    });
+                    cx.notify();
+                }),
+            )
             .child(
                 div()
                     .flex()
                     .flex_row()
                     .items_center()
                     .gap(px(4.0))
+                    .child(
+                        div()
+                            .id(ElementId::NamedInteger("reviewed".into(), i as u64))
+                            .text_size(px(11.0))
+                            .text_color(rgb(0x888888))
+                            .hover(|style| style.text_color(rgb(0xffffff)))
+                            .child(if is_reviewed { "☑" } else { "☐" })
+                            .on_click(cx.listener(move |this, _event, _window, cx| {
+                                cx.stop_propagation();
+                                this.toggle_reviewed(i, cx);
+                            })),
+                    )
                     .child(
                         div()
                             .text_size(px(11.0))
@@ -438,11 +3833,13 @@ impl DiffViewer {
                     )
                     .child(
                         div()
-                            .text_size(px(12.0))
-                            .text_color(rgb(0xcccccc))
+                            .text_size(px(FILE_NAME_TEXT_SIZE))
+                            .text_color(name_color)
                             .overflow_x_hidden()
                             .child(name),
-                    ),
+                    )
+                    .children(mixed_line_endings)
+                    .children(status_badge),
             )
             .child(
                 div()
@@ -458,6 +3855,8 @@ impl DiffViewer {
         nodes: &BTreeMap<String, TreeNode>,
         parent_path: &str,
         depth: usize,
+        cursor_row: Option<&TreeRow>,
+        window: &Window,
         cx: &mut Context<Self>,
     ) -> Vec<gpui::AnyElement> {
         let mut elements: Vec<gpui::AnyElement> = Vec::new();
@@ -483,6 +3882,7 @@ impl DiffViewer {
 
                 let is_collapsed = self.collapsed_dirs.contains(&dir_path);
                 let arrow = if is_collapsed { "▶" } else { "▼" };
+                let is_focused = cursor_row == Some(&TreeRow::Dir(dir_path.clone()));
                 let dir_path_clone = dir_path.clone();
 
                 let dir_header = div()
@@ -491,14 +3891,12 @@ impl DiffViewer {
                     .pl(px(12.0 + indent))
                     .pr(px(12.0))
                     .py(px(4.0))
+                    .when(is_focused, |header| header.bg(rgb(0x04395e)))
                     .cursor_pointer()
                     .hover(|style| style.bg(rgb(0x2a2d2e)))
                     .on_click(cx.listener(move |this, _event, _window, _cx| {
-                        if this.collapsed_dirs.contains(&dir_path_clone) {
-                            this.collapsed_dirs.remove(&dir_path_clone);
-                        } else {
-                            this.collapsed_dirs.insert(dir_path_clone.clone());
-                        }
+                        this.toggle_dir(&dir_path_clone);
+                        this.set_tree_cursor(&TreeRow::Dir(dir_path_clone.clone()));
                     }))
                     .child(
                         div()
@@ -530,8 +3928,14 @@ impl DiffViewer {
                 elements.push(dir_header.into_any_element());
 
                 if !is_collapsed {
-                    let child_elements =
-                        self.render_tree_nodes(children, &dir_path, depth + 1, cx);
+                    let child_elements = self.render_tree_nodes(
+                        children,
+                        &dir_path,
+                        depth + 1,
+                        cursor_row,
+                        window,
+                        cx,
+                    );
                     elements.extend(child_elements);
                 }
             }
@@ -540,8 +3944,9 @@ impl DiffViewer {
         for (_key, node) in &files {
             if let TreeNode::File { diff_index, .. } = node {
                 let diff = &self.diffs[*diff_index];
+                let is_focused = cursor_row == Some(&TreeRow::File(*diff_index));
                 elements.push(
-                    self.render_file_item(*diff_index, diff, indent, cx)
+                    self.render_file_item(*diff_index, diff, indent, is_focused, window, cx)
                         .into_any_element(),
                 );
             }
@@ -550,13 +3955,111 @@ impl DiffViewer {
         elements
     }
 
-    fn render_file_panel(&self, cx: &mut Context<Self>) -> impl IntoElement {
+    fn render_footer(&self) -> impl IntoElement {
+        let mut items: Vec<SharedString> = Vec::new();
+        match self.selected_diff().zip(self.selected_index) {
+            Some((diff, index)) => {
+                items.push(format!("File {} of {}", index + 1, self.diffs.len()).into());
+                let focused = self.focused_line.and_then(|ix| diff.lines.get(ix));
+                if let Some(line) = focused {
+                    let lineno = |n: Option<usize>| n.map_or("-".to_string(), |n| n.to_string());
+                    items.push(
+                        format!(
+                            "Ln {} → {}",
+                            lineno(line.old_lineno),
+                            lineno(line.new_lineno)
+                        )
+                        .into(),
+                    );
+                }
+                let count = |tag| diff.lines.iter().filter(|l| l.tag == tag).count();
+                let (additions, deletions) = (count(ChangeTag::Insert), count(ChangeTag::Delete));
+                items.push(format!("+{additions} −{deletions}").into());
+            }
+            None => items.push("No file".into()),
+        }
+        items.push(
+            match self.view_mode {
+                ViewMode::Unified => "Unified",
+                ViewMode::SideBySide => "Side-by-Side",
+                ViewMode::ChangesOnly => "Changes Only",
+            }
+            .into(),
+        );
+
+        div()
+            .w_full()
+            .flex()
+            .flex_row()
+            .flex_shrink_0()
+            .gap(px(16.0))
+            .px(px(12.0))
+            .py(px(2.0))
+            .bg(rgb(0x252526))
+            .border_t_1()
+            .border_color(rgb(0x404040))
+            .text_size(px(11.0))
+            .text_color(rgb(0x808080))
+            .children(items.into_iter().map(|item| div().child(item)))
+    }
+
+    fn panel_sections(&self) -> Vec<(Option<&'static str>, Vec<usize>)> {
+        let visible = |staging: Option<Staging>| -> Vec<usize> {
+            (0..self.diffs.len())
+                .filter(|&i| self.diffs[i].staging == staging)
+                .filter(|&i| self.is_file_visible(&self.diffs[i]))
+                .collect()
+        };
+        if self.diffs.iter().all(|d| d.staging.is_none()) {
+            return vec![(None, visible(None))];
+        }
+        [
+            ("STAGED", Some(Staging::Staged)),
+            ("UNSTAGED", Some(Staging::Unstaged)),
+        ]
+        .into_iter()
+        .map(|(title, staging)| (Some(title), visible(staging)))
+        .filter(|(_, indices)| !indices.is_empty())
+        .collect()
+    }
+
+    fn render_file_panel(&self, window: &Window, cx: &mut Context<Self>) -> impl IntoElement {
         let list_active = self.panel_mode == PanelMode::List;
         let tree_active = self.panel_mode == PanelMode::Tree;
 
         let list_bg = if list_active { rgb(0x007acc) } else { rgb(0x3c3c3c) };
         let tree_bg = if tree_active { rgb(0x007acc) } else { rgb(0x3c3c3c) };
 
+        let generated_count = self.diffs.iter().filter(|d| d.generated).count();
+        let generated_bg = if self.show_generated {
+            rgb(0x007acc)
+        } else {
+            rgb(0x3c3c3c)
+        };
+
+        let header_label = match self.loading {
+            Some((done, total)) => SharedString::from(format!("LOADING {done}/{total}")),
+            None => SharedString::from(format!("FILES ({})", self.diffs.len())),
+        };
+        let reviewed_label = (self.loading.is_none() && !self.diffs.is_empty()).then(|| {
+            let reviewed = self
+                .diffs
+                .iter()
+                .filter(|d| self.reviewed.contains(&d.new_path))
+                .count();
+            SharedString::from(format!("{reviewed}/{} reviewed", self.diffs.len()))
+        });
+        let line_ratio_label = (self.show_line_ratio && self.loading.is_none()).then(|| {
+            let visible = self.diffs.iter().filter(|d| self.is_file_visible(d));
+            self.line_ratio_label(visible)
+        });
+
+        let cursor_row = self
+            .tree_cursor_row()
+            .filter(|_| self.tree_focus_handle.is_focused(window))
+            .map(|(_, row)| row);
+
+        let panel_side = self.panel_side;
         let mut panel = div()
             .flex()
             .flex_col()
@@ -564,8 +4067,20 @@ impl DiffViewer {
             .flex_shrink_0()
             .h_full()
             .bg(rgb(0x252526))
-            .border_l_1()
+            .when(panel_side == PanelSide::Right, |panel| panel.border_l_1())
+            .when(panel_side == PanelSide::Left, |panel| panel.border_r_1())
             .border_color(rgb(0x404040))
+            .when(self.panel_mode == PanelMode::Tree, |panel| {
+                panel
+                    .key_context("FileTree")
+                    .track_focus(&self.tree_focus_handle)
+                    .on_action(cx.listener(Self::select_prev_tree_node))
+                    .on_action(cx.listener(Self::select_next_tree_node))
+                    .on_action(cx.listener(Self::expand_tree_node))
+                    .on_action(cx.listener(Self::collapse_tree_node))
+                    .on_action(cx.listener(Self::open_tree_node))
+                    .on_action(cx.listener(Self::blur_file_tree))
+            })
             .child(
                 div()
                     .w_full()
@@ -580,12 +4095,20 @@ impl DiffViewer {
                     .border_color(rgb(0x404040))
                     .child(
                         div()
+                            .flex()
+                            .flex_row()
+                            .gap(px(6.0))
                             .text_size(px(11.0))
                             .text_color(rgb(0x999999))
-                            .child(SharedString::from(format!(
-                                "FILES ({})",
-                                self.diffs.len()
-                            ))),
+                            .child(header_label)
+                            .children(
+                                reviewed_label
+                                    .map(|label| div().text_color(rgb(0x707070)).child(label)),
+                            )
+                            .children(
+                                line_ratio_label
+                                    .map(|label| div().text_color(rgb(0x707070)).child(label)),
+                            ),
                     )
                     .child(
                         div()
@@ -604,7 +4127,7 @@ impl DiffViewer {
                                     .text_color(rgb(0xffffff))
                                     .child("List")
                                     .on_click(cx.listener(|this, _event, _window, _cx| {
-                                        this.panel_mode = PanelMode::List;
+                                        this.choose_panel_mode(PanelMode::List);
                                     })),
                             )
                             .child(
@@ -619,42 +4142,278 @@ impl DiffViewer {
                                     .text_color(rgb(0xffffff))
                                     .child("Tree")
                                     .on_click(cx.listener(|this, _event, _window, _cx| {
-                                        this.panel_mode = PanelMode::Tree;
+                                        this.choose_panel_mode(PanelMode::Tree);
                                     })),
-                            ),
+                            )
+                            .when(generated_count > 0, |buttons| {
+                                buttons.child(
+                                    div()
+                                        .id("btn-generated")
+                                        .px(px(6.0))
+                                        .py(px(1.0))
+                                        .bg(generated_bg)
+                                        .rounded(px(3.0))
+                                        .cursor_pointer()
+                                        .text_size(px(10.0))
+                                        .text_color(rgb(0xffffff))
+                                        .child("Gen")
+                                        .on_click(cx.listener(|this, _event, _window, cx| {
+                                            this.toggle_generated(cx);
+                                        })),
+                                )
+                            }),
                     ),
             );
 
-        match self.panel_mode {
-            PanelMode::List => {
-                for (i, diff) in self.diffs.iter().enumerate() {
-                    panel = panel.child(self.render_file_item(i, diff, 0.0, cx));
-                }
+        for (title, mut indices) in self.panel_sections() {
+            if let Some(title) = title {
+                panel = panel.child(
+                    div()
+                        .w_full()
+                        .px(px(12.0))
+                        .pt(px(8.0))
+                        .pb(px(2.0))
+                        .text_size(px(10.0))
+                        .text_color(rgb(0x999999))
+                        .child(title),
+                );
             }
-            PanelMode::Tree => {
-                let tree = build_file_tree(&self.diffs);
-                let elements = self.render_tree_nodes(&tree, "", 0, cx);
-                for el in elements {
-                    panel = panel.child(el);
+            match self.panel_mode {
+                PanelMode::List => {
+                    if self.reviewed_last {
+                        indices.sort_by_key(|&i| self.reviewed.contains(&self.diffs[i].new_path));
+                    }
+                    for i in indices {
+                        panel = panel.child(self.render_file_item(
+                            i,
+                            &self.diffs[i],
+                            0.0,
+                            false,
+                            window,
+                            cx,
+                        ));
+                    }
+                }
+                PanelMode::Tree => {
+                    let tree = build_file_tree(&self.diffs, &indices);
+                    let elements =
+                        self.render_tree_nodes(&tree, "", 0, cursor_row.as_ref(), window, cx);
+                    for el in elements {
+                        panel = panel.child(el);
+                    }
                 }
             }
         }
 
+        if generated_count > 0 && !self.show_generated {
+            let label = if generated_count == 1 {
+                "1 generated file hidden".to_string()
+            } else {
+                format!("{generated_count} generated files hidden")
+            };
+            panel = panel.child(
+                div()
+                    .id("generated-hidden")
+                    .w_full()
+                    .px(px(12.0))
+                    .py(px(4.0))
+                    .cursor_pointer()
+                    .text_size(px(11.0))
+                    .text_color(rgb(0x808080))
+                    .hover(|style| style.bg(rgb(0x2a2d2e)))
+                    .child(SharedString::from(label))
+                    .on_click(cx.listener(|this, _event, _window, cx| {
+                        this.toggle_generated(cx);
+                    })),
+            );
+        }
+
         panel
     }
-}
 
-impl Render for DiffViewer {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let diff_content = if let Some(idx) = self.selected_index {
+    fn render_file_body(
+        &self,
+        idx: usize,
+        diff: &FileDiff,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> gpui::AnyElement {
+        let body = match (diff.too_large, self.view_mode) {
+            _ if diff.image => self.render_image_diff(diff, cx).into_any_element(),
+            _ if diff.is_unchanged() => div()
+                .p(px(20.0))
+                .text_color(rgb(0x888888))
+                .child("No changes")
+                .into_any_element(),
+            (Some(size), _) => self.render_too_large(idx, size, cx).into_any_element(),
+            _ if self.shows_deleted_content(diff) => self
+                .render_file_diff(idx, diff, window, cx)
+                .into_any_element(),
+            (None, ViewMode::Unified) => self
+                .render_file_diff(idx, diff, window, cx)
+                .into_any_element(),
+            (None, ViewMode::SideBySide) => self
+                .render_side_by_side_diff(idx, diff, window, cx)
+                .into_any_element(),
+            (None, ViewMode::ChangesOnly) => {
+                self.render_changes_only(idx, diff, cx).into_any_element()
+            }
+        };
+        let type_change = diff.type_change_message().map(|message| {
+            div()
+                .px(px(20.0))
+                .pt(px(12.0))
+                .text_color(rgb(0x888888))
+                .child(SharedString::from(message))
+        });
+        div()
+            .flex()
+            .flex_col()
+            .w_full()
+            .children(type_change)
+            .child(body)
+            .into_any_element()
+    }
+
+    /// File headers get a fixed height so the pinned one can be pushed up by
+    /// the next file's header as it scrolls in.
+    fn file_header_height(&self) -> Pixels {
+        px((self.text_size() * 1.6 + 8.0).round())
+    }
+
+    /// Offset of the pinned header for the block at `position`: 0 at the top
+    /// of the scroll area, negative while the next block's header pushes it
+    /// out.
+    fn sticky_header_top(&self, position: usize) -> Pixels {
+        let Some(next) = self.diff_scroll.bounds_for_item(position + 1) else {
+            return px(0.0);
+        };
+        let next_top = next.top() - self.diff_scroll.bounds().top() + self.diff_scroll.offset().y;
+        (next_top - self.file_header_height()).min(px(0.0))
+    }
+
+    fn render_file_header(&self, index: usize, diff: &FileDiff) -> impl IntoElement {
+        let (additions, deletions) = diff.change_counts();
+        div()
+            .w_full()
+            .h(self.file_header_height())
+            .flex_shrink_0()
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap(px(8.0))
+            .px(px(12.0))
+            .bg(if self.selected_index == Some(index) {
+                rgb(0x2d2d30)
+            } else {
+                rgb(0x252526)
+            })
+            .border_t_1()
+            .border_b_1()
+            .border_color(rgb(0x404040))
+            .child(
+                div()
+                    .flex_grow()
+                    .min_w(px(0.0))
+                    .overflow_x_hidden()
+                    .whitespace_nowrap()
+                    .text_color(rgb(0xcccccc))
+                    .child(self.file_display_name(diff)),
+            )
+            .child(
+                div()
+                    .flex_shrink_0()
+                    .text_size(px(11.0))
+                    .text_color(self.theme.insert.text)
+                    .child(SharedString::from(format!("+{additions}"))),
+            )
+            .child(
+                div()
+                    .flex_shrink_0()
+                    .text_size(px(11.0))
+                    .text_color(self.theme.delete.text)
+                    .child(SharedString::from(format!("−{deletions}"))),
+            )
+    }
+
+    /// Stacks every visible file's diff in one scroll, each under a header
+    /// with its path and stats. The header of the file at the top stays
+    /// pinned while its block scrolls past.
+    fn render_all_files(&self, window: &mut Window, cx: &mut Context<Self>) -> gpui::AnyElement {
+        let order = self.all_files_order();
+        let blocks = order.iter().map(|&index| {
+            let diff = &self.diffs[index];
+            div()
+                .id(ElementId::NamedInteger("file-block".into(), index as u64))
+                .w_full()
+                .flex()
+                .flex_col()
+                .on_mouse_down(
+                    MouseButton::Left,
+                    cx.listener(move |this, _event, _window, cx| {
+                        this.select_file_block(index, cx);
+                    }),
+                )
+                .child(self.render_file_header(index, diff))
+                .child(self.render_file_body(index, diff, window, cx))
+        });
+        let blocks: Vec<_> = blocks.collect();
+        let top_item = self.diff_scroll.top_item();
+        let sticky = order
+            .get(top_item)
+            .filter(|_| self.diff_scroll.offset().y < px(0.0))
+            .map(|&index| {
+                div()
+                    .id("sticky-file-header")
+                    .absolute()
+                    .top(self.sticky_header_top(top_item))
+                    .left_0()
+                    .right_0()
+                    .occlude()
+                    .on_mouse_down(
+                        MouseButton::Left,
+                        cx.listener(move |this, _event, _window, cx| {
+                            this.select_file_block(index, cx);
+                        }),
+                    )
+                    .child(self.render_file_header(index, &self.diffs[index]))
+            });
+        let toolbar = self
+            .selected_diff()
+            .map(|diff| self.render_toolbar(diff, cx));
+
+        div()
+            .flex()
+            .flex_col()
+            .flex_grow()
+            .min_w(px(0.0))
+            .children(toolbar)
+            .child(
+                div()
+                    .relative()
+                    .flex_1()
+                    .min_h(px(0.0))
+                    .overflow_hidden()
+                    .child(
+                        div()
+                            .id("diff-content")
+                            .size_full()
+                            .overflow_y_scroll()
+                            .overflow_x_hidden()
+                            .track_scroll(&self.diff_scroll)
+                            .children(blocks),
+                    )
+                    .children(sticky),
+            )
+            .into_any_element()
+    }
+
+    fn render_diff_content(&self, window: &mut Window, cx: &mut Context<Self>) -> gpui::AnyElement {
+        let empty_message = self.empty_message.clone().filter(|_| self.diffs.is_empty());
+        if let Some(idx) = self.selected_index {
             if let Some(diff) = self.diffs.get(idx) {
                 let toolbar = self.render_toolbar(diff, cx);
-                let body = match self.view_mode {
-                    ViewMode::Unified => self.render_file_diff(diff).into_any_element(),
-                    ViewMode::SideBySide => {
-                        self.render_side_by_side_diff(diff).into_any_element()
-                    }
-                };
+                let body = self.render_file_body(idx, diff, window, cx);
                 div()
                     .flex()
                     .flex_col()
@@ -665,24 +4424,71 @@ impl Render for DiffViewer {
             } else {
                 div().into_any_element()
             }
+        } else if let Some((done, total)) = self.loading {
+            div()
+                .p(px(20.0))
+                .text_color(rgb(0x888888))
+                .child(SharedString::from(format!(
+                    "Loading {done} of {total} files…"
+                )))
+                .into_any_element()
+        } else if let Some(message) = empty_message {
+            div()
+                .size_full()
+                .flex()
+                .flex_col()
+                .items_center()
+                .justify_center()
+                .gap(px(6.0))
+                .child(
+                    div()
+                        .text_size(px(16.0))
+                        .text_color(rgb(0xd4d4d4))
+                        .child("Nothing to review"),
+                )
+                .child(div().text_color(rgb(0x888888)).child(message))
+                .into_any_element()
         } else {
             div()
                 .p(px(20.0))
                 .text_color(rgb(0x888888))
                 .child("No file selected")
                 .into_any_element()
-        };
+        }
+    }
+}
+
+impl Render for DiffViewer {
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let title = window_title(&self.diffs, self.repo_root.is_none());
+        if title != self.window_title {
+            window.set_window_title(&title);
+            self.window_title = title;
+        }
+        if self.show_age_heatmap {
+            self.request_line_ages(cx);
+        }
 
         let initial_width = self.panel_width;
 
+        // The hit zone takes its own space in the row rather than overlapping
+        // the panel and content, so it never swallows clicks meant for them.
         let drag_handle = div()
             .id("panel-resize-handle")
+            .group("panel-resize-handle")
             .w(px(DRAG_HANDLE_WIDTH))
             .h_full()
             .flex_shrink_0()
+            .flex()
+            .justify_center()
             .cursor(CursorStyle::ResizeLeftRight)
-            .bg(rgb(0x404040))
-            .hover(|style| style.bg(rgb(0x007acc)))
+            .child(
+                div()
+                    .w(px(DRAG_HANDLE_LINE_WIDTH))
+                    .h_full()
+                    .bg(rgb(0x404040))
+                    .group_hover("panel-resize-handle", |style| style.bg(rgb(0x007acc))),
+            )
             .on_drag(
                 PanelResizeDrag { initial_width },
                 |drag, _offset, _window, cx| {
@@ -695,32 +4501,129 @@ impl Render for DiffViewer {
                 move |this, event: &gpui::DragMoveEvent<PanelResizeDrag>, window, _cx| {
                     let window_width = window.bounds().size.width;
                     let mouse_x = event.event.position.x;
-                    let new_width = window_width - mouse_x - px(DRAG_HANDLE_WIDTH);
-                    let clamped = new_width
-                        .max(px(MIN_PANEL_WIDTH))
-                        .min(px(MAX_PANEL_WIDTH));
-                    this.panel_width = clamped;
+                    // Keep the middle of the handle under the pointer.
+                    let half_handle = px(DRAG_HANDLE_WIDTH / 2.0);
+                    let new_width = match this.panel_side {
+                        PanelSide::Left => mouse_x - half_handle,
+                        PanelSide::Right => window_width - mouse_x - half_handle,
+                    };
+                    this.panel_width = clamp_panel_width(new_width, window_width);
                 },
             ));
 
-        div()
+        let toast = self.toast.clone().map(|message| {
+            div()
+                .absolute()
+                .bottom(px(16.0))
+                .left(px(16.0))
+                .px(px(10.0))
+                .py(px(6.0))
+                .rounded(px(4.0))
+                .bg(rgb(0x3c3c3c))
+                .border_1()
+                .border_color(rgb(0x007acc))
+                .text_size(px(12.0))
+                .text_color(rgb(0xffffff))
+                .child(message)
+        });
+
+        if let Some(index) = self.pending_file_scroll.take() {
+            cx.on_next_frame(window, move |this, _window, cx| {
+                this.scroll_to_file_block(index);
+                cx.notify();
+            });
+        }
+        let content = if self.all_files && self.selected_diff().is_some() {
+            self.render_all_files(window, cx)
+        } else {
+            div()
+                .id("diff-content")
+                .flex_grow()
+                .min_w(px(0.0))
+                .overflow_y_scroll()
+                .overflow_x_hidden()
+                .track_scroll(&self.diff_scroll)
+                .child(self.render_diff_content(window, cx))
+                .into_any_element()
+        };
+        let panel = self.render_file_panel(window, cx);
+
+        let root = div()
+            .key_context("DiffViewer")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::select_prev_line))
+            .on_action(cx.listener(Self::select_next_line))
+            .on_action(cx.listener(Self::extend_selection_up))
+            .on_action(cx.listener(Self::extend_selection_down))
+            .on_action(cx.listener(Self::copy_selection_action))
+            .on_action(cx.listener(Self::toggle_line_wrap))
+            .on_action(cx.listener(Self::clear_word_highlight))
+            .on_action(cx.listener(Self::open_command_palette))
+            .on_action(cx.listener(Self::toggle_shortcut_help))
+            .on_action(cx.listener(Self::focus_next))
+            .on_action(cx.listener(Self::focus_previous))
+            .on_action(cx.listener(Self::reverse_diff))
+            .on_action(cx.listener(Self::next_conflict_marker))
+            .on_action(cx.listener(Self::prev_conflict_marker))
+            .on_action(cx.listener(Self::scroll_to_top))
+            .on_action(cx.listener(Self::scroll_to_bottom))
+            .on_action(cx.listener(Self::scroll_page_up))
+            .on_action(cx.listener(Self::scroll_page_down))
+            .on_action(cx.listener(Self::navigate_back))
+            .on_action(cx.listener(Self::navigate_forward))
+            .on_action(cx.listener(Self::zoom_in))
+            .on_action(cx.listener(Self::zoom_out))
+            .on_action(cx.listener(Self::reset_zoom))
+            .on_action(cx.listener(Self::toggle_panel))
+            .on_action(cx.listener(Self::open_search))
+            .on_action(cx.listener(Self::search_next))
+            .on_action(cx.listener(Self::search_prev))
+            .on_mouse_down(
+                MouseButton::Navigate(NavigationDirection::Back),
+                cx.listener(|this, _event, _window, cx| this.navigate_history(true, cx)),
+            )
+            .on_mouse_down(
+                MouseButton::Navigate(NavigationDirection::Forward),
+                cx.listener(|this, _event, _window, cx| this.navigate_history(false, cx)),
+            )
             .flex()
-            .flex_row()
+            .flex_col()
+            .relative()
             .size_full()
             .bg(rgb(0x1e1e1e))
             .text_color(rgb(0xd4d4d4))
-            .font_family("Menlo")
-            .text_size(px(13.0))
-            .child(
-                div()
-                    .id("diff-content")
-                    .flex_grow()
-                    .min_w(px(0.0))
-                    .overflow_y_scroll()
-                    .overflow_x_hidden()
-                    .child(diff_content),
+            .font_family(self.font_family.clone())
+            .text_size(px(self.text_size()));
+
+        let main = div().flex().flex_row().flex_1().min_h(px(0.0)).w_full();
+        let main = match (self.panel_visible, self.panel_side) {
+            (false, _) => main.child(content),
+            (true, PanelSide::Left) => main.child(panel).child(drag_handle).child(content),
+            (true, PanelSide::Right) => main.child(content).child(drag_handle).child(panel),
+        };
+
+        root.child(main)
+            .child(self.render_footer())
+            .children(toast)
+            .children(
+                self.search
+                    .as_ref()
+                    .map(|search| self.render_search_bar(search, cx)),
+            )
+            .children(
+                self.palette
+                    .as_ref()
+                    .map(|palette| self.render_command_palette(palette, cx)),
+            )
+            .children(
+                self.file_menu
+                    .as_ref()
+                    .map(|menu| self.render_file_menu(menu, cx)),
+            )
+            .children(
+                self.shortcut_help
+                    .as_ref()
+                    .map(|focus_handle| self.render_shortcut_help(focus_handle)),
             )
-            .child(drag_handle)
-            .child(self.render_file_panel(cx))
     }
 }
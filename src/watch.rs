@@ -0,0 +1,228 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures::future::{self, Either};
+use futures::StreamExt;
+use notify::event::{MetadataKind, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::git::{git_all_ignored, SystemGit};
+
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// What `--watch` listens to for changes.
+#[derive(Clone)]
+pub enum WatchTarget {
+    /// Compared files. Their parent directories are watched so that editors
+    /// which save by renaming over the file are seen.
+    Files(Vec<PathBuf>),
+    /// A git work tree and everything in it that git doesn't ignore; inside
+    /// `.git` only the index counts.
+    WorkTree(PathBuf),
+}
+
+impl WatchTarget {
+    /// Resolves symlinks in the watched directories, as some backends report
+    /// events under the resolved path (FSEvents gives `/private/tmp/a.txt`
+    /// for `/tmp/a.txt`). A compared file keeps its own name, so it is
+    /// matched as its canonical parent plus that name.
+    pub fn canonicalize(self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Files(files) => Self::Files(
+                files
+                    .iter()
+                    .filter_map(|file| Some((file.parent()?, file.file_name()?)))
+                    .map(|(dir, name)| {
+                        let dir = if dir.as_os_str().is_empty() {
+                            Path::new(".")
+                        } else {
+                            dir
+                        };
+                        Ok(dir.canonicalize()?.join(name))
+                    })
+                    .collect::<io::Result<_>>()?,
+            ),
+            Self::WorkTree(root) => Self::WorkTree(root.canonicalize()?),
+        })
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        match self {
+            Self::Files(files) => files.iter().any(|file| file == path),
+            Self::WorkTree(root) => path.strip_prefix(root).is_ok_and(|relative| {
+                relative == Path::new(".git/index") || !relative.starts_with(".git")
+            }),
+        }
+    }
+
+    /// Whether a burst of changes to `paths` can change the diffs. In a work
+    /// tree, changes only to files git ignores (build output, `node_modules`)
+    /// can't; if git can't tell, the diffs are reloaded anyway.
+    pub fn needs_reload(&self, paths: &BTreeSet<PathBuf>) -> bool {
+        let Self::WorkTree(root) = self else {
+            return !paths.is_empty();
+        };
+        let relative: Option<Vec<&str>> = paths
+            .iter()
+            .map(|path| path.strip_prefix(root).ok()?.to_str())
+            .collect();
+        match relative {
+            Some(relative) if !relative.contains(&".git/index") => {
+                !git_all_ignored(&SystemGit, &root.to_string_lossy(), &relative).unwrap_or(false)
+            }
+            _ => true,
+        }
+    }
+}
+
+fn changes_files(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        _ => false,
+    }
+}
+
+/// Starts watching `target`, which should be canonicalized, sending on
+/// `changes` every changed path that may change the diffs. Watching stops
+/// when the returned watcher is dropped.
+pub fn start_watcher(
+    target: &WatchTarget,
+    changes: UnboundedSender<PathBuf>,
+) -> notify::Result<RecommendedWatcher> {
+    let (dirs, mode): (BTreeSet<PathBuf>, _) = match target {
+        WatchTarget::Files(files) => (
+            files
+                .iter()
+                .filter_map(|file| file.parent())
+                .map(Path::to_path_buf)
+                .collect(),
+            RecursiveMode::NonRecursive,
+        ),
+        WatchTarget::WorkTree(root) => (BTreeSet::from([root.clone()]), RecursiveMode::Recursive),
+    };
+    let target = target.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if changes_files(&event.kind) {
+            for path in event.paths {
+                if target.is_relevant(&path) {
+                    changes.unbounded_send(path).ok();
+                }
+            }
+        }
+    })?;
+    for dir in dirs {
+        watcher.watch(&dir, mode)?;
+    }
+    Ok(watcher)
+}
+
+/// Waits for a change, then until no further change arrives within one
+/// `debounce()` period, so that a burst of saves causes a single reload.
+/// Returns the paths changed in the burst, or `None` once the watcher has
+/// gone away.
+pub async fn settled_change<T>(
+    changes: &mut UnboundedReceiver<PathBuf>,
+    mut debounce: impl FnMut() -> T,
+) -> Option<BTreeSet<PathBuf>>
+where
+    T: Future<Output = ()> + Unpin,
+{
+    let mut paths = BTreeSet::from([changes.next().await?]);
+    loop {
+        match future::select(changes.next(), debounce()).await {
+            Either::Left((Some(path), _)) => {
+                paths.insert(path);
+            }
+            Either::Left((None, _)) => return None,
+            Either::Right(_) => return Some(paths),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use notify::event::{CreateKind, DataChange};
+    use std::fs;
+
+    #[test]
+    fn test_relevant_changes() {
+        let write = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+        assert!(changes_files(&write));
+        assert!(changes_files(&EventKind::Create(CreateKind::File)));
+        let read = EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime));
+        assert!(!changes_files(&read));
+
+        let files = WatchTarget::Files(vec![PathBuf::from("/src/a.txt")]);
+        assert!(files.is_relevant(Path::new("/src/a.txt")));
+        assert!(!files.is_relevant(Path::new("/src/b.txt")));
+
+        let tree = WatchTarget::WorkTree(PathBuf::from("/repo"));
+        assert!(tree.is_relevant(Path::new("/repo/src/main.rs")));
+        assert!(tree.is_relevant(Path::new("/repo/.gitignore")));
+        assert!(tree.is_relevant(Path::new("/repo/.git/index")));
+        assert!(!tree.is_relevant(Path::new("/repo/.git/index.lock")));
+        assert!(!tree.is_relevant(Path::new("/repo/.git/objects/ab/cdef")));
+        assert!(!tree.is_relevant(Path::new("/elsewhere/a.txt")));
+    }
+
+    #[test]
+    fn test_canonicalize_resolves_parent_dirs() {
+        let dir = std::env::temp_dir().join(format!("gpui-diff-tool-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let canonical_dir = fs::canonicalize(&dir).unwrap();
+        let file = dir.join("./a.txt");
+        let Ok(WatchTarget::Files(files)) = WatchTarget::Files(vec![file]).canonicalize() else {
+            panic!("expected the files to canonicalize");
+        };
+        assert_eq!(files, vec![canonical_dir.join("a.txt")]);
+        #[cfg(unix)]
+        {
+            // A file reached through a symlinked directory matches the
+            // events reported under the directory it points to.
+            let link_dir = dir.join("link");
+            std::os::unix::fs::symlink(&dir, &link_dir).unwrap();
+            let link = link_dir.join("b.txt");
+            let Ok(WatchTarget::Files(files)) = WatchTarget::Files(vec![link]).canonicalize()
+            else {
+                panic!("expected the files to canonicalize");
+            };
+            assert_eq!(files, vec![canonical_dir.join("b.txt")]);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_settled_change_coalesces_bursts() {
+        let (tx, mut rx) = mpsc::unbounded();
+        for path in ["/a", "/b", "/a"] {
+            tx.unbounded_send(PathBuf::from(path)).unwrap();
+        }
+        let mut periods = 0;
+        let debounce = || {
+            periods += 1;
+            future::ready(())
+        };
+        assert_eq!(
+            block_on(settled_change(&mut rx, debounce)),
+            Some(BTreeSet::from([PathBuf::from("/a"), PathBuf::from("/b")]))
+        );
+        assert_eq!(periods, 3);
+
+        drop(tx);
+        assert_eq!(
+            block_on(settled_change(&mut rx, || future::ready(()))),
+            None
+        );
+    }
+}